@@ -0,0 +1,2 @@
+-- synthetic ids (--id-from hash) span the 64-bit range
+ALTER TABLE transactions ALTER COLUMN tx_id TYPE BIGINT;
@@ -0,0 +1,154 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::events::{ImportEvent, ImportObserver};
+
+/// The last row of a file committed to the table, so that an interrupted import of the file can
+/// resume after it rather than from the start
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// the file name of the source
+    pub source: String,
+    pub account: String,
+    pub tx_id: u64,
+}
+
+impl Checkpoint {
+    /// reads the checkpoint file; a missing checkpoint file has no checkpoint
+    pub fn load(path: &Path) -> io::Result<Option<Self>> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// removes the checkpoint file, once its file is completely imported
+    pub fn remove(path: &Path) -> io::Result<()> {
+        match std::fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// whether the checkpoint was recorded for the source; sources are identified by their file
+    /// name, like in the state file
+    pub fn is_of(&self, source: &Path) -> bool {
+        self.source == file_name(source)
+    }
+}
+
+/// Records the last committed row of the table after every commit, replacing the checkpoint
+/// file
+pub struct CheckpointWriter {
+    path: PathBuf,
+    table_name: String,
+    /// the file name of the source being loaded
+    source: Mutex<String>,
+}
+
+impl CheckpointWriter {
+    pub fn new(path: &Path, table_name: &str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            table_name: table_name.to_string(),
+            source: Mutex::new(String::new()),
+        }
+    }
+
+    /// writes the checkpoint to a temporary file first, and renames it over the checkpoint file,
+    /// so that an interruption never leaves a partial checkpoint
+    fn write(&self, checkpoint: &Checkpoint) -> io::Result<()> {
+        let temporary = self.path.with_extension("tmp");
+        let mut f = std::fs::File::create(&temporary)?;
+        f.write_all(serde_json::to_string(checkpoint)?.as_bytes())?;
+        f.sync_all()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+}
+
+impl ImportObserver for CheckpointWriter {
+    fn on_event(&self, event: &ImportEvent) {
+        match event {
+            ImportEvent::FileStarted { source } => {
+                *self.source.lock().unwrap() = file_name(source);
+            }
+            ImportEvent::ChunkCommitted {
+                table_name,
+                last: Some(last),
+                ..
+            } if *table_name == self.table_name => {
+                let checkpoint = Checkpoint {
+                    source: self.source.lock().unwrap().clone(),
+                    account: last.account.clone(),
+                    tx_id: last.id,
+                };
+                if let Err(e) = self.write(&checkpoint) {
+                    warn!("Could not write the checkpoint {:?}: {}", self.path, e);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn file_name(source: &Path) -> String {
+    source
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    use crate::domain::CsvRecord;
+
+    #[test]
+    fn each_commit_replaces_the_checkpoint() {
+        let dir =
+            std::env::temp_dir().join(format!("csv-importer-checkpoint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("checkpoint.json");
+        Checkpoint::remove(&path).unwrap();
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+
+        let writer = CheckpointWriter::new(&path, "transactions");
+        let date = FixedOffset::east(0).ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let rows = (1..=3)
+            .map(|id| CsvRecord::builder("BOFA", id, date).build())
+            .collect::<Vec<_>>();
+        writer.on_event(&ImportEvent::FileStarted {
+            source: Path::new("in/huge.csv"),
+        });
+        for (table_name, last) in [("transactions", &rows[1]), ("other", &rows[2])] {
+            writer.on_event(&ImportEvent::ChunkCommitted {
+                table_name,
+                rows: 1,
+                last: Some(last),
+            });
+        }
+
+        // commits to other tables, such as an output table, don't move the checkpoint
+        let checkpoint = Checkpoint::load(&path).unwrap().unwrap();
+        assert_eq!(
+            checkpoint,
+            Checkpoint {
+                source: "huge.csv".to_string(),
+                account: "BOFA".to_string(),
+                tx_id: 2,
+            }
+        );
+        assert!(checkpoint.is_of(Path::new("elsewhere/huge.csv")));
+        Checkpoint::remove(&path).unwrap();
+    }
+}
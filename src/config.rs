@@ -1,21 +1,204 @@
 use std::error::Error;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use chrono::FixedOffset;
+use chrono_tz::Tz;
 use clap::{App, Arg};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 
+use crate::currency::{
+    self, Currency, CurrencyFormat, CurrencyOptions, NullAmountPolicy, OverflowPolicy, Rounding,
+    UnknownSignPolicy,
+};
+use crate::db::{ConflictPolicy, HookScope, TooLongPolicy, KEY_COLUMNS};
+use crate::dialect::{self, Dialect};
 use crate::domain;
-use crate::domain::LoadOptions;
+use crate::domain::{
+    BalanceAnchor, DateBoundsPolicy, LoadOptions, PrimaryDate, UnmappedColumnsPolicy,
+};
+use crate::exit::ExitCode;
+use crate::export::{BoolVocabulary, ExportFormat, ExportOptions};
+use crate::groups::GroupReportFormat;
+use crate::manifest::{self, ManifestEntry};
+use crate::mask::{MaskedField, Masker};
+use crate::normalize::{TypeMap, TypeSigns, UnicodeForm, VendorCase};
+use crate::partition::TableTemplate;
+use crate::transform::Transform;
 
 pub struct Config {
     pub database: DatabaseConfig,
-    pub source: Source,
+    pub csv: CsvConfig,
+    /// the source to import; only absent in maintenance modes, such as `--replace-account` and
+    /// `--self-test`
+    pub source: Option<Source>,
     pub load_options: domain::LoadOptions,
+    pub sample: Option<usize>,
+    /// writes the parsed records to standard output, instead of importing them
+    pub export: Option<ExportOptions>,
+    /// imports only rows whose vendor contains any of these lower-cased substrings, if any
+    pub vendor_contains: Vec<String>,
+    /// imports only the last rows of each file, in file order
+    pub tail: Option<usize>,
+    /// the number of times to parse the source when benchmarking the parser, rather than importing
+    pub parse_only: Option<usize>,
+    pub explain: bool,
+    /// counts the rows of the source that are new to the table, rather than importing
+    pub preview_new: bool,
+    /// runs the parsers against built-in cases, rather than importing
+    pub self_test: bool,
+    pub sort_rows: bool,
+    /// only imports rows dated after the last import into the table
+    pub since_last_run: bool,
+    /// only imports rows of accounts without any rows in the table
+    pub skip_existing_accounts: bool,
+    /// fails before importing if the table lacks columns to be imported, or has them with
+    /// incompatible types
+    pub validate_schema: bool,
+    /// the date ordering and resuming rows, rather than their ids
+    pub primary_date: Option<PrimaryDate>,
+    pub deduplicate: bool,
+    /// fails a file with more than one row with the same account and id
+    pub require_unique_ids: bool,
+    /// the number of days within which rows with the same account, amount, and vendor are
+    /// near-duplicates
+    pub dedupe_window: Option<u32>,
+    /// where the rows dropped as duplicates are listed, as csv
+    pub dedup_report: Option<PathBuf>,
+    pub mask: Option<Masker>,
+    pub vendor_case: VendorCase,
+    /// the normalization form applied to every text value, if any
+    pub unicode_form: Option<UnicodeForm>,
+    /// maps the raw transaction types of rows to canonical ones
+    pub type_map: Option<TypeMap>,
+    /// signs amounts by the direction of their transaction type, with `--sign-by-type`
+    pub type_signs: Option<TypeSigns>,
+    /// applied to every row, in order, after the other normalization
+    pub transforms: Vec<Transform>,
+    pub backend: Backend,
+    pub webhook_url: Option<String>,
+    pub state_file: Option<PathBuf>,
+    /// where the last committed row of the file is recorded, for resuming an interrupted import
+    pub checkpoint: Option<PathBuf>,
+    pub date_bounds: DateBoundsPolicy,
+    /// recomputes balances from an anchor row of each account, flagging stated balances that
+    /// diverge
+    pub balance_anchor: Option<BalanceAnchor>,
+    /// the number of files parsed in parallel by directory imports
+    pub threads: usize,
+    /// caps the rows parsed but not yet loaded, by waiting to parse files until their rows fit
+    pub max_in_flight_rows: Option<usize>,
+    pub verify_totals: bool,
+    /// the (old, new) names of an account to rename in the table, instead of importing
+    pub replace_account: Option<(String, String)>,
+    /// lists the keys shared by several rows of the table, instead of importing
+    pub check_duplicates: bool,
+    /// where the duplicate keys are written, as csv
+    pub duplicates_file: Option<PathBuf>,
+    /// confirms destructive operations without asking
+    pub assume_yes: bool,
+    pub report_path: Option<PathBuf>,
+    /// prints the rows loaded by category and month, after the import
+    pub group_report: Option<GroupReportFormat>,
+    pub on_too_long: TooLongPolicy,
+    /// the url of a database receiving the same inserts, if any
+    pub mirror_url: Option<String>,
+    /// whether a failure of the mirror fails the import
+    pub mirror_required: bool,
+}
+
+/// The configuration of running without any options: every row is inserted as is. The database
+/// configuration is empty, so this suits library use with an in-memory store, e.g. with
+/// `Importer::import_records`.
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database: DatabaseConfig::default(),
+            csv: CsvConfig::default(),
+            source: None,
+            load_options: LoadOptions::All,
+            sample: None,
+            export: None,
+            vendor_contains: Vec::new(),
+            tail: None,
+            parse_only: None,
+            explain: false,
+            preview_new: false,
+            self_test: false,
+            sort_rows: false,
+            since_last_run: false,
+            skip_existing_accounts: false,
+            validate_schema: false,
+            primary_date: None,
+            deduplicate: false,
+            require_unique_ids: false,
+            dedupe_window: None,
+            dedup_report: None,
+            mask: None,
+            vendor_case: VendorCase::None,
+            unicode_form: None,
+            type_map: None,
+            type_signs: None,
+            transforms: Vec::new(),
+            backend: Backend::Postgres,
+            webhook_url: None,
+            state_file: None,
+            checkpoint: None,
+            date_bounds: DateBoundsPolicy::Off,
+            balance_anchor: None,
+            threads: 1,
+            max_in_flight_rows: None,
+            verify_totals: false,
+            replace_account: None,
+            check_duplicates: false,
+            duplicates_file: None,
+            assume_yes: false,
+            report_path: None,
+            group_report: None,
+            on_too_long: TooLongPolicy::Off,
+            mirror_url: None,
+            mirror_required: false,
+        }
+    }
+}
+
+/// Where imported transactions are stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Postgres,
+    #[cfg(feature = "memory")]
+    Memory,
+}
+
+#[cfg(feature = "memory")]
+const BACKENDS: [&str; 2] = ["postgres", "memory"];
+#[cfg(not(feature = "memory"))]
+const BACKENDS: [&str; 1] = ["postgres"];
+
+/// The environment a table belongs to, on clusters shared between environments; its name
+/// suffixes the table names
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Environment {
+    Dev,
+    Staging,
+    Prod,
+}
+
+impl Environment {
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::Dev => "_dev",
+            Self::Staging => "_staging",
+            Self::Prod => "_prod",
+        }
+    }
 }
 
 pub enum Source {
     File(PathBuf),
     Directory(PathBuf),
+    Archive(PathBuf),
+    Manifest(Vec<ManifestEntry>),
 }
 
 pub struct DatabaseConfig {
@@ -26,7 +209,30 @@ pub struct DatabaseConfig {
     database_name: String,
     tls: bool,
     table_name: String,
+    /// the table name resolved for the date of each row, when rows are routed by date
+    routing: Option<TableTemplate>,
+    /// the table replaced by the loaded staging table, with `--load-then-swap`; the table name is
+    /// then that of the staging table
+    swap_target: Option<String>,
+    output_table: Option<String>,
+    /// the lookup table of categories, with `--normalize-categories`
+    categories_table: Option<String>,
+    environment: Option<Environment>,
+    key_columns: Vec<String>,
+    commit_every: Option<usize>,
+    /// how long files and chunks are started for, with `--max-runtime`
+    max_runtime: Option<Duration>,
+    sort_for_insert: bool,
+    /// the statements of the `--before-sql` and `--after-sql` files, if any
+    before_sql: Option<String>,
+    after_sql: Option<String>,
+    hook_scope: HookScope,
+    jsonb: bool,
+    /// whether the source file and line of each row are inserted
+    store_source_position: bool,
     init: bool,
+    append_only: bool,
+    conflict: ConflictPolicy,
 }
 
 impl DatabaseConfig {
@@ -34,9 +240,96 @@ impl DatabaseConfig {
         self.table_name.clone()
     }
 
+    /// the table the staging table replaces once loaded, with `--load-then-swap`
+    pub fn get_swap_target(&self) -> Option<&str> {
+        self.swap_target.as_deref()
+    }
+
+    /// the table name resolved for the date of each row, with `--route-by-date`; other uses of
+    /// the table name resolve it for the time of the run
+    pub fn get_routing(&self) -> Option<&TableTemplate> {
+        self.routing.as_ref()
+    }
+
+    /// the table the cleaned rows are loaded into, leaving the table with the rows as read
+    pub fn get_output_table(&self) -> Option<&str> {
+        self.output_table.as_deref()
+    }
+
+    /// the lookup table the categories of rows are stored in, with a foreign key on each row, if
+    /// categories are normalized
+    pub fn get_categories_table(&self) -> Option<&str> {
+        self.categories_table.as_deref()
+    }
+
+    /// the environment suffixing the table names, if any
+    pub fn get_environment(&self) -> Option<Environment> {
+        self.environment
+    }
+
+    /// the columns identifying a row: the primary key created by `init`, and the conflict
+    /// target of upserts
+    pub fn get_key_columns(&self) -> &[String] {
+        &self.key_columns
+    }
+
+    /// the number of rows inserted before committing, if not every chunk
+    pub fn get_commit_every(&self) -> Option<usize> {
+        self.commit_every
+    }
+
+    /// limits the runtime of the import, counted from its start, like `--max-runtime`
+    pub fn with_max_runtime(mut self, max_runtime: Duration) -> Self {
+        self.max_runtime = Some(max_runtime);
+        self
+    }
+
+    /// how long files and chunks are started for, if limited
+    pub fn get_max_runtime(&self) -> Option<Duration> {
+        self.max_runtime
+    }
+
+    /// the statements run before inserting, per the hook scope
+    pub fn get_before_sql(&self) -> Option<&str> {
+        self.before_sql.as_deref()
+    }
+
+    /// the statements run after inserting, per the hook scope
+    pub fn get_after_sql(&self) -> Option<&str> {
+        self.after_sql.as_deref()
+    }
+
+    pub fn get_hook_scope(&self) -> HookScope {
+        self.hook_scope
+    }
+
+    /// whether the rows of each chunk are inserted in the order of their key columns
+    pub fn is_sort_for_insert(&self) -> bool {
+        self.sort_for_insert
+    }
+
+    /// whether records are stored as JSONB documents rather than in normalized columns
+    pub fn is_jsonb(&self) -> bool {
+        self.jsonb
+    }
+
+    /// whether the `source_file` and `source_line` columns are inserted
+    pub fn is_store_source_position(&self) -> bool {
+        self.store_source_position
+    }
+
     pub fn is_init(&self) -> bool {
         self.init
     }
+
+    /// append-only mode guarantees existing rows are never modified or deleted
+    pub fn is_append_only(&self) -> bool {
+        self.append_only
+    }
+
+    pub fn get_conflict_policy(&self) -> &ConflictPolicy {
+        &self.conflict
+    }
 }
 
 impl Default for DatabaseConfig {
@@ -49,7 +342,23 @@ impl Default for DatabaseConfig {
             database_name: "".to_string(),
             tls: false,
             table_name: "".to_string(),
+            routing: None,
+            swap_target: None,
+            output_table: None,
+            categories_table: None,
+            environment: None,
+            key_columns: KEY_COLUMNS.iter().map(|c| c.to_string()).collect(),
+            commit_every: None,
+            max_runtime: None,
+            sort_for_insert: false,
+            before_sql: None,
+            after_sql: None,
+            hook_scope: HookScope::Transaction,
+            jsonb: false,
+            store_source_position: false,
             init: false,
+            append_only: false,
+            conflict: ConflictPolicy::Nothing,
         }
     }
 }
@@ -100,11 +409,73 @@ impl From<clap::ArgMatches<'_>> for DatabaseConfig {
             .unwrap_or("false")
             .parse::<bool>()
             .unwrap_or(false);
-        let table_name = matches
-            .value_of("table_name")
-            .unwrap_or("transactions")
-            .to_string();
+        let environment = match matches.value_of("env") {
+            Some("dev") => Some(Environment::Dev),
+            Some("staging") => Some(Environment::Staging),
+            Some("prod") => Some(Environment::Prod),
+            _ => None,
+        };
+        let suffix = environment.map_or("", |e| e.suffix());
+        // validated in parse_args; macros are resolved for the time of the run
+        let now = chrono::Local::now();
+        let template =
+            |name: &str| TableTemplate::parse(&format!("{}{}", name, suffix)).unwrap_or_default();
+        let table_template = template(matches.value_of("table").unwrap_or("transactions"));
+        let table_name = table_template.resolve(&now);
+        let (table_name, swap_target) = if matches.is_present("load_then_swap") {
+            (format!("{}_staging", table_name), Some(table_name))
+        } else {
+            (table_name, None)
+        };
+        let routing = if matches.is_present("route_by_date") {
+            Some(table_template)
+        } else {
+            None
+        };
+        let output_table = matches
+            .value_of("output_table")
+            .map(|s| template(s).resolve(&now));
+        let categories_table = if matches.is_present("normalize_categories") {
+            Some(format!("categories{}", suffix))
+        } else {
+            None
+        };
+        let key_columns = match matches.values_of("key_columns") {
+            Some(columns) => columns.map(|c| c.to_string()).collect(),
+            None => KEY_COLUMNS.iter().map(|c| c.to_string()).collect(),
+        };
+        // validated in parse_args
+        let commit_every = matches
+            .value_of("commit_every")
+            .and_then(|n| n.parse::<usize>().ok());
+        // validated in parse_args; the runtime counts from the start of the import
+        let max_runtime = matches.value_of("max_runtime").and_then(parse_duration);
+        let sort_for_insert = matches.is_present("sort_for_insert");
+        // validated in parse_args
+        let before_sql = matches
+            .value_of("before_sql")
+            .map(|path| std::fs::read_to_string(path).unwrap_or_default());
+        let after_sql = matches
+            .value_of("after_sql")
+            .map(|path| std::fs::read_to_string(path).unwrap_or_default());
+        let hook_scope = match matches.value_of("hook_scope") {
+            Some("run") => HookScope::Run,
+            _ => HookScope::Transaction,
+        };
+        let jsonb = matches.is_present("jsonb");
+        let store_source_position = matches.is_present("store_source_position");
         let init = matches.is_present("init_db");
+        let append_only = matches.is_present("append_only");
+        let conflict = if matches.is_present("upsert") && !append_only {
+            ConflictPolicy::Update {
+                key: key_columns.clone(),
+                // validated in parse_args
+                compare: matches.value_of("upsert_compare").map(|s| s.to_string()),
+                merge_notes: matches.is_present("merge_notes"),
+            }
+        } else {
+            ConflictPolicy::Nothing
+        };
 
         Self {
             port,
@@ -114,7 +485,390 @@ impl From<clap::ArgMatches<'_>> for DatabaseConfig {
             database_name,
             tls,
             table_name,
+            routing,
+            swap_target,
+            output_table,
+            categories_table,
+            environment,
+            key_columns,
+            commit_every,
+            max_runtime,
+            sort_for_insert,
+            before_sql,
+            after_sql,
+            hook_scope,
+            jsonb,
+            store_source_position,
             init,
+            append_only,
+            conflict,
+        }
+    }
+}
+
+/// Options controlling how csv files are read
+#[derive(Clone, Default)]
+pub struct CsvConfig {
+    percent_column: Option<String>,
+    currency_column: Option<String>,
+    currency_code: Option<String>,
+    trim_headers: bool,
+    require_utf8: bool,
+    flexible: bool,
+    read_buffer_bytes: Option<usize>,
+    /// the number of lines the header row is searched for in, with `--header-detect`
+    header_detect_lines: Option<usize>,
+    /// whether each record keeps the file and line it was read from
+    store_source_position: bool,
+    column_map: Vec<(String, String)>,
+    account: Option<String>,
+    account_from_filename: Option<String>,
+    id_from_hash: bool,
+    default_account: Option<String>,
+    sign_column: Option<String>,
+    on_unknown_sign: UnknownSignPolicy,
+    unmapped_columns: UnmappedColumnsPolicy,
+    date_from_filename: Option<String>,
+    timezone: Option<FixedOffset>,
+    dialects: Vec<Dialect>,
+    opening_balances: Vec<(String, Currency)>,
+    /// the time zone of the dates of each account, as read from the file
+    account_zones: Vec<(String, Tz)>,
+    currency: CurrencyOptions,
+}
+
+impl CsvConfig {
+    /// the source header mapped to the percentage column, if any
+    pub fn get_percent_column(&self) -> Option<&str> {
+        self.percent_column.as_deref()
+    }
+
+    /// the source header mapped to the currency code column, if any
+    pub fn get_currency_column(&self) -> Option<&str> {
+        self.currency_column.as_deref()
+    }
+
+    /// the currency code of rows without one
+    pub fn get_currency_code(&self) -> Option<&str> {
+        self.currency_code.as_deref()
+    }
+
+    /// whether whitespace around headers is trimmed; without trimming, headers must match exactly
+    pub fn is_trim_headers(&self) -> bool {
+        self.trim_headers
+    }
+
+    /// whether files read as utf-8 must be valid utf-8 as a whole
+    pub fn is_require_utf8(&self) -> bool {
+        self.require_utf8
+    }
+
+    /// whether rows may have fewer or more fields than the header row; missing trailing fields
+    /// are blank, and extra ones are ignored
+    pub fn is_flexible(&self) -> bool {
+        self.flexible
+    }
+
+    /// the capacity of the buffer files are read through, if not the csv reader's default
+    pub fn get_read_buffer_bytes(&self) -> Option<usize> {
+        self.read_buffer_bytes
+    }
+
+    /// the number of leading lines searched for the header row, if the header row is detected
+    /// rather than the first line
+    pub fn get_header_detect_lines(&self) -> Option<usize> {
+        self.header_detect_lines
+    }
+
+    /// whether each record keeps the file and line it was read from, with
+    /// `--store-source-position`
+    pub fn is_store_source_position(&self) -> bool {
+        self.store_source_position
+    }
+
+    /// the source headers read by the configuration: the fields of a record, and the sources of
+    /// the column map and of the percent, currency, and sign columns
+    pub fn expected_headers(&self) -> Vec<&str> {
+        domain::HEADERS
+            .iter()
+            .copied()
+            .chain(self.column_map.iter().map(|(source, _)| source.as_str()))
+            .chain(self.get_percent_column())
+            .chain(self.get_currency_column())
+            .chain(self.get_sign_column())
+            .map(|header| header.trim())
+            .collect()
+    }
+
+    /// pairs of (source header, record header) renaming source columns to the fields of a record
+    pub fn get_column_map(&self) -> &[(String, String)] {
+        &self.column_map
+    }
+
+    /// the account of every row, when the file has no account column
+    pub fn get_account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+
+    /// the pattern used to derive the account from file names, if any
+    pub fn get_account_from_filename(&self) -> Option<&str> {
+        self.account_from_filename.as_deref()
+    }
+
+    /// whether ids are synthesized from the business fields of rows, rather than read
+    pub fn is_id_from_hash(&self) -> bool {
+        self.id_from_hash
+    }
+
+    /// the account of rows without one from the file, the command line, or the file name; only
+    /// when accounts don't identify rows, i.e. a single ledger keyed by tx_id alone
+    pub fn get_default_account(&self) -> Option<&str> {
+        self.default_account.as_deref()
+    }
+
+    /// the source header of a debit/credit indicator column, applied to the sign of the amount
+    pub fn get_sign_column(&self) -> Option<&str> {
+        self.sign_column.as_deref()
+    }
+
+    pub fn get_unknown_sign_policy(&self) -> UnknownSignPolicy {
+        self.on_unknown_sign
+    }
+
+    /// what to do with source columns that no field of a record is read from
+    pub fn get_unmapped_columns_policy(&self) -> UnmappedColumnsPolicy {
+        self.unmapped_columns
+    }
+
+    /// the chrono pattern used to derive a date from file names, if any
+    pub fn get_date_from_filename(&self) -> Option<&str> {
+        self.date_from_filename.as_deref()
+    }
+
+    /// the offset of dates without one of their own, if not UTC
+    pub fn get_timezone(&self) -> Option<FixedOffset> {
+        self.timezone
+    }
+
+    /// the first dialect matching the file name of the source, if any
+    pub fn get_dialect(&self, source: &Path) -> Option<&Dialect> {
+        self.dialects.iter().find(|d| d.matches(source))
+    }
+
+    /// the options of the file of a manifest entry, with the entry's overrides applied
+    pub fn for_manifest_entry(&self, entry: &ManifestEntry) -> CsvConfig {
+        let mut config = self.clone();
+        if let Some(account) = entry.get_account() {
+            config.account = Some(account.to_string());
+        }
+        if entry.get_delimiter().is_some() || entry.get_date_format().is_some() {
+            let base = self.get_dialect(entry.get_path());
+            config.dialects = vec![Dialect::overridden(
+                base,
+                entry.get_delimiter(),
+                entry.get_date_format(),
+            )];
+        }
+        config
+    }
+
+    /// pairs of (account, balance) from which the balances of files without a balance column
+    /// are derived
+    pub fn get_opening_balances(&self) -> &[(String, Currency)] {
+        &self.opening_balances
+    }
+
+    /// whether any account has a time zone of its own
+    pub fn has_account_zones(&self) -> bool {
+        !self.account_zones.is_empty()
+    }
+
+    /// the time zone of the dates of the account, if not the `--timezone` offset
+    pub fn get_account_zone(&self, account: &str) -> Option<Tz> {
+        self.account_zones
+            .iter()
+            .find(|(a, _)| a == account)
+            .map(|(_, zone)| *zone)
+    }
+
+    pub fn get_currency_options(&self) -> CurrencyOptions {
+        self.currency
+    }
+}
+
+impl CsvConfig {
+    /// parses `SOURCE=FIELD` column map entries; fields are the header names of a `CsvRecord`.
+    /// Sources are trimmed like the headers they match.
+    fn parse_column_map<'a>(
+        entries: impl Iterator<Item = &'a str>,
+        trim_headers: bool,
+    ) -> Result<Vec<(String, String)>, ConfigError> {
+        entries
+            .map(|entry| match entry.split_once('=') {
+                Some((source, field)) if domain::HEADERS.contains(&field.trim()) => {
+                    let source = if trim_headers { source.trim() } else { source };
+                    Ok((source.to_string(), field.trim().to_string()))
+                }
+                _ => Err(ConfigError::invalid_value("column-map", entry)),
+            })
+            .collect()
+    }
+
+    /// parses `ACCOUNT=AMOUNT` opening balances
+    fn parse_opening_balances<'a>(
+        entries: impl Iterator<Item = &'a str>,
+    ) -> Result<Vec<(String, Currency)>, ConfigError> {
+        entries
+            .map(|entry| {
+                entry
+                    .rsplit_once('=')
+                    .and_then(|(account, amount)| {
+                        Some((account.trim().to_string(), amount.trim().parse().ok()?))
+                    })
+                    .ok_or_else(|| ConfigError::invalid_value("opening-balance", entry))
+            })
+            .collect()
+    }
+
+    fn parse_account_zones<'a>(
+        entries: impl Iterator<Item = &'a str>,
+    ) -> Result<Vec<(String, Tz)>, ConfigError> {
+        entries
+            .map(|entry| {
+                entry
+                    .rsplit_once('=')
+                    .and_then(|(account, zone)| {
+                        Some((account.trim().to_string(), zone.trim().parse().ok()?))
+                    })
+                    .ok_or_else(|| ConfigError::invalid_value("account-timezone", entry))
+            })
+            .collect()
+    }
+}
+
+impl From<&clap::ArgMatches<'_>> for CsvConfig {
+    fn from(matches: &clap::ArgMatches) -> Self {
+        let percent_column = matches.value_of("percent_column").map(|s| s.to_string());
+        let currency_column = matches.value_of("currency_column").map(|s| s.to_string());
+        let currency_code = matches.value_of("currency_code").map(|s| s.to_string());
+        let trim_headers = !matches.is_present("no_trim_headers");
+        let require_utf8 = matches.is_present("require_utf8");
+        let flexible = matches.is_present("flexible");
+        let store_source_position = matches.is_present("store_source_position");
+        // validated in parse_args
+        let read_buffer_bytes = matches
+            .value_of("read_buffer_bytes")
+            .and_then(|n| n.parse::<usize>().ok());
+        // validated in parse_args
+        let header_detect_lines = matches
+            .value_of("header_detect")
+            .and_then(|n| n.parse::<usize>().ok());
+        // validated in parse_args
+        let column_map = matches
+            .values_of("column_map")
+            .map(|entries| Self::parse_column_map(entries, trim_headers).unwrap_or_default())
+            .unwrap_or_default();
+        let account = matches.value_of("account").map(|s| s.to_string());
+        let account_keyed = matches
+            .values_of("key_columns")
+            .map_or(true, |mut columns| columns.any(|c| c == "account"));
+        let default_account = if account_keyed {
+            None
+        } else {
+            Some(domain::LEDGER_ACCOUNT.to_string())
+        };
+        let id_from_hash = matches.value_of("id_from") == Some("hash");
+        let account_from_filename = matches
+            .value_of("account_from_filename")
+            .map(|s| s.to_string());
+        let sign_column = matches.value_of("sign_column").map(|s| s.to_string());
+        let on_unknown_sign = match matches.value_of("unknown_sign") {
+            Some("credit") => UnknownSignPolicy::Credit,
+            Some("debit") => UnknownSignPolicy::Debit,
+            _ => UnknownSignPolicy::Error,
+        };
+        let unmapped_columns = if matches.is_present("strict_columns") {
+            UnmappedColumnsPolicy::Reject
+        } else if matches.is_present("report_unmapped_columns") {
+            UnmappedColumnsPolicy::Report
+        } else {
+            UnmappedColumnsPolicy::Ignore
+        };
+        let date_from_filename = matches
+            .value_of("date_from_filename")
+            .map(|s| s.to_string());
+        // validated in parse_args
+        let timezone = matches.value_of("timezone").and_then(domain::parse_offset);
+        // validated in parse_args
+        let dialects = matches
+            .value_of("dialect_map")
+            .and_then(|path| dialect::load(Path::new(path)).ok())
+            .unwrap_or_default();
+        let opening_balances = matches
+            .values_of("opening_balance")
+            .map(|entries| Self::parse_opening_balances(entries).unwrap_or_default())
+            .unwrap_or_default();
+        // validated in parse_args
+        let account_zones = matches
+            .values_of("account_timezone")
+            .map(|entries| Self::parse_account_zones(entries).unwrap_or_default())
+            .unwrap_or_default();
+        let on_overflow = match matches.value_of("on_overflow") {
+            Some("clamp") => OverflowPolicy::Clamp,
+            Some("skip") => OverflowPolicy::Skip,
+            _ => OverflowPolicy::Error,
+        };
+
+        let on_null_amount = match matches.value_of("null_amount_policy") {
+            Some("zero") => NullAmountPolicy::Zero,
+            Some("error") => NullAmountPolicy::Error,
+            _ => NullAmountPolicy::Skip,
+        };
+        let rounding = match matches.value_of("rounding") {
+            Some("half-up") => Rounding::HalfUp,
+            Some("truncate") => Rounding::Truncate,
+            _ => Rounding::HalfEven,
+        };
+        let warn_precision_loss = matches.value_of("precision_loss") != Some("ignore");
+        let coalesce_whitespace = matches.is_present("coalesce_whitespace_in_amount");
+        let minor_units = matches.is_present("amount_in_minor_units");
+        let format = match matches.value_of("currency_format") {
+            Some("european") => CurrencyFormat::European,
+            _ => CurrencyFormat::Us,
+        };
+
+        Self {
+            percent_column,
+            currency_column,
+            currency_code,
+            trim_headers,
+            require_utf8,
+            flexible,
+            read_buffer_bytes,
+            header_detect_lines,
+            store_source_position,
+            column_map,
+            account,
+            account_from_filename,
+            id_from_hash,
+            default_account,
+            sign_column,
+            on_unknown_sign,
+            unmapped_columns,
+            date_from_filename,
+            timezone,
+            dialects,
+            opening_balances,
+            account_zones,
+            currency: CurrencyOptions {
+                on_overflow,
+                on_null_amount,
+                rounding,
+                warn_precision_loss,
+                coalesce_whitespace,
+                minor_units,
+                format,
+            },
         }
     }
 }
@@ -125,6 +879,8 @@ pub enum ConfigError {
     DirectoryNotFound(String),
     DirectoryEmpty(String),
     RequiredConfigurationMissing(String),
+    InvalidValue(String, String),
+    ProdNotConfirmed(String),
 }
 
 impl ConfigError {
@@ -140,9 +896,17 @@ impl ConfigError {
         ConfigError::DirectoryEmpty(s.to_string())
     }
 
-    fn required_configuration_missing(s: &str) -> Self {
+    pub(crate) fn required_configuration_missing(s: &str) -> Self {
         ConfigError::RequiredConfigurationMissing(s.to_string())
     }
+
+    fn invalid_value(arg: &str, value: &str) -> Self {
+        ConfigError::InvalidValue(arg.to_string(), value.to_string())
+    }
+
+    fn prod_not_confirmed(table_name: &str) -> Self {
+        ConfigError::ProdNotConfirmed(table_name.to_string())
+    }
 }
 
 impl std::fmt::Display for ConfigError {
@@ -150,10 +914,22 @@ impl std::fmt::Display for ConfigError {
         match &self {
             Self::FileNotFound(s) => write!(f, "File not found: {}", s),
             Self::DirectoryNotFound(s) => write!(f, "Directory not found: {}", s),
-            Self::DirectoryEmpty(s) => write!(f, "Directory {} does not contain any CSV files.", s),
+            Self::DirectoryEmpty(s) => write!(
+                f,
+                "Directory {} does not contain any CSV files (files named *.csv or *.csv.gz).",
+                s
+            ),
             Self::RequiredConfigurationMissing(s) => {
                 write!(f, "Required configuration argument missing: {}", s)
             }
+            Self::InvalidValue(arg, value) => {
+                write!(f, "Invalid value for argument {}: {}", arg, value)
+            }
+            Self::ProdNotConfirmed(s) => write!(
+                f,
+                "Refusing to write to production table {}; use --confirm-prod to confirm.",
+                s
+            ),
         }
     }
 }
@@ -164,8 +940,9 @@ impl std::error::Error for ConfigError {
     }
 }
 
-pub fn parse_args() -> Result<Config, Box<dyn Error>> {
-    let matches = App::new("CSV Importer")
+/// the command line interface, without the checks of parse_args
+pub(crate) fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("CSV Importer")
         .version("1.0")
         .author("Trey Hutcheson")
         .about("Imports formatted CSV files into a financial database")
@@ -175,7 +952,13 @@ pub fn parse_args() -> Result<Config, Box<dyn Error>> {
                 .long("file")
                 .value_name("FILE")
                 .takes_value(true)
-                .required_unless("directory"),
+                .required_unless_one(&[
+                    "directory",
+                    "manifest",
+                    "replace_account",
+                    "check_duplicates",
+                    "self_test",
+                ]),
         )
         .arg(
             Arg::with_name("directory")
@@ -184,7 +967,20 @@ pub fn parse_args() -> Result<Config, Box<dyn Error>> {
                 .value_name("DIR")
                 .takes_value(true)
                 .conflicts_with("file")
-                .required_unless("file"),
+                .required_unless_one(&[
+                    "file",
+                    "manifest",
+                    "replace_account",
+                    "check_duplicates",
+                    "self_test",
+                ]),
+        )
+        .arg(
+            Arg::with_name("manifest")
+                .long("manifest")
+                .value_name("PATH")
+                .takes_value(true)
+                .conflicts_with_all(&["file", "directory"]),
         )
         .arg(
             Arg::with_name("db_port")
@@ -246,7 +1042,628 @@ pub fn parse_args() -> Result<Config, Box<dyn Error>> {
                 .takes_value(true)
                 .env("DB_TABLE"),
         )
+        .arg(
+            Arg::with_name("route_by_date")
+                .long("route-by-date")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "output_table",
+                    "since_last_run",
+                    "skip_existing_accounts",
+                    "validate_schema",
+                    "preview_new",
+                ]),
+        )
+        .arg(
+            Arg::with_name("load_then_swap")
+                .long("load-then-swap")
+                .takes_value(false)
+                .conflicts_with_all(&[
+                    "route_by_date",
+                    "init_db",
+                    "since_last_run",
+                    "skip_existing_accounts",
+                    "preview_new",
+                ]),
+        )
         .arg(Arg::with_name("init_db").long("init").takes_value(false))
+        .arg(
+            Arg::with_name("append_only")
+                .long("append-only")
+                .takes_value(false)
+                .conflicts_with_all(&["load_then_swap"]),
+        )
+        .arg(
+            Arg::with_name("upsert")
+                .long("upsert")
+                .takes_value(false)
+                .conflicts_with("append_only"),
+        )
+        .arg(
+            Arg::with_name("merge_notes")
+                .long("merge-notes")
+                .takes_value(false)
+                .requires("upsert"),
+        )
+        .arg(
+            Arg::with_name("upsert_compare")
+                .long("upsert-compare")
+                .value_name("COLUMN")
+                .takes_value(true)
+                .requires("upsert"),
+        )
+        .arg(
+            Arg::with_name("sort_for_insert")
+                .long("sort-for-insert")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("max_runtime")
+                .long("max-runtime")
+                .value_name("DURATION")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("before_sql")
+                .long("before-sql")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("after_sql")
+                .long("after-sql")
+                .value_name("FILE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hook_scope")
+                .long("hook-scope")
+                .value_name("SCOPE")
+                .possible_values(&["transaction", "run"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("commit_every")
+                .long("commit-every")
+                .value_name("ROWS")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("key_columns")
+                .long("key-columns")
+                .value_name("COLUMNS")
+                .possible_values(&KEY_COLUMNS)
+                .use_delimiter(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("env")
+                .long("env")
+                .value_name("ENV")
+                .possible_values(&["dev", "staging", "prod"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("confirm_prod")
+                .long("confirm-prod")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("output_table")
+                .long("output-table")
+                .value_name("TABLE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("percent_column")
+                .long("percent-column")
+                .value_name("HEADER")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("currency_column")
+                .long("currency-column")
+                .value_name("HEADER")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("currency_code")
+                .long("currency-code")
+                .value_name("CODE")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("column_map")
+                .long("column-map")
+                .value_name("SOURCE=FIELD")
+                .takes_value(true)
+                .use_delimiter(true)
+                .multiple(true),
+        )
+        .arg(
+            Arg::with_name("no_trim_headers")
+                .long("no-trim-headers")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("account")
+                .long("account")
+                .value_name("NAME")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("id_from")
+                .long("id-from")
+                .value_name("SOURCE")
+                .possible_values(&["column", "hash"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("account_from_filename")
+                .long("account-from-filename")
+                .value_name("PATTERN")
+                .takes_value(true)
+                .conflicts_with("account"),
+        )
+        .arg(
+            Arg::with_name("sign_column")
+                .long("sign-column")
+                .value_name("HEADER")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("unknown_sign")
+                .long("unknown-sign")
+                .value_name("ACTION")
+                .possible_values(&["error", "credit", "debit"])
+                .default_value("error")
+                .requires("sign_column")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("report_unmapped_columns")
+                .long("report-unmapped-columns")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("strict_columns")
+                .long("strict-columns")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("date_from_filename")
+                .long("date-from-filename")
+                .value_name("PATTERN")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("timezone")
+                .long("timezone")
+                .value_name("OFFSET")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("opening_balance")
+                .long("opening-balance")
+                .value_name("ACCOUNT=AMOUNT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("account_timezone")
+                .long("account-timezone")
+                .value_name("ACCOUNT=ZONE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("read_buffer_bytes")
+                .long("read-buffer-bytes")
+                .value_name("BYTES")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("require_utf8")
+                .long("require-utf8")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("flexible")
+                .long("flexible")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("header_detect")
+                .long("header-detect")
+                .value_name("LINES")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("dialect_map")
+                .long("dialect-map")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("on_overflow")
+                .long("on-overflow")
+                .value_name("POLICY")
+                .possible_values(&["error", "clamp", "skip"])
+                .default_value("error")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("null_amount_policy")
+                .long("null-amount-policy")
+                .value_name("POLICY")
+                .possible_values(&["zero", "skip", "error"])
+                .default_value("skip")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rounding")
+                .long("rounding")
+                .value_name("MODE")
+                .possible_values(&["half-up", "half-even", "truncate"])
+                .default_value("half-even")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("coalesce_whitespace_in_amount")
+                .long("coalesce-whitespace-in-amount")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("currency_format")
+                .long("currency-format")
+                .value_name("FORMAT")
+                .possible_values(&["us", "european"])
+                .default_value("us")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("amount_in_minor_units")
+                .long("amount-in-minor-units")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("precision_loss")
+                .long("precision-loss")
+                .value_name("ACTION")
+                .possible_values(&["warn", "ignore"])
+                .default_value("warn")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("vendor_contains")
+                .long("vendor-contains")
+                .value_name("TEXT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("tail")
+                .long("tail")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sample")
+                .long("sample")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("export")
+                .long("export")
+                .value_name("FORMAT")
+                .possible_values(&["csv", "json"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("export_null")
+                .long("export-null")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .requires("export"),
+        )
+        .arg(
+            Arg::with_name("export_bool")
+                .long("export-bool")
+                .value_name("VOCABULARY")
+                .possible_values(&["truefalse", "yesno", "onezero"])
+                .takes_value(true)
+                .requires("export"),
+        )
+        .arg(Arg::with_name("explain").long("explain").takes_value(false))
+        .arg(
+            Arg::with_name("parse_only")
+                .long("parse-only")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("repeat")
+                .long("repeat")
+                .value_name("N")
+                .takes_value(true)
+                .requires("parse_only"),
+        )
+        .arg(
+            Arg::with_name("preview_new")
+                .long("preview-new")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("self_test")
+                .long("self-test")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("require_unique_ids")
+                .long("require-unique-ids")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("deduplicate")
+                .long("deduplicate-across-files")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("dedupe_window")
+                .long("dedupe-window")
+                .value_name("DAYS")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("since_last_run")
+                .long("since-last-run")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("validate_schema")
+                .long("validate-schema")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("skip_existing_accounts")
+                .long("skip-existing-accounts")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("primary_date")
+                .long("primary-date")
+                .value_name("DATE")
+                .possible_values(&["transaction", "posted"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sort_rows")
+                .long("sort-rows")
+                .value_name("BOOL")
+                .possible_values(&["true", "false"])
+                .default_value("true")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mask")
+                .long("mask")
+                .value_name("FIELDS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .multiple(true)
+                .requires("mask_key"),
+        )
+        .arg(
+            Arg::with_name("mask_key")
+                .long("mask-key")
+                .value_name("KEY")
+                .takes_value(true)
+                .env("MASK_KEY"),
+        )
+        .arg(
+            Arg::with_name("vendor_case")
+                .long("vendor-case")
+                .value_name("CASE")
+                .possible_values(&["upper", "lower", "title", "none"])
+                .default_value("none")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("normalize_unicode")
+                .long("normalize-unicode")
+                .value_name("FORM")
+                .possible_values(&["nfc", "nfkc"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("type_map")
+                .long("type-map")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("unmapped_type")
+                .long("unmapped-type")
+                .value_name("ACTION")
+                .possible_values(&["pass", "warn"])
+                .default_value("pass")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sign_by_type")
+                .long("sign-by-type")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keep_signed_amounts")
+                .long("keep-signed-amounts")
+                .takes_value(false)
+                .requires("sign_by_type"),
+        )
+        .arg(
+            Arg::with_name("transform")
+                .long("transform")
+                .value_name("FIELD=EXPRESSION")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("backend")
+                .long("backend")
+                .value_name("BACKEND")
+                .possible_values(&BACKENDS)
+                .default_value("postgres")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("webhook_url")
+                .long("webhook-url")
+                .value_name("URL")
+                .takes_value(true)
+                .env("WEBHOOK_URL"),
+        )
+        .arg(
+            Arg::with_name("state_file")
+                .long("state-file")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("file")
+                .conflicts_with_all(&[
+                    "load_new",
+                    "route_by_date",
+                    "since_last_run",
+                    "skip_existing_accounts",
+                    "load_then_swap",
+                ]),
+        )
+        .arg(
+            Arg::with_name("balance_anchor")
+                .long("balance-anchor")
+                .value_name("ROW")
+                .possible_values(&["first"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("date_bounds")
+                .long("date-bounds")
+                .value_name("ACTION")
+                .possible_values(&["off", "warn", "reject"])
+                .default_value("off")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .long("threads")
+                .value_name("N")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_in_flight_rows")
+                .long("max-in-flight-rows")
+                .value_name("N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("jsonb")
+                .long("jsonb")
+                .takes_value(false)
+                .conflicts_with_all(&["upsert_compare", "merge_notes", "verify_totals"]),
+        )
+        .arg(
+            Arg::with_name("store_source_position")
+                .long("store-source-position")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("normalize_categories")
+                .long("normalize-categories")
+                .takes_value(false)
+                .conflicts_with("jsonb"),
+        )
+        .arg(
+            Arg::with_name("verify_totals")
+                .long("verify-totals")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("replace_account")
+                .long("replace-account")
+                .value_names(&["OLD", "NEW"])
+                .number_of_values(2)
+                .takes_value(true)
+                .conflicts_with("append_only"),
+        )
+        .arg(
+            Arg::with_name("check_duplicates")
+                .long("check-duplicates")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("duplicates_file")
+                .long("duplicates-file")
+                .value_name("PATH")
+                .takes_value(true)
+                .requires("check_duplicates"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .short("y")
+                .long("yes")
+                .alias("force")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("dedup_report")
+                .long("dedup-report")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("report_path")
+                .long("report-path")
+                .value_name("PATH")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("group_report")
+                .long("group-report")
+                .value_name("FORMAT")
+                .possible_values(&["table", "json"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("on_too_long")
+                .long("on-too-long")
+                .value_name("ACTION")
+                .possible_values(&["off", "truncate", "reject"])
+                .default_value("off")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mirror_url")
+                .long("mirror-url")
+                .value_name("URL")
+                .takes_value(true)
+                .env("MIRROR_URL"),
+        )
+        .arg(
+            Arg::with_name("mirror_required")
+                .long("mirror-required")
+                .takes_value(false)
+                .requires("mirror_url"),
+        )
         .arg(Arg::with_name("load_all").long("all").takes_value(false))
         .arg(
             Arg::with_name("load_new")
@@ -254,12 +1671,33 @@ pub fn parse_args() -> Result<Config, Box<dyn Error>> {
                 .takes_value(false)
                 .conflicts_with("load_all"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("prefer_file_order")
+                .long("prefer-file-order")
+                .takes_value(false)
+                .requires("load_new"),
+        )
+}
+
+pub fn parse_args() -> Result<Config, Box<dyn Error>> {
+    let matches = app().get_matches_safe().unwrap_or_else(|e| {
+        // usage errors exit like the configuration errors of parse_args itself; help and
+        // version output exits successfully
+        if e.use_stderr() {
+            eprintln!("{}", e.message);
+            std::process::exit(ExitCode::Config.code());
+        }
+        e.exit()
+    });
 
     let source = if let Some(f) = matches.value_of("file") {
         let p = Path::new(f);
         if p.exists() {
-            Source::File(p.to_path_buf())
+            if p.extension() == Some(std::ffi::OsStr::new("zip")) {
+                Some(Source::Archive(p.to_path_buf()))
+            } else {
+                Some(Source::File(p.to_path_buf()))
+            }
         } else {
             return Err(Box::new(ConfigError::file_not_found(f)));
         }
@@ -267,52 +1705,549 @@ pub fn parse_args() -> Result<Config, Box<dyn Error>> {
         let p = Path::new(d);
         if p.exists() {
             if directory_contains_csvs(p) {
-                Source::Directory(p.to_path_buf())
+                Some(Source::Directory(p.to_path_buf()))
             } else {
                 return Err(Box::new(ConfigError::directory_empty(d)));
             }
         } else {
             return Err(Box::new(ConfigError::directory_not_found(d)));
         }
+    } else if let Some(m) = matches.value_of("manifest") {
+        let entries = manifest::load(Path::new(m))
+            .map_err(|e| ConfigError::invalid_value("manifest", &format!("{}: {}", m, e)))?;
+        if let Some(missing) = entries.iter().find(|e| !e.get_path().is_file()) {
+            return Err(Box::new(ConfigError::file_not_found(
+                &missing.get_path().to_string_lossy(),
+            )));
+        }
+        Some(Source::Manifest(entries))
+    } else if matches.is_present("replace_account")
+        || matches.is_present("check_duplicates")
+        || matches.is_present("self_test")
+    {
+        None
     } else {
         return Err(Box::new(ConfigError::required_configuration_missing(
             "file or directory",
         )));
     };
 
-    let load_options = if matches.is_present("load_new") {
+    let load_options = if matches.is_present("prefer_file_order") {
+        LoadOptions::NewInFileOrder
+    } else if matches.is_present("load_new") {
         LoadOptions::New
     } else {
         LoadOptions::All
     };
 
+    let sample = match matches.value_of("sample") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => return Err(Box::new(ConfigError::invalid_value("sample", n))),
+        },
+        None => None,
+    };
+    let export = matches.value_of("export").map(|format| ExportOptions {
+        null: matches.value_of("export_null").map(|s| s.to_string()),
+        bools: match matches.value_of("export_bool") {
+            Some("yesno") => BoolVocabulary::YesNo,
+            Some("onezero") => BoolVocabulary::OneZero,
+            _ => BoolVocabulary::TrueFalse,
+        },
+        ..ExportOptions::new(match format {
+            "json" => ExportFormat::Json,
+            _ => ExportFormat::Csv,
+        })
+    });
+
+    let parse_only = if matches.is_present("parse_only") {
+        match matches.value_of("repeat").unwrap_or("1").parse::<usize>() {
+            Ok(n) if n > 0 => Some(n),
+            _ => {
+                return Err(Box::new(ConfigError::invalid_value(
+                    "repeat",
+                    matches.value_of("repeat").unwrap_or_default(),
+                )))
+            }
+        }
+    } else {
+        None
+    };
+
+    let vendor_contains = matches
+        .values_of("vendor_contains")
+        .into_iter()
+        .flatten()
+        .map(|s| s.to_lowercase())
+        .collect();
+    let tail = match matches.value_of("tail") {
+        Some(n) => match n.parse::<usize>() {
+            Ok(n) => Some(n),
+            Err(_) => return Err(Box::new(ConfigError::invalid_value("tail", n))),
+        },
+        None => None,
+    };
+
+    let explain = matches.is_present("explain");
+    let preview_new = matches.is_present("preview_new");
+    let self_test = matches.is_present("self_test");
+    let since_last_run = matches.is_present("since_last_run");
+    let skip_existing_accounts = matches.is_present("skip_existing_accounts");
+    let validate_schema = matches.is_present("validate_schema");
+    let primary_date = match matches.value_of("primary_date") {
+        Some("transaction") => Some(PrimaryDate::Transaction),
+        Some("posted") => Some(PrimaryDate::Posted),
+        _ => None,
+    };
+    // resuming in file order depends on the rows keeping the order of the file
+    let sort_rows =
+        matches.value_of("sort_rows") != Some("false") && !matches.is_present("prefer_file_order");
+    let deduplicate = matches.is_present("deduplicate");
+    let require_unique_ids = matches.is_present("require_unique_ids");
+    let dedupe_window = match matches.value_of("dedupe_window") {
+        Some(days) => match days.parse::<u32>() {
+            Ok(days) => Some(days),
+            Err(_) => return Err(Box::new(ConfigError::invalid_value("dedupe-window", days))),
+        },
+        None => None,
+    };
+
+    let mask = match (matches.values_of("mask"), matches.value_of("mask_key")) {
+        (Some(fields), Some(key)) => {
+            let mut masked = Vec::new();
+            for field in fields {
+                match field.parse::<MaskedField>() {
+                    Ok(f) => masked.push(f),
+                    Err(f) => return Err(Box::new(ConfigError::invalid_value("mask", &f))),
+                }
+            }
+            Some(Masker::new(key, masked))
+        }
+        _ => None,
+    };
+
+    let vendor_case = match matches.value_of("vendor_case") {
+        Some("upper") => VendorCase::Upper,
+        Some("lower") => VendorCase::Lower,
+        Some("title") => VendorCase::Title,
+        _ => VendorCase::None,
+    };
+
+    let unicode_form = match matches.value_of("normalize_unicode") {
+        Some("nfc") => Some(UnicodeForm::Nfc),
+        Some("nfkc") => Some(UnicodeForm::Nfkc),
+        _ => None,
+    };
+
+    let type_map = match matches.value_of("type_map") {
+        Some(path) => {
+            let warn_unmapped = matches.value_of("unmapped_type") == Some("warn");
+            match TypeMap::load(Path::new(path), warn_unmapped) {
+                Ok(type_map) => Some(type_map),
+                Err(e) => {
+                    return Err(Box::new(ConfigError::invalid_value(
+                        "type-map",
+                        &format!("{}: {}", path, e),
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let type_signs = match matches.value_of("sign_by_type") {
+        Some(path) => {
+            let keep_signed = matches.is_present("keep_signed_amounts");
+            match TypeSigns::load(Path::new(path), keep_signed) {
+                Ok(type_signs) => Some(type_signs),
+                Err(e) => {
+                    return Err(Box::new(ConfigError::invalid_value(
+                        "sign-by-type",
+                        &format!("{}: {}", path, e),
+                    )))
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut transforms = Vec::new();
+    for entry in matches.values_of("transform").into_iter().flatten() {
+        match entry.parse::<Transform>() {
+            Ok(t) => transforms.push(t),
+            Err(e) => return Err(Box::new(ConfigError::invalid_value("transform", &e))),
+        }
+    }
+
+    let backend = match matches.value_of("backend") {
+        #[cfg(feature = "memory")]
+        Some("memory") => Backend::Memory,
+        _ => Backend::Postgres,
+    };
+
+    if let Some(entries) = matches.values_of("column_map") {
+        CsvConfig::parse_column_map(entries, !matches.is_present("no_trim_headers"))?;
+    }
+    if let Some(entries) = matches.values_of("opening_balance") {
+        CsvConfig::parse_opening_balances(entries)?;
+    }
+    if let Some(entries) = matches.values_of("account_timezone") {
+        CsvConfig::parse_account_zones(entries)?;
+    }
+    if let Some(code) = matches.value_of("currency_code") {
+        if !currency::is_currency_code(code) {
+            return Err(Box::new(ConfigError::invalid_value("currency-code", code)));
+        }
+    }
+    for (arg, name) in [("db_table", "table"), ("output-table", "output_table")] {
+        if let Some(value) = matches.value_of(name) {
+            TableTemplate::parse(value)
+                .map_err(|e| ConfigError::invalid_value(arg, &format!("{}: {}", value, e)))?;
+        }
+    }
+    if matches.is_present("route_by_date") {
+        let table = matches.value_of("table").unwrap_or_default();
+        if !TableTemplate::parse(table).map_or(false, |t| t.has_macros()) {
+            return Err(Box::new(ConfigError::invalid_value("db_table", table)));
+        }
+    }
+    for (arg, name) in [("before-sql", "before_sql"), ("after-sql", "after_sql")] {
+        if let Some(path) = matches.value_of(name) {
+            std::fs::read_to_string(path)
+                .map_err(|e| ConfigError::invalid_value(arg, &format!("{}: {}", path, e)))?;
+        }
+    }
+    if let Some(d) = matches.value_of("max_runtime") {
+        if parse_duration(d).is_none() {
+            return Err(Box::new(ConfigError::invalid_value("max-runtime", d)));
+        }
+    }
+    if let Some(offset) = matches.value_of("timezone") {
+        if domain::parse_offset(offset).is_none() {
+            return Err(Box::new(ConfigError::invalid_value("timezone", offset)));
+        }
+    }
+    if let Some(n) = matches.value_of("header_detect") {
+        match n.parse::<usize>() {
+            Ok(n) if n > 0 => (),
+            _ => return Err(Box::new(ConfigError::invalid_value("header-detect", n))),
+        }
+    }
+    if let Some(n) = matches.value_of("read_buffer_bytes") {
+        match n.parse::<usize>() {
+            Ok(n) if n > 0 => (),
+            _ => return Err(Box::new(ConfigError::invalid_value("read-buffer-bytes", n))),
+        }
+    }
+    if let Some(n) = matches.value_of("commit_every") {
+        match n.parse::<usize>() {
+            Ok(n) if n > 0 => (),
+            _ => return Err(Box::new(ConfigError::invalid_value("commit-every", n))),
+        }
+    }
+    // rows are always told apart by id; the account is optional
+    if let Some(columns) = matches.values_of("key_columns") {
+        let columns = columns.collect::<Vec<_>>();
+        if !columns.contains(&"tx_id") {
+            return Err(Box::new(ConfigError::invalid_value(
+                "key-columns",
+                &columns.join(","),
+            )));
+        }
+    }
+    // the compared column is written into the conflict clause, so it must be a plain name
+    if let Some(column) = matches.value_of("upsert_compare") {
+        if !is_identifier(column) {
+            return Err(Box::new(ConfigError::invalid_value(
+                "upsert-compare",
+                column,
+            )));
+        }
+    }
+    if let Some(path) = matches.value_of("dialect_map") {
+        dialect::load(Path::new(path))
+            .map_err(|e| ConfigError::invalid_value("dialect-map", &format!("{}: {}", path, e)))?;
+    }
+
+    let webhook_url = matches.value_of("webhook_url").map(|s| s.to_string());
+    let state_file = matches.value_of("state_file").map(PathBuf::from);
+    let checkpoint = matches.value_of("checkpoint").map(PathBuf::from);
+    let threads = match matches.value_of("threads").map(|n| (n, n.parse::<usize>())) {
+        Some((_, Ok(n))) if n > 0 => n,
+        Some((n, _)) => return Err(Box::new(ConfigError::invalid_value("threads", n))),
+        None => 1,
+    };
+    let max_in_flight_rows = match matches
+        .value_of("max_in_flight_rows")
+        .map(|n| (n, n.parse::<usize>()))
+    {
+        Some((_, Ok(n))) if n > 0 => Some(n),
+        Some((n, _)) => {
+            return Err(Box::new(ConfigError::invalid_value(
+                "max-in-flight-rows",
+                n,
+            )))
+        }
+        None => None,
+    };
+    let verify_totals = matches.is_present("verify_totals");
+    let assume_yes = matches.is_present("yes");
+    let report_path = matches.value_of("report_path").map(PathBuf::from);
+    let dedup_report = matches.value_of("dedup_report").map(PathBuf::from);
+    let group_report = match matches.value_of("group_report") {
+        Some("json") => Some(GroupReportFormat::Json),
+        Some(_) => Some(GroupReportFormat::Table),
+        None => None,
+    };
+    let on_too_long = match matches.value_of("on_too_long") {
+        Some("truncate") => TooLongPolicy::Truncate,
+        Some("reject") => TooLongPolicy::Reject,
+        _ => TooLongPolicy::Off,
+    };
+    let mirror_url = matches.value_of("mirror_url").map(|s| s.to_string());
+    let mirror_required = matches.is_present("mirror_required");
+    let replace_account = matches
+        .values_of("replace_account")
+        .and_then(|mut names| Some((names.next()?.to_string(), names.next()?.to_string())));
+    let check_duplicates = matches.is_present("check_duplicates");
+    let duplicates_file = matches.value_of("duplicates_file").map(PathBuf::from);
+    let date_bounds = match matches.value_of("date_bounds") {
+        Some("warn") => DateBoundsPolicy::Warn,
+        Some("reject") => DateBoundsPolicy::Reject,
+        _ => DateBoundsPolicy::Off,
+    };
+    let balance_anchor = match matches.value_of("balance_anchor") {
+        Some(_) => Some(BalanceAnchor::First),
+        None => None,
+    };
+
+    let confirm_prod = matches.is_present("confirm_prod");
+    let csv = CsvConfig::from(&matches);
     let database = DatabaseConfig::from(matches);
 
+    // modes that only read are allowed against production tables without confirmation
+    let writes = sample.is_none()
+        && export.is_none()
+        && parse_only.is_none()
+        && !explain
+        && !preview_new
+        && !check_duplicates
+        && !self_test;
+    check_prod_confirmed(&database, writes, confirm_prod)?;
+
     let c = Config {
         database,
+        csv,
         source,
         load_options,
+        sample,
+        export,
+        vendor_contains,
+        tail,
+        parse_only,
+        explain,
+        preview_new,
+        self_test,
+        sort_rows,
+        since_last_run,
+        skip_existing_accounts,
+        validate_schema,
+        primary_date,
+        deduplicate,
+        require_unique_ids,
+        dedupe_window,
+        dedup_report,
+        mask,
+        vendor_case,
+        unicode_form,
+        type_map,
+        type_signs,
+        transforms,
+        backend,
+        webhook_url,
+        state_file,
+        checkpoint,
+        date_bounds,
+        balance_anchor,
+        threads,
+        max_in_flight_rows,
+        verify_totals,
+        replace_account,
+        check_duplicates,
+        duplicates_file,
+        assume_yes,
+        report_path,
+        group_report,
+        on_too_long,
+        mirror_url,
+        mirror_required,
     };
     Ok(c)
 }
 
+/// parses a duration such as `90`, `90s`, `15m`, or `2h`; a bare number is in seconds
+fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => return None,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .map(|n| Duration::from_secs(n * seconds))
+}
+
+/// whether the name is a plain SQL identifier: a letter or underscore, then letters, digits,
+/// or underscores
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// whether the path names a csv file, by (case-sensitive) extension
+pub fn is_csv(p: &Path) -> bool {
+    p.extension() == Some(std::ffi::OsStr::new("csv"))
+}
+
+/// whether the path names a gzipped csv file, by (case-sensitive) `.csv.gz` extension
+pub fn is_gzipped_csv(p: &Path) -> bool {
+    p.extension() == Some(std::ffi::OsStr::new("gz"))
+        && p.file_stem().map_or(false, |stem| is_csv(Path::new(stem)))
+}
+
+/// the files of the directory a directory import reads, in file name order
+pub fn csv_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && (is_csv(path) || is_gzipped_csv(path)))
+        .collect::<Vec<_>>();
+    paths.sort();
+    Ok(paths)
+}
+
+/// refuses writes to a production table without `--confirm-prod`
+fn check_prod_confirmed(
+    database: &DatabaseConfig,
+    writes: bool,
+    confirm_prod: bool,
+) -> Result<(), ConfigError> {
+    if database.get_environment() == Some(Environment::Prod) && writes && !confirm_prod {
+        let table_name = database
+            .get_swap_target()
+            .map_or_else(|| database.get_table_name(), |t| t.to_string());
+        return Err(ConfigError::prod_not_confirmed(&table_name));
+    }
+    Ok(())
+}
+
+/// whether a directory import of the directory would read any files; checked with the same
+/// filter as the import itself
 fn directory_contains_csvs(p: &Path) -> bool {
-    let ext = Some(std::ffi::OsStr::new("csv"));
-
-    let read_result = std::fs::read_dir(p);
-    return if let Ok(read_dir) = read_result {
-        // let read_dir: std::fs::ReadDir = read_result.unwrap();
-        for entry in read_dir {
-            if let std::io::Result::Ok(dir_entry) = entry {
-                let path: PathBuf = dir_entry.path();
-                if ext == path.extension() {
-                    return true;
-                }
-            }
-        }
+    csv_files(p).map_or(false, |paths| !paths.is_empty())
+}
 
-        false
-    } else {
-        false
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn append_only_rejects_upsert() {
+        let args = [
+            "csv-importer",
+            "--file",
+            "x.csv",
+            "--append-only",
+            "--upsert",
+        ];
+        assert!(app().get_matches_from_safe(&args).is_err());
+    }
+
+    #[test]
+    fn append_only_rejects_load_then_swap() {
+        let args = [
+            "csv-importer",
+            "--file",
+            "x.csv",
+            "--append-only",
+            "--load-then-swap",
+        ];
+        assert!(app().get_matches_from_safe(&args).is_err());
+    }
+
+    #[test]
+    fn upsert_alone_is_accepted() {
+        let args = ["csv-importer", "--file", "x.csv", "--upsert"];
+        assert!(app().get_matches_from_safe(&args).is_ok());
+    }
+
+    #[test]
+    fn max_runtime_is_parsed() {
+        let matches = app().get_matches_from(&["csv-importer", "--max-runtime", "30m"]);
+        let database = DatabaseConfig::from(matches);
+        assert_eq!(
+            database.get_max_runtime(),
+            Some(Duration::from_secs(30 * 60))
+        );
+        assert_eq!(DatabaseConfig::default().get_max_runtime(), None);
+    }
+
+    #[test]
+    fn prod_writes_are_refused_without_confirmation() {
+        let database = |args: &[&str]| {
+            DatabaseConfig::from(
+                app().get_matches_from(std::iter::once("csv-importer").chain(args.iter().copied())),
+            )
+        };
+        let prod = database(&["--env", "prod"]);
+        assert_eq!(prod.get_table_name(), "transactions_prod");
+
+        assert!(matches!(
+            check_prod_confirmed(&prod, true, false),
+            Err(ConfigError::ProdNotConfirmed(table)) if table == "transactions_prod"
+        ));
+        assert!(check_prod_confirmed(&prod, true, true).is_ok());
+        // modes that only read need no confirmation
+        assert!(check_prod_confirmed(&prod, false, false).is_ok());
+        assert!(check_prod_confirmed(&database(&["--env", "dev"]), true, false).is_ok());
+    }
+
+    #[test]
+    fn upsert_compare_must_be_an_identifier() {
+        assert!(is_identifier("updated_at"));
+        assert!(is_identifier("_rev2"));
+        assert!(!is_identifier(""));
+        assert!(!is_identifier("2nd"));
+        assert!(!is_identifier("balance; DROP TABLE t"));
+        assert!(!is_identifier("\"amount\""));
+    }
+
+    #[test]
+    fn a_directory_of_gzipped_csvs_is_not_empty() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("csv-importer-gzipped-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = std::fs::File::create(dir.join("2021.csv.gz")).unwrap();
+        let mut gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        gz.write_all(b"ACCOUNT,ID,Date,Amount,Balance,Vendor,Type\n")
+            .unwrap();
+        gz.finish().unwrap();
+        std::fs::write(dir.join("notes.txt.gz"), b"").unwrap();
+
+        assert!(directory_contains_csvs(&dir));
+        assert_eq!(csv_files(&dir).unwrap(), vec![dir.join("2021.csv.gz")]);
+    }
 }
@@ -1,26 +1,343 @@
+use std::borrow::Cow;
+use std::cell::Cell;
 use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, Neg, Sub};
 use std::str::FromStr;
 
+use log::warn;
 use serde::{Deserialize, Deserializer};
 
-#[derive(Debug, Clone)]
+/// The largest whole amount supported; matches the integer digits of a NUMERIC(13,4) column
+const MAX_WHOLE: i32 = 999_999_999;
+/// The number of fractional digits kept by a `Currency`
+const SCALE: usize = 2;
+/// The number of minor units in a whole amount, i.e. cents in a dollar
+const UNITS_PER_WHOLE: i64 = 100;
+
+/// An amount of money, as a count of minor units; the count carries the sign, so -0.50 is
+/// negative too. Amounts compare and add by their value; the operators saturate at the bounds of
+/// the count, while `checked_add` and `checked_sub` return None instead.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Currency {
-    whole: i32,
-    digits: u8,
+    units: i64,
 }
 
 impl Currency {
     fn zero() -> Self {
+        Self { units: 0 }
+    }
+
+    /// the same magnitude, negative for debits and positive for credits
+    pub fn with_sign(&self, negative: bool) -> Self {
+        let units = self.units.abs();
         Self {
-            whole: 0,
-            digits: 0,
+            units: if negative { -units } else { units },
+        }
+    }
+
+    /// the value of the whole amount and cents, with the sign of the whole amount, e.g.
+    /// `Currency::new(-12, 34)` is -12.34; cents of 100 or more carry into the whole amount. A
+    /// whole amount of zero has no sign, so values between -1 and 0 (e.g. -0.50) can't be built
+    /// this way; use `from_minor_units(-50)` instead.
+    pub fn new(whole: i32, cents: u8) -> Self {
+        let whole = whole as i64 * UNITS_PER_WHOLE;
+        if whole < 0 {
+            Self::from_minor_units(whole - cents as i64)
+        } else {
+            Self::from_minor_units(whole + cents as i64)
+        }
+    }
+
+    /// the value of the given minor units, i.e. cents
+    pub fn from_minor_units(units: i64) -> Self {
+        Self { units }
+    }
+
+    /// the value in minor units, i.e. cents
+    pub fn to_minor_units(&self) -> i64 {
+        self.units
+    }
+
+    /// the value of the given cents, like `from_minor_units`
+    pub fn from_cents(cents: i64) -> Self {
+        Self::from_minor_units(cents)
+    }
+
+    /// the value in cents, like `to_minor_units`; e.g. to bind amounts as integers
+    pub fn as_cents(&self) -> i64 {
+        self.to_minor_units()
+    }
+
+    /// the sum, or None if it overflows
+    pub fn checked_add(&self, other: &Currency) -> Option<Currency> {
+        self.units
+            .checked_add(other.units)
+            .map(Currency::from_minor_units)
+    }
+
+    /// the difference, or None if it overflows
+    pub fn checked_sub(&self, other: &Currency) -> Option<Currency> {
+        self.units
+            .checked_sub(other.units)
+            .map(Currency::from_minor_units)
+    }
+
+    /// the largest (or smallest, if negative) supported value
+    fn clamped(negative: bool) -> Self {
+        let units = MAX_WHOLE as i64 * UNITS_PER_WHOLE + (UNITS_PER_WHOLE - 1);
+        Self {
+            units: if negative { -units } else { units },
         }
     }
 }
 
+impl Add for Currency {
+    type Output = Currency;
+
+    fn add(self, other: Currency) -> Currency {
+        Currency::from_minor_units(self.units.saturating_add(other.units))
+    }
+}
+
+impl Sub for Currency {
+    type Output = Currency;
+
+    fn sub(self, other: Currency) -> Currency {
+        Currency::from_minor_units(self.units.saturating_sub(other.units))
+    }
+}
+
+impl Neg for Currency {
+    type Output = Currency;
+
+    fn neg(self) -> Currency {
+        Currency::from_minor_units(self.units.saturating_neg())
+    }
+}
+
+impl Sum for Currency {
+    fn sum<I: Iterator<Item = Currency>>(iter: I) -> Currency {
+        iter.fold(Currency::zero(), Add::add)
+    }
+}
+
+/// How values with more fractional digits than `SCALE` are rounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// halves are rounded away from zero: 12.005 becomes 12.01
+    HalfUp,
+    /// halves are rounded to the even digit (banker's rounding): 12.005 becomes 12.00, and
+    /// 12.015 becomes 12.02
+    HalfEven,
+    /// the extra digits are dropped: 12.009 becomes 12.00
+    Truncate,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding::HalfEven
+    }
+}
+
+impl Rounding {
+    /// whether the kept fractional digits round up in magnitude, given the dropped digits
+    fn rounds_up(&self, kept: u32, dropped: &[u32]) -> bool {
+        match (self, dropped.split_first()) {
+            (_, None) | (Rounding::Truncate, _) => false,
+            (Rounding::HalfUp, Some((first, _))) => *first >= 5,
+            (Rounding::HalfEven, Some((first, rest))) => {
+                *first > 5 || (*first == 5 && (rest.iter().any(|d| *d != 0) || kept % 2 == 1))
+            }
+        }
+    }
+}
+
+/// How the digits of amounts are grouped, and separated from the fraction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CurrencyFormat {
+    /// `1,234.56`: commas group thousands, and a point separates the fraction
+    Us,
+    /// `1.234,56`: points group thousands, and a comma separates the fraction
+    European,
+}
+
+impl Default for CurrencyFormat {
+    fn default() -> Self {
+        CurrencyFormat::Us
+    }
+}
+
+impl CurrencyFormat {
+    /// the value in the US format, which is the format the parsers read
+    fn to_us<'a>(&self, from: &'a str) -> Cow<'a, str> {
+        match self {
+            CurrencyFormat::Us => Cow::Borrowed(from),
+            CurrencyFormat::European => Cow::Owned(
+                from.chars()
+                    .filter(|c| *c != '.')
+                    .map(|c| if c == ',' { '.' } else { c })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// What to do with a value exceeding the supported range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowPolicy {
+    /// the row is rejected as unparseable
+    Error,
+    /// the value is clamped to the supported range
+    Clamp,
+    /// the row is skipped with a warning
+    Skip,
+}
+
+/// What to do with a row whose amount is blank or unparseable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NullAmountPolicy {
+    /// the amount is imported as zero, with a warning
+    Zero,
+    /// the row is rejected as unparseable
+    Skip,
+    /// the import of the file is aborted
+    Error,
+}
+
+/// What to do with a sign indicator that is neither a debit nor a credit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownSignPolicy {
+    /// the row is rejected as unparseable
+    Error,
+    /// the amount is taken as a credit
+    Credit,
+    /// the amount is taken as a debit
+    Debit,
+}
+
+impl Default for UnknownSignPolicy {
+    fn default() -> Self {
+        UnknownSignPolicy::Error
+    }
+}
+
+/// parses a debit/credit indicator such as "DR"/"CR" or "D"/"C"; returns whether the amount is
+/// negative, or None if the indicator is unknown
+pub fn parse_sign_indicator(indicator: &str) -> Option<bool> {
+    match indicator.trim().to_uppercase().as_str() {
+        "DR" | "D" | "DEBIT" => Some(true),
+        "CR" | "C" | "CREDIT" => Some(false),
+        _ => None,
+    }
+}
+
+/// Options applied by `deserialize_money` and `deserialize_amount`
+#[derive(Debug, Clone, Copy)]
+pub struct CurrencyOptions {
+    pub on_overflow: OverflowPolicy,
+    pub on_null_amount: NullAmountPolicy,
+    pub rounding: Rounding,
+    /// whether to warn about values with more fractional digits than are kept
+    pub warn_precision_loss: bool,
+    /// whether all whitespace is stripped before parsing, for values padded for alignment such
+    /// as `1 234.56` or `-  45.00`
+    pub coalesce_whitespace: bool,
+    /// whether values without a decimal point are hundredths, such as `1234` for 12.34
+    pub minor_units: bool,
+    pub format: CurrencyFormat,
+}
+
+impl Default for CurrencyOptions {
+    fn default() -> Self {
+        Self {
+            on_overflow: OverflowPolicy::Error,
+            on_null_amount: NullAmountPolicy::Skip,
+            rounding: Rounding::default(),
+            warn_precision_loss: true,
+            coalesce_whitespace: false,
+            minor_units: false,
+            format: CurrencyFormat::Us,
+        }
+    }
+}
+
+thread_local! {
+    static OPTIONS: Cell<CurrencyOptions> = Cell::new(CurrencyOptions::default());
+    static OVERFLOWED: Cell<bool> = Cell::new(false);
+    static PRECISION_LOST: Cell<bool> = Cell::new(false);
+    static NULL_AMOUNT: Cell<bool> = Cell::new(false);
+}
+
+/// runs `f` with the given options applied to all currency deserialization on this thread
+pub fn with_options<T, F: FnOnce() -> T>(options: CurrencyOptions, f: F) -> T {
+    let previous = OPTIONS.with(|o| o.replace(options));
+    let result = f();
+    OPTIONS.with(|o| o.set(previous));
+    result
+}
+
+/// whether a value overflowed since the last call; resets the flag
+pub fn take_overflowed() -> bool {
+    OVERFLOWED.with(|o| o.replace(false))
+}
+
+/// whether a value lost precision since the last call; resets the flag
+pub fn take_precision_lost() -> bool {
+    PRECISION_LOST.with(|p| p.replace(false))
+}
+
+/// whether an amount was blank or unparseable since the last call; resets the flag
+pub fn take_null_amount() -> bool {
+    NULL_AMOUNT.with(|n| n.replace(false))
+}
+
+/// whether the value has non-zero fractional digits beyond `SCALE`, which are rounded away when
+/// parsed
+fn loses_precision(from: &str) -> bool {
+    from.split_once('.').map_or(false, |(_, fraction)| {
+        fraction
+            .chars()
+            .filter(|c| c.is_ascii_digit())
+            .skip(SCALE)
+            .any(|c| c != '0')
+    })
+}
+
+/// The signs that make a value negative: a hyphen-minus, and the Unicode minus sign
+const MINUS_SIGNS: [char; 2] = ['-', '\u{2212}'];
+
+/// whether the value is negative: in parentheses, with a minus sign in its whole part (leading,
+/// as in `-12.34` or `$-12.34`), or with a trailing minus sign, as in `12.34-`
+fn is_negative(from: &str, whole: &str) -> bool {
+    from.contains('(')
+        || whole.contains(&MINUS_SIGNS[..])
+        || from.trim().ends_with(&MINUS_SIGNS[..])
+}
+
+/// whether the value is one of the forms of zero without a fraction: a dash, as in accounting
+/// formats (`-`, `$-`, `$ -`), or zeros (`0`, `$0`)
+fn is_zero(from: &str) -> bool {
+    let compact = from
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '$')
+        .collect::<String>();
+
+    compact == "-" || (!compact.is_empty() && compact.chars().all(|c| c == '0'))
+}
+
+/// whether the code has the shape of an ISO 4217 currency code: three upper-case letters, such
+/// as `USD`
+pub fn is_currency_code(code: &str) -> bool {
+    code.len() == 3 && code.chars().all(|c| c.is_ascii_uppercase())
+}
+
 impl fmt::Display for Currency {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}.{:02}", self.whole, self.digits)
+        let sign = if self.units < 0 { "-" } else { "" };
+        let units = self.units.unsigned_abs();
+        let per_whole = UNITS_PER_WHOLE as u64;
+        write!(f, "{}{}.{:02}", sign, units / per_whole, units % per_whole)
     }
 }
 
@@ -28,66 +345,130 @@ impl FromStr for Currency {
     type Err = ParseCurrencyError;
 
     fn from_str(from: &str) -> Result<Self, Self::Err> {
-        if let Some((whole_chars, digit_chars)) = from.split_once(".") {
-            let mut negative = false;
+        Currency::parse_with(from, CurrencyFormat::default())
+    }
+}
+
+impl Currency {
+    /// parses the value in the given format, e.g. `1.234,56` in the European format; fractional
+    /// digits beyond `SCALE` are rounded half to even
+    pub fn parse_with(from: &str, format: CurrencyFormat) -> Result<Self, ParseCurrencyError> {
+        Currency::parse_rounded(&format.to_us(from), Rounding::default())
+    }
+
+    /// parses the value, rounding fractional digits beyond `SCALE` per the rounding mode
+    pub fn parse_rounded(from: &str, rounding: Rounding) -> Result<Self, ParseCurrencyError> {
+        if is_zero(from) {
+            Ok(Currency::zero())
+        } else if let Some((whole_chars, digit_chars)) = from.split_once(".") {
+            let negative = is_negative(from, whole_chars);
             let mut whole = 0i32;
-            let mut digits = 0;
-            let mut magnitude = 1i32;
-
-            for c in whole_chars.chars().rev() {
-                if c == '(' {
-                    negative = true
-                } else if c.is_numeric() {
-                    if let Some(digit) = c.to_digit(10) {
-                        whole += (digit as i32) * magnitude;
-                        magnitude *= 10;
-                    }
-                }
-            }
 
-            magnitude = 1;
-            for c in digit_chars.chars().rev() {
-                if c.is_numeric() {
-                    if let Some(digit) = c.to_digit(10) {
-                        digits += (digit as i32) * magnitude;
-                        magnitude *= 10;
-                    }
+            for c in whole_chars.chars() {
+                if let Some(digit) = c.to_digit(10) {
+                    whole = whole
+                        .checked_mul(10)
+                        .and_then(|w| w.checked_add(digit as i32))
+                        .filter(|w| *w <= MAX_WHOLE)
+                        .ok_or_else(|| ParseCurrencyError::overflow(from, negative))?;
                 }
             }
 
-            if negative {
-                whole *= -1;
+            // the fraction is positional; "5" and "50" are both fifty hundredths
+            let fraction = digit_chars
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .collect::<Vec<_>>();
+            let mut digits = fraction
+                .iter()
+                .chain(std::iter::repeat(&0))
+                .take(SCALE)
+                .fold(0, |digits, d| digits * 10 + d);
+
+            if rounding.rounds_up(digits, fraction.get(SCALE..).unwrap_or_default()) {
+                digits += 1;
+            }
+            // rounding up 0.995 carries into the whole part
+            if digits >= 10u32.pow(SCALE as u32) {
+                digits = 0;
+                whole = whole
+                    .checked_add(1)
+                    .filter(|w| *w <= MAX_WHOLE)
+                    .ok_or_else(|| ParseCurrencyError::overflow(from, negative))?;
             }
 
-            let result = Currency {
-                whole,
-                digits: digits as u8,
-            };
-            Ok(result)
-        } else if from.replace(" ", "") == "$-" {
-            Ok(Currency::zero())
+            let units = whole as i64 * UNITS_PER_WHOLE + digits as i64;
+            Ok(Currency::from_minor_units(if negative {
+                -units
+            } else {
+                units
+            }))
         } else {
             Err(ParseCurrencyError::new(from))
         }
     }
+
+    /// parses a value without a decimal point as hundredths, i.e. cents: `1234` is 12.34
+    pub fn parse_minor_units(from: &str) -> Result<Self, ParseCurrencyError> {
+        let negative = is_negative(from, from);
+        let mut units = 0i64;
+
+        for digit in from.chars().filter_map(|c| c.to_digit(10)) {
+            units = units
+                .checked_mul(10)
+                .and_then(|u| u.checked_add(digit as i64))
+                .filter(|u| u / UNITS_PER_WHOLE <= MAX_WHOLE as i64)
+                .ok_or_else(|| ParseCurrencyError::overflow(from, negative))?;
+        }
+
+        Ok(Currency::from_minor_units(if negative {
+            -units
+        } else {
+            units
+        }))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct ParseCurrencyError {
     source: String,
+    kind: ParseCurrencyErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ParseCurrencyErrorKind {
+    Invalid,
+    Overflow { negative: bool },
 }
 
 impl ParseCurrencyError {
     fn new(s: &str) -> Self {
         ParseCurrencyError {
             source: s.to_string(),
+            kind: ParseCurrencyErrorKind::Invalid,
+        }
+    }
+
+    fn overflow(s: &str, negative: bool) -> Self {
+        ParseCurrencyError {
+            source: s.to_string(),
+            kind: ParseCurrencyErrorKind::Overflow { negative },
         }
     }
 }
 
 impl fmt::Display for ParseCurrencyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Could not parse {} into a currency.", self.source)
+        match self.kind {
+            ParseCurrencyErrorKind::Invalid => {
+                write!(f, "Could not parse {} into a currency.", self.source)
+            }
+            ParseCurrencyErrorKind::Overflow { .. } => write!(
+                f,
+                "Could not parse {} into a currency; it exceeds the supported range.",
+                self.source
+            ),
+        }
     }
 }
 
@@ -102,5 +483,298 @@ where
     D: Deserializer<'de>,
 {
     let buf = String::deserialize(d)?;
-    Currency::from_str(&buf).map_err(serde::de::Error::custom)
+
+    parse_money(&buf).map_err(serde::de::Error::custom)
+}
+
+/// deserializes the amount of a row, which unlike other values is subject to the null amount
+/// policy when blank or unparseable
+pub fn deserialize_amount<'de, D>(d: D) -> std::result::Result<Currency, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = String::deserialize(d)?;
+
+    parse_money(&buf).or_else(|e| {
+        if e.kind != ParseCurrencyErrorKind::Invalid {
+            return Err(serde::de::Error::custom(e));
+        }
+
+        NULL_AMOUNT.with(|n| n.set(true));
+        match OPTIONS.with(|o| o.get()).on_null_amount {
+            NullAmountPolicy::Zero => {
+                warn!("Importing the amount {:?} as zero.", buf.trim());
+                Ok(Currency::zero())
+            }
+            NullAmountPolicy::Skip | NullAmountPolicy::Error => Err(serde::de::Error::custom(e)),
+        }
+    })
+}
+
+/// parses the value, warning about lost precision, and clamping it per the overflow policy
+fn parse_money(buf: &str) -> Result<Currency, ParseCurrencyError> {
+    let options = OPTIONS.with(|o| o.get());
+
+    let coalesced;
+    let buf = if options.coalesce_whitespace {
+        coalesced = buf
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>();
+        coalesced.as_str()
+    } else {
+        buf
+    };
+    let us = options.format.to_us(buf);
+    let buf = us.as_ref();
+
+    if loses_precision(buf) {
+        PRECISION_LOST.with(|p| p.set(true));
+
+        if options.warn_precision_loss {
+            warn!(
+                "{} has more than {} fractional digits; it is rounded ({:?}).",
+                buf.trim(),
+                SCALE,
+                options.rounding
+            );
+        }
+    }
+
+    // values with a decimal point are never minor units
+    let parsed =
+        if options.minor_units && !buf.contains('.') && buf.chars().any(|c| c.is_ascii_digit()) {
+            Currency::parse_minor_units(buf)
+        } else {
+            Currency::parse_rounded(buf, options.rounding)
+        };
+
+    parsed.or_else(|e| {
+        if let ParseCurrencyErrorKind::Overflow { negative } = e.kind {
+            OVERFLOWED.with(|o| o.set(true));
+
+            if options.on_overflow == OverflowPolicy::Clamp {
+                warn!("Clamping {} to the supported currency range.", buf.trim());
+                return Ok(Currency::clamped(negative));
+            }
+        }
+
+        Err(e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// parses the value with the default options, and formats it back
+    fn parsed(from: &str) -> String {
+        Currency::from_str(from).unwrap().to_string()
+    }
+
+    /// parses the value like a field of a row, with the options
+    fn parsed_with(from: &str, options: CurrencyOptions) -> Result<Currency, ParseCurrencyError> {
+        with_options(options, || parse_money(from))
+    }
+
+    #[test]
+    fn overflow_follows_the_policy() {
+        let options = |on_overflow: OverflowPolicy| CurrencyOptions {
+            on_overflow,
+            ..CurrencyOptions::default()
+        };
+        let clamped = |from: &str| {
+            parsed_with(from, options(OverflowPolicy::Clamp))
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(parsed_with("1000000000.00", options(OverflowPolicy::Error)).is_err());
+        assert!(take_overflowed());
+        assert_eq!(clamped("1000000000.00"), "999999999.99");
+        assert_eq!(clamped("-1000000000.00"), "-999999999.99");
+        assert!(take_overflowed());
+        // the reader skips the row, so the value itself is an error
+        assert!(parsed_with("1000000000.00", options(OverflowPolicy::Skip)).is_err());
+        assert!(take_overflowed());
+
+        assert_eq!(clamped("999999999.99"), "999999999.99");
+        assert!(!take_overflowed());
+    }
+
+    #[test]
+    fn fraction_is_positional() {
+        assert_eq!(parsed("0.5"), "0.50");
+        assert_eq!(parsed("0.05"), "0.05");
+        assert_eq!(parsed("0.50"), "0.50");
+        assert_eq!(parsed("1.5"), "1.50");
+        assert_eq!(parsed("1.05"), "1.05");
+        assert_eq!(parsed("1.50"), "1.50");
+    }
+
+    #[test]
+    fn fraction_beyond_scale_is_rounded() {
+        assert_eq!(parsed("1.5000"), "1.50");
+        assert_eq!(parsed("1.505"), "1.50");
+        assert_eq!(parsed("1.515"), "1.52");
+    }
+
+    #[test]
+    fn zero_forms_are_zero() {
+        for zero in &["-", "$-", "$ -", " - ", "0", "0.00", "$0.00", "-0.00"] {
+            assert_eq!(Currency::from_str(zero), Ok(Currency::zero()), "{:?}", zero);
+        }
+        for invalid in &["", "$", "--", "0-0", "abc"] {
+            assert!(Currency::from_str(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn halves_follow_the_rounding_mode() {
+        let rounded = |from: &str, rounding: Rounding| {
+            parsed_with(
+                from,
+                CurrencyOptions {
+                    rounding,
+                    ..CurrencyOptions::default()
+                },
+            )
+            .unwrap()
+            .to_string()
+        };
+
+        assert_eq!(rounded("12.005", Rounding::HalfUp), "12.01");
+        assert_eq!(rounded("12.005", Rounding::HalfEven), "12.00");
+        assert_eq!(rounded("12.005", Rounding::Truncate), "12.00");
+        assert_eq!(rounded("12.015", Rounding::HalfEven), "12.02");
+        assert_eq!(rounded("12.009", Rounding::Truncate), "12.00");
+        assert_eq!(rounded("-12.005", Rounding::HalfUp), "-12.01");
+    }
+
+    #[test]
+    fn value_is_a_count_of_cents() {
+        let units = |from: &str| Currency::from_str(from).unwrap().to_minor_units();
+        assert_eq!(units("12.34"), 1234);
+        assert_eq!(units("(0.50)"), -50);
+        assert_eq!(units("0.00"), 0);
+        assert_eq!(Currency::from_minor_units(-1234).to_string(), "-12.34");
+        assert_eq!(Currency::from_minor_units(5).to_string(), "0.05");
+        assert_eq!(
+            Currency::from_cents(-1234),
+            Currency::from_minor_units(-1234)
+        );
+        assert_eq!(Currency::from_str("-12.34").unwrap().as_cents(), -1234);
+    }
+
+    #[test]
+    fn formatting_round_trips() {
+        for (from, formatted) in &[
+            ("-12.34", "-12.34"),
+            ("(12.34)", "-12.34"),
+            ("0.00", "0.00"),
+        ] {
+            assert_eq!(parsed(from), *formatted);
+            assert_eq!(parsed(formatted), *formatted);
+        }
+    }
+
+    #[test]
+    fn thousands_are_grouped() {
+        assert_eq!(parsed("1,234.56"), "1234.56");
+        assert_eq!(parsed("12,345,678.90"), "12345678.90");
+
+        let european = |from: &str| {
+            Currency::parse_with(from, CurrencyFormat::European)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(european("1.234,56"), "1234.56");
+        assert_eq!(european("12.345.678,90"), "12345678.90");
+        assert_eq!(european("0,5"), "0.50");
+    }
+
+    #[test]
+    fn minus_signs_are_negative() {
+        assert_eq!(parsed("-12.34"), "-12.34");
+        assert_eq!(parsed("12.34-"), "-12.34");
+        assert_eq!(parsed("\u{2212}12.34"), "-12.34");
+        assert_eq!(parsed("$-12.34"), "-12.34");
+        assert_eq!(parsed("-0.50"), "-0.50");
+    }
+
+    #[test]
+    fn amounts_sum_and_order() {
+        let amounts = ["12.34", "-0.50", "(1.00)"]
+            .iter()
+            .map(|from| Currency::from_str(from).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            amounts.iter().cloned().sum::<Currency>().to_string(),
+            "10.84"
+        );
+        assert_eq!(amounts.iter().max().unwrap().to_string(), "12.34");
+        assert_eq!(amounts.iter().min().unwrap().to_string(), "-1.00");
+        assert!(Currency::from_minor_units(-1) < Currency::zero());
+    }
+
+    #[test]
+    fn checked_arithmetic_detects_overflow() {
+        let max = Currency::from_minor_units(i64::MAX);
+        let one = Currency::from_minor_units(1);
+        assert_eq!(max.checked_add(&one), None);
+        assert_eq!(Currency::from_minor_units(i64::MIN).checked_sub(&one), None);
+        assert_eq!(one.checked_add(&one), Some(Currency::from_minor_units(2)));
+        // the operators saturate instead
+        assert_eq!(max.clone() + one, max);
+    }
+
+    #[test]
+    fn padded_amounts_are_coalesced() {
+        let coalesced = |from: &str, format: CurrencyFormat| {
+            let options = CurrencyOptions {
+                coalesce_whitespace: true,
+                format,
+                ..CurrencyOptions::default()
+            };
+            parsed_with(from, options).map(|c| c.to_string())
+        };
+
+        assert_eq!(
+            coalesced("1 234.56", CurrencyFormat::Us),
+            Ok("1234.56".to_string())
+        );
+        assert_eq!(
+            coalesced("-  45.00", CurrencyFormat::Us),
+            Ok("-45.00".to_string())
+        );
+        assert_eq!(
+            coalesced("(1 234.56)", CurrencyFormat::Us),
+            Ok("-1234.56".to_string())
+        );
+        assert_eq!(
+            coalesced("- 1 234,56", CurrencyFormat::European),
+            Ok("-1234.56".to_string())
+        );
+    }
+
+    #[test]
+    fn integer_amounts_are_minor_units_when_configured() {
+        let minor = |from: &str| {
+            let options = CurrencyOptions {
+                minor_units: true,
+                ..CurrencyOptions::default()
+            };
+            parsed_with(from, options).map(|c| c.to_string())
+        };
+
+        assert_eq!(minor("1234"), Ok("12.34".to_string()));
+        assert_eq!(minor("-1234"), Ok("-12.34".to_string()));
+        assert_eq!(minor("(5)"), Ok("-0.05".to_string()));
+        // values with a decimal point are whole amounts either way
+        assert_eq!(minor("12.34"), Ok("12.34".to_string()));
+        assert_eq!(
+            parsed_with("1234", CurrencyOptions::default()).map(|c| c.to_string()),
+            Ok("1234.00".to_string())
+        );
+    }
 }
@@ -0,0 +1,170 @@
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::domain;
+
+/// The parsing settings of the files whose names match a pattern, where `*` matches any text;
+/// settings that are absent fall back to the defaults
+#[derive(Debug, Clone, Deserialize)]
+pub struct Dialect {
+    pattern: String,
+    delimiter: Option<char>,
+    #[serde(default)]
+    encoding: Encoding,
+    /// a chrono format pattern, such as `%d.%m.%Y`
+    date_format: Option<String>,
+}
+
+/// The text encoding of a csv file
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum Encoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// ISO-8859-1, as exported by some older banking software
+    #[serde(rename = "latin1")]
+    Latin1,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding::Utf8
+    }
+}
+
+impl Dialect {
+    /// whether the dialect applies to the file name of the path
+    pub fn matches(&self, path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| domain::glob_matches(&self.pattern, name))
+    }
+
+    pub fn get_delimiter(&self) -> Option<u8> {
+        self.delimiter.map(|d| d as u8)
+    }
+
+    pub fn get_encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn get_date_format(&self) -> Option<&str> {
+        self.date_format.as_deref()
+    }
+
+    /// a dialect matching any file, with the settings of the base dialect, if any, except where
+    /// overridden
+    pub fn overridden(
+        base: Option<&Dialect>,
+        delimiter: Option<char>,
+        date_format: Option<&str>,
+    ) -> Dialect {
+        Dialect {
+            pattern: "*".to_string(),
+            delimiter: delimiter.or_else(|| base.and_then(|d| d.delimiter)),
+            encoding: base.map_or(Encoding::Utf8, |d| d.encoding),
+            date_format: date_format
+                .map(|f| f.to_string())
+                .or_else(|| base.and_then(|d| d.date_format.clone())),
+        }
+    }
+}
+
+/// loads the dialects of a JSON file holding a list of dialects, in order of precedence
+pub fn load(path: &Path) -> Result<Vec<Dialect>, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let dialects: Vec<Dialect> =
+        serde_json::from_reader(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    match dialects
+        .iter()
+        .find(|d| d.delimiter.map_or(false, |c| !c.is_ascii()))
+    {
+        Some(d) => Err(format!("the delimiter of {} is not ascii", d.pattern)),
+        None => Ok(dialects),
+    }
+}
+
+/// decodes the text of the reader into utf-8, which the csv reader requires
+pub fn decode<'a, R: Read + 'a>(
+    encoding: Encoding,
+    mut reader: R,
+) -> io::Result<Box<dyn Read + 'a>> {
+    match encoding {
+        Encoding::Utf8 => Ok(Box::new(reader)),
+        Encoding::Latin1 => {
+            // every latin1 byte is the code point of the same value
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes)?;
+            let text = bytes.iter().map(|b| *b as char).collect::<String>();
+            Ok(Box::new(io::Cursor::new(text.into_bytes())))
+        }
+    }
+}
+
+/// reads the text of the reader, failing unless it is valid utf-8 after any byte order mark,
+/// rather than leaving invalid bytes to fail (or be replaced in) individual rows
+pub fn require_utf8<'a, R: Read + 'a>(mut reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let text = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes);
+
+    if let Err(e) = std::str::from_utf8(text) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("not valid utf-8 at byte {}", e.valid_up_to()),
+        ));
+    }
+    Ok(Box::new(io::Cursor::new(text.to_vec())))
+}
+
+/// finds the header row among the first lines of the reader, as the line with the most fields
+/// named like an expected header (the first, if several tie), and skips the lines before it.
+/// Returns the number of lines skipped, and the text from the header row on, or None if no line
+/// has an expected header.
+pub fn skip_preamble<'a>(
+    reader: Box<dyn Read + 'a>,
+    lines: usize,
+    delimiter: u8,
+    expected: &[&str],
+) -> io::Result<Option<(usize, Box<dyn Read + 'a>)>> {
+    let mut reader = io::BufReader::new(reader);
+    let mut preamble = Vec::new();
+    for _ in 0..lines {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        preamble.push(line);
+    }
+
+    let matching = |line: &str| {
+        csv::ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(delimiter)
+            .from_reader(line.as_bytes())
+            .records()
+            .next()
+            .and_then(|record| record.ok())
+            .map_or(0, |record| {
+                record
+                    .iter()
+                    .map(|field| field.trim_start_matches('\u{feff}').trim())
+                    .filter(|field| expected.contains(field))
+                    .count()
+            })
+    };
+    let header = preamble
+        .iter()
+        .enumerate()
+        .map(|(i, line)| (i, matching(line)))
+        .filter(|(_, n)| *n > 0)
+        .max_by_key(|(i, n)| (*n, std::cmp::Reverse(*i)));
+
+    Ok(header.map(|(i, _)| {
+        let text = preamble[i..].concat();
+        let rest: Box<dyn Read + 'a> = Box::new(io::Cursor::new(text.into_bytes()).chain(reader));
+        (i, rest)
+    }))
+}
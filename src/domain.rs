@@ -1,48 +1,470 @@
-use serde::{Deserialize, Deserializer};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cell::{Cell, RefCell};
 use std::fmt;
+use std::hash::Hasher;
+use std::path::Path;
+use std::str::FromStr;
 
+use chrono::format::{Parsed, StrftimeItems};
 use chrono::prelude::*;
+use chrono_tz::Tz;
 use serde::de::Unexpected;
+use siphasher::sip::SipHasher13;
 
-use crate::currency::{deserialize_money, Currency};
+use crate::currency::{deserialize_amount, deserialize_money, Currency};
 
-#[derive(Deserialize, Debug, Clone)]
+/// A row of a csv file; deserialized from the csv headers, and serialized (as a JSONB document)
+/// with the names of the table columns
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CsvRecord {
-    #[serde(rename = "ACCOUNT")]
+    #[serde(rename(deserialize = "ACCOUNT", serialize = "account"))]
     pub account: String,
-    #[serde(rename = "ID")]
+    #[serde(rename(deserialize = "ID", serialize = "tx_id"))]
     pub id: u64,
-    #[serde(rename = "Date", deserialize_with = "parse_date_time")]
+    #[serde(
+        rename(deserialize = "Date", serialize = "tx_date"),
+        deserialize_with = "parse_date_time"
+    )]
     pub date: DateTime<FixedOffset>,
-    #[serde(rename = "Amount", deserialize_with = "deserialize_money")]
+    /// the date the transaction posted to the account, when the source has one
+    #[serde(
+        rename(deserialize = "Posted", serialize = "posted_date"),
+        default,
+        deserialize_with = "parse_optional_date_time"
+    )]
+    pub posted_date: Option<DateTime<FixedOffset>>,
+    #[serde(
+        rename(deserialize = "Amount", serialize = "amount"),
+        deserialize_with = "deserialize_amount",
+        serialize_with = "serialize_display"
+    )]
     pub amount: Currency,
-    #[serde(rename = "Balance", deserialize_with = "deserialize_money")]
+    #[serde(
+        rename(deserialize = "Balance", serialize = "balance"),
+        deserialize_with = "deserialize_money",
+        serialize_with = "serialize_display"
+    )]
     pub balance: Currency,
-    #[serde(rename = "Vendor")]
+    #[serde(rename(deserialize = "Vendor", serialize = "vendor"))]
     pub vendor: String,
-    #[serde(rename = "Digits")]
+    #[serde(rename(deserialize = "Digits", serialize = "digits"))]
     pub digits: Option<String>,
-    #[serde(rename = "Type")]
+    #[serde(rename(deserialize = "Type", serialize = "transaction_type"))]
     pub transaction_type: String,
-    #[serde(rename = "Category")]
+    #[serde(rename(deserialize = "Category", serialize = "category"))]
     pub category: Option<String>,
-    #[serde(rename = "Subcategory")]
+    #[serde(rename(deserialize = "Subcategory", serialize = "subcategory"))]
     pub subcategory: Option<String>,
-    #[serde(rename = "Notes")]
+    #[serde(rename(deserialize = "Notes", serialize = "notes"))]
     pub notes: Option<String>,
-    #[serde(rename = "Income", deserialize_with = "parse_bool")]
+    #[serde(
+        rename(deserialize = "Income", serialize = "is_income"),
+        default,
+        deserialize_with = "parse_bool"
+    )]
     pub income: bool,
-    #[serde(rename = "Fixed", deserialize_with = "parse_bool")]
+    #[serde(
+        rename(deserialize = "Fixed", serialize = "is_fixed"),
+        default,
+        deserialize_with = "parse_bool"
+    )]
     pub fixed: bool,
-    #[serde(rename = "Spend", deserialize_with = "parse_bool")]
+    #[serde(
+        rename(deserialize = "Spend", serialize = "is_spend"),
+        default,
+        deserialize_with = "parse_bool"
+    )]
     pub spend: bool,
+    #[serde(
+        rename(deserialize = "Percent", serialize = "percentage"),
+        default,
+        deserialize_with = "parse_percentage",
+        serialize_with = "serialize_percentage"
+    )]
+    pub percentage: Option<Percentage>,
+    /// the ISO 4217 code of the currency of the amounts, if known
+    #[serde(rename(deserialize = "Currency", serialize = "currency_code"), default)]
+    pub currency_code: Option<String>,
+    /// the id of the category and subcategory in the categories table, with
+    /// `--normalize-categories`; set before insert, never read
+    #[serde(skip)]
+    pub category_id: Option<i32>,
+    /// the path or archive entry name of the file the row was read from, with
+    /// `--store-source-position`
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    /// the 1-based line of the file the row starts on, counting any preamble before the header
+    #[serde(skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u64>,
+}
+
+/// A percentage or ratio, stored as a ratio; i.e. `1.5%` is stored as `0.015`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Percentage {
+    value: i64,
+    scale: u32,
+}
+
+/// The header name a configured percent column is mapped to
+pub const PERCENT_HEADER: &str = "Percent";
+/// The header name a configured currency column is mapped to
+pub const CURRENCY_HEADER: &str = "Currency";
+/// The header name of the date column
+pub const DATE_HEADER: &str = "Date";
+/// The header name of the optional posted date column
+pub const POSTED_DATE_HEADER: &str = "Posted";
+/// The header name of the id column
+pub const ID_HEADER: &str = "ID";
+/// The header name of the account column
+pub const ACCOUNT_HEADER: &str = "ACCOUNT";
+/// The header name of the balance column
+pub const BALANCE_HEADER: &str = "Balance";
+/// The header names of the fields every row must have
+pub const REQUIRED_HEADERS: [&str; 7] = [
+    ACCOUNT_HEADER,
+    ID_HEADER,
+    DATE_HEADER,
+    "Amount",
+    BALANCE_HEADER,
+    "Vendor",
+    "Type",
+];
+/// The account of the rows of a single ledger, keyed by id alone
+pub const LEDGER_ACCOUNT: &str = "ledger";
+/// The placeholder for the account in a file name pattern
+pub const ACCOUNT_PLACEHOLDER: &str = "{account}";
+/// The format of dates, unless a dialect says otherwise
+pub const DEFAULT_DATE_FORMAT: &str = "%m/%d/%Y";
+/// The header names of every field of a `CsvRecord`, which source columns may be mapped to
+pub const HEADERS: [&str; 17] = [
+    ACCOUNT_HEADER,
+    ID_HEADER,
+    DATE_HEADER,
+    POSTED_DATE_HEADER,
+    "Amount",
+    BALANCE_HEADER,
+    "Vendor",
+    "Digits",
+    "Type",
+    "Category",
+    "Subcategory",
+    "Notes",
+    "Income",
+    "Fixed",
+    "Spend",
+    PERCENT_HEADER,
+    CURRENCY_HEADER,
+];
+
+/// Options applied by `parse_date_time`
+#[derive(Debug, Clone, Default)]
+pub struct DateOptions {
+    /// the date applied to rows without a parseable date
+    pub fallback: Option<DateTime<FixedOffset>>,
+    /// the chrono format of the dates, if not `DEFAULT_DATE_FORMAT`
+    pub format: Option<String>,
+    /// the offset of dates without one of their own, if not UTC
+    pub offset: Option<FixedOffset>,
+}
+
+impl DateOptions {
+    pub fn get_offset(&self) -> FixedOffset {
+        self.offset.unwrap_or_else(|| FixedOffset::east(0))
+    }
+}
+
+thread_local! {
+    static DATE_OPTIONS: RefCell<DateOptions> = RefCell::new(DateOptions::default());
+    static ZONE: Cell<Option<Tz>> = Cell::new(None);
+}
+
+/// runs `f` with the given options applied to all date deserialization on this thread; a zone
+/// set by `f` is cleared once it returns
+pub fn with_date_options<T, F: FnOnce() -> T>(options: DateOptions, f: F) -> T {
+    let previous = DATE_OPTIONS.with(|o| o.replace(options));
+    let result = f();
+    DATE_OPTIONS.with(|o| o.replace(previous));
+    ZONE.with(|z| z.set(None));
+    result
+}
+
+/// sets the time zone of dates without an offset of their own, such as the zone of the account of
+/// the row about to be deserialized on this thread; it takes precedence over the offset of the
+/// date options
+pub fn set_zone(zone: Option<Tz>) {
+    ZONE.with(|z| z.set(zone));
+}
+
+/// the synthetic id of the record, with `--id-from hash`: SipHash-1-3 with a fixed zero key, of
+/// the account, date (`YYYY-MM-DD`), amount (e.g. `-12.34`), vendor, and type, each followed by
+/// a unit separator. The whole 64-bit hash is the id, which never changes across runs or
+/// machines.
+pub fn synthetic_id(record: &CsvRecord) -> u64 {
+    let mut hasher = SipHasher13::new_with_keys(0, 0);
+    for field in [
+        record.account.as_str(),
+        &record.date.format("%Y-%m-%d").to_string(),
+        &record.amount.to_string(),
+        &record.vendor,
+        &record.transaction_type,
+    ] {
+        hasher.write(field.as_bytes());
+        hasher.write_u8(0x1f);
+    }
+    hasher.finish()
+}
+
+/// extracts a date from the file name (without extension) of the path, using a chrono format
+/// pattern such as `statement-%Y-%m`. If the pattern has no day, the first of the month is used.
+pub fn date_from_filename(
+    path: &Path,
+    pattern: &str,
+    offset: FixedOffset,
+) -> Option<DateTime<FixedOffset>> {
+    let stem = path.file_stem()?.to_str()?;
+
+    let parsed = if pattern.contains("%d") {
+        NaiveDate::parse_from_str(stem, pattern)
+    } else {
+        NaiveDate::parse_from_str(&format!("{}-01", stem), &format!("{}-%d", pattern))
+    };
+
+    parsed
+        .ok()
+        .and_then(|d| offset.from_local_datetime(&d.and_hms(0, 0, 0)).single())
+}
+
+/// extracts the account from the file name (without extension) of the path, using a pattern such
+/// as `{account}-statement-*`, where `*` matches any text. The shortest matching account is used.
+pub fn account_from_filename(path: &Path, pattern: &str) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let (before, after) = pattern.split_once(ACCOUNT_PLACEHOLDER)?;
+
+    let boundaries = stem
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(stem.len()))
+        .collect::<Vec<_>>();
+
+    for (n, &start) in boundaries.iter().enumerate() {
+        if !glob_matches(before, &stem[..start]) {
+            continue;
+        }
+        for &end in &boundaries[n + 1..] {
+            if glob_matches(after, &stem[end..]) {
+                return Some(stem[start..end].to_string());
+            }
+        }
+    }
+
+    None
+}
+
+/// whether the text matches the pattern, where `*` matches any text
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((prefix, rest)) => {
+            text.starts_with(prefix)
+                && text[prefix.len()..]
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(text.len() - prefix.len()))
+                    .any(|i| glob_matches(rest, &text[prefix.len() + i..]))
+        }
+    }
+}
+
+/// What to do with rows dated outside of a sane range, which usually means the date was parsed
+/// with the wrong format or column
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBoundsPolicy {
+    Off,
+    Warn,
+    Reject,
+}
+
+/// What to do with source columns that no field of a record is read from, which usually means the
+/// column map is missing an entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmappedColumnsPolicy {
+    Ignore,
+    /// the columns are listed at info level
+    Report,
+    /// the file is failed
+    Reject,
+}
+
+impl Default for UnmappedColumnsPolicy {
+    fn default() -> Self {
+        Self::Ignore
+    }
+}
+
+/// Which row's stated balance the balances of the other rows of its account are recomputed from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BalanceAnchor {
+    /// the first row of each account in a file, in row order
+    First,
+}
+
+/// whether the date is between 1970-01-01 and a day from now
+pub fn within_date_bounds(date: &DateTime<FixedOffset>) -> bool {
+    let min = Utc.ymd(1970, 1, 1).and_hms(0, 0, 0);
+    let max = Utc::now() + chrono::Duration::days(1);
 
+    let date = date.with_timezone(&Utc);
+    date >= min && date <= max
+}
+
+/// Which date of a row drives its ordering and resumption, for sources with both a transaction
+/// and a posted date
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrimaryDate {
+    Transaction,
+    Posted,
+}
+
+impl PrimaryDate {
+    /// the primary date of the row; a row without a posted date falls back to its transaction date
+    pub fn of(&self, row: &CsvRecord) -> DateTime<FixedOffset> {
+        match self {
+            PrimaryDate::Transaction => row.date,
+            PrimaryDate::Posted => row.posted_date.unwrap_or(row.date),
+        }
+    }
 }
 
 #[derive(Copy, Clone)]
 pub enum LoadOptions {
     All,
+    /// rows with ids greater than the max id of their account are imported
     New,
+    /// rows after the last row (in file order) already in the table are imported, for feeds
+    /// whose ids don't follow the order of the transactions
+    NewInFileOrder,
+}
+
+impl CsvRecord {
+    /// the text values of the record, by column name
+    pub fn text_columns_mut(&mut self) -> [(&'static str, Option<&mut String>); 8] {
+        [
+            ("account", Some(&mut self.account)),
+            ("vendor", Some(&mut self.vendor)),
+            ("digits", self.digits.as_mut()),
+            ("transaction_type", Some(&mut self.transaction_type)),
+            ("category", self.category.as_mut()),
+            ("subcategory", self.subcategory.as_mut()),
+            ("notes", self.notes.as_mut()),
+            ("currency_code", self.currency_code.as_mut()),
+        ]
+    }
+
+    /// starts a record of the account, id, and date, for records built in memory rather than
+    /// read from a csv file; amounts and balances default to zero, and the other columns to
+    /// blank
+    pub fn builder(account: &str, id: u64, date: DateTime<FixedOffset>) -> CsvRecordBuilder {
+        CsvRecordBuilder {
+            record: CsvRecord {
+                account: account.to_string(),
+                id,
+                date,
+                posted_date: None,
+                amount: Currency::from_minor_units(0),
+                balance: Currency::from_minor_units(0),
+                vendor: String::new(),
+                digits: None,
+                transaction_type: String::new(),
+                category: None,
+                subcategory: None,
+                notes: None,
+                income: false,
+                fixed: false,
+                spend: false,
+                percentage: None,
+                currency_code: None,
+                category_id: None,
+                source_file: None,
+                source_line: None,
+            },
+        }
+    }
+}
+
+/// Builds a `CsvRecord` column by column, see `CsvRecord::builder`
+#[derive(Debug, Clone)]
+pub struct CsvRecordBuilder {
+    record: CsvRecord,
+}
+
+impl CsvRecordBuilder {
+    pub fn posted_date(mut self, posted_date: DateTime<FixedOffset>) -> Self {
+        self.record.posted_date = Some(posted_date);
+        self
+    }
+
+    pub fn amount(mut self, amount: Currency) -> Self {
+        self.record.amount = amount;
+        self
+    }
+
+    pub fn balance(mut self, balance: Currency) -> Self {
+        self.record.balance = balance;
+        self
+    }
+
+    pub fn vendor(mut self, vendor: &str) -> Self {
+        self.record.vendor = vendor.to_string();
+        self
+    }
+
+    pub fn digits(mut self, digits: &str) -> Self {
+        self.record.digits = Some(digits.to_string());
+        self
+    }
+
+    pub fn transaction_type(mut self, transaction_type: &str) -> Self {
+        self.record.transaction_type = transaction_type.to_string();
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.record.category = Some(category.to_string());
+        self
+    }
+
+    pub fn subcategory(mut self, subcategory: &str) -> Self {
+        self.record.subcategory = Some(subcategory.to_string());
+        self
+    }
+
+    pub fn notes(mut self, notes: &str) -> Self {
+        self.record.notes = Some(notes.to_string());
+        self
+    }
+
+    /// sets the income, fixed, and spend flags
+    pub fn flags(mut self, income: bool, fixed: bool, spend: bool) -> Self {
+        self.record.income = income;
+        self.record.fixed = fixed;
+        self.record.spend = spend;
+        self
+    }
+
+    pub fn percentage(mut self, percentage: Percentage) -> Self {
+        self.record.percentage = Some(percentage);
+        self
+    }
+
+    /// the ISO 4217 code of the currency of the amounts
+    pub fn currency_code(mut self, currency_code: &str) -> Self {
+        self.record.currency_code = Some(currency_code.to_string());
+        self
+    }
+
+    pub fn build(self) -> CsvRecord {
+        self.record
+    }
 }
 
 impl fmt::Display for CsvRecord {
@@ -50,28 +472,167 @@ impl fmt::Display for CsvRecord {
         write!(f, "(Account: {}", self.account)?;
         write!(f, ", Id: {}", self.id)?;
         write!(f, ", Date: {}", self.date)?;
+        if let Some(posted_date) = &self.posted_date {
+            write!(f, ", Posted: {}", posted_date)?;
+        }
         write!(f, ", Amount: {}", self.amount)?;
         write!(f, ", Balance: {}", self.balance)?;
         write!(f, ", Vendor: {}", self.vendor)?;
         write!(f, ", Digits: {:?}", self.digits)?;
         write!(f, ", Type: {}", self.transaction_type)?;
-        write!(f, ", Category: {:?})", &self.category)?;
-        write!(f, ", Subcategory: {:?})", &self.subcategory)?;
-        write!(f, ", Notes: {:?})", &self.notes)
+        write!(f, ", Category: {:?}", &self.category)?;
+        write!(f, ", Subcategory: {:?}", &self.subcategory)?;
+        write!(f, ", Notes: {:?}", &self.notes)?;
+        write!(f, ", Income: {}", self.income)?;
+        write!(f, ", Fixed: {}", self.fixed)?;
+        write!(f, ", Spend: {}", self.spend)?;
+        if let Some(percentage) = &self.percentage {
+            write!(f, ", Percent: {}", percentage)?;
+        }
+        if let Some(currency_code) = &self.currency_code {
+            write!(f, ", Currency: {}", currency_code)?;
+        }
+        write!(f, ")")
     }
 }
 
+impl fmt::Display for Percentage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.value < 0 { "-" } else { "" };
+        let digits = format!(
+            "{:0width$}",
+            self.value.unsigned_abs(),
+            width = self.scale as usize + 1
+        );
+        let (whole, fraction) = digits.split_at(digits.len() - self.scale as usize);
+
+        if fraction.is_empty() {
+            write!(f, "{}{}", sign, whole)
+        } else {
+            write!(f, "{}{}.{}", sign, whole, fraction)
+        }
+    }
+}
+
+impl FromStr for Percentage {
+    type Err = ParsePercentageError;
+
+    /// parses "1.5%", "1,5%" (decimal comma), and plain ratios such as "0.015"
+    fn from_str(from: &str) -> Result<Self, Self::Err> {
+        let trimmed = from.trim();
+        let (number, percent) = match trimmed.strip_suffix('%') {
+            Some(n) => (n.trim(), true),
+            None => (trimmed, false),
+        };
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(n) => (true, n),
+            None => (false, number),
+        };
+
+        let normalized = number.replace(',', ".");
+        let (whole_chars, fraction_chars) = normalized.split_once('.').unwrap_or((&normalized, ""));
+
+        if (whole_chars.is_empty() && fraction_chars.is_empty())
+            || !whole_chars.chars().all(|c| c.is_ascii_digit())
+            || !fraction_chars.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(ParsePercentageError::new(from));
+        }
+
+        let mut value = 0i64;
+        for c in whole_chars.chars().chain(fraction_chars.chars()) {
+            let digit = c.to_digit(10).unwrap_or(0) as i64;
+            value = value
+                .checked_mul(10)
+                .and_then(|v| v.checked_add(digit))
+                .ok_or_else(|| ParsePercentageError::new(from))?;
+        }
+
+        let mut scale = fraction_chars.len() as u32;
+        if percent {
+            scale += 2;
+        }
+        if negative {
+            value = -value;
+        }
+
+        Ok(Percentage { value, scale })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsePercentageError {
+    source: String,
+}
+
+impl ParsePercentageError {
+    fn new(s: &str) -> Self {
+        ParsePercentageError {
+            source: s.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParsePercentageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Could not parse {} into a percentage.", self.source)
+    }
+}
+
+impl std::error::Error for ParsePercentageError {
+    fn description(&self) -> &str {
+        "Failed to parse percentage"
+    }
+}
+
+/// parses the flags used by various banks; a blank value is false
 fn parse_bool<'de, D>(d: D) -> std::result::Result<bool, D::Error>
-    where
-        D: Deserializer<'de>,
+where
+    D: Deserializer<'de>,
+{
+    let v = String::deserialize(d)?.trim().to_lowercase();
+
+    match v.as_str() {
+        "true" | "yes" | "y" | "1" => Ok(true),
+        "false" | "no" | "n" | "0" | "" => Ok(false),
+        _ => Err(serde::de::Error::invalid_value(
+            Unexpected::Str(&v),
+            &"true|false|yes|no|y|n|1|0",
+        )),
+    }
+}
+
+fn parse_percentage<'de, D>(d: D) -> std::result::Result<Option<Percentage>, D::Error>
+where
+    D: Deserializer<'de>,
 {
-    let v =
-    String::deserialize(d)?
-        .to_lowercase();
+    let buf = String::deserialize(d)?;
+    if buf.trim().is_empty() {
+        return Ok(None);
+    }
+
+    Percentage::from_str(&buf)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
 
-    v
-        .parse()
-        .map_err(|_|serde::de::Error::invalid_value(Unexpected::Str(&v), &"true|false"))
+/// serializes decimal values as strings, so that no precision is lost to floats
+fn serialize_display<T, S>(value: &T, s: S) -> std::result::Result<S::Ok, S::Error>
+where
+    T: fmt::Display,
+    S: Serializer,
+{
+    s.collect_str(value)
+}
+
+fn serialize_percentage<S>(value: &Option<Percentage>, s: S) -> std::result::Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match value {
+        Some(percentage) => s.collect_str(percentage),
+        None => s.serialize_none(),
+    }
 }
 
 fn parse_date_time<'de, D>(d: D) -> std::result::Result<DateTime<FixedOffset>, D::Error>
@@ -80,7 +641,173 @@ where
 {
     let buf = String::deserialize(d)?;
 
-    let formatted = format!("{}  00:00:00 +00:00", buf);
+    DATE_OPTIONS.with(|o| {
+        let options = o.borrow();
+        let format = options.format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT);
+
+        parse_in_zone(&buf, format, &options)
+            .or_else(|e| options.fallback.ok_or_else(|| serde::de::Error::custom(e)))
+    })
+}
+
+/// parses an optional date like `parse_date_time`; a blank date is absent, and there is no fallback
+fn parse_optional_date_time<'de, D>(
+    d: D,
+) -> std::result::Result<Option<DateTime<FixedOffset>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let buf = String::deserialize(d)?;
+    if buf.trim().is_empty() {
+        return Ok(None);
+    }
+
+    DATE_OPTIONS.with(|o| {
+        let options = o.borrow();
+        let format = options.format.as_deref().unwrap_or(DEFAULT_DATE_FORMAT);
+
+        parse_in_zone(buf.trim(), format, &options)
+            .map(Some)
+            .map_err(serde::de::Error::custom)
+    })
+}
+
+/// parses a date in the zone set for the row, if any, or else the offset of the options
+fn parse_in_zone(
+    buf: &str,
+    format: &str,
+    options: &DateOptions,
+) -> chrono::ParseResult<DateTime<FixedOffset>> {
+    match ZONE.with(|z| z.get()) {
+        Some(zone) => parse_date(buf, format, &zone),
+        None => parse_date(buf, format, &options.get_offset()),
+    }
+}
+
+/// parses a date in the chrono format. A format without a time is midnight, and a format without
+/// an offset (`%z`) is in the given zone; a local time skipped by a daylight saving change takes
+/// the offset before the change.
+pub fn parse_date<Z: TimeZone>(
+    buf: &str,
+    format: &str,
+    zone: &Z,
+) -> chrono::ParseResult<DateTime<FixedOffset>> {
+    let mut parsed = Parsed::new();
+    chrono::format::parse(&mut parsed, buf, StrftimeItems::new(format))?;
+
+    let date = parsed.to_naive_date()?;
+    let time = match parsed.to_naive_time() {
+        Ok(time) => time,
+        Err(_) if parsed.hour_div_12.is_none() => NaiveTime::from_hms(0, 0, 0),
+        Err(e) => return Err(e),
+    };
+    let local = date.and_time(time);
+    let offset = match parsed.offset {
+        Some(_) => parsed.to_fixed_offset()?,
+        None => zone
+            .from_local_datetime(&local)
+            .earliest()
+            .map(|d| d.offset().fix())
+            .unwrap_or_else(|| zone.offset_from_utc_datetime(&local).fix()),
+    };
+
+    // a fixed offset maps every local time to exactly one instant
+    Ok(offset.from_local_datetime(&local).unwrap())
+}
+
+/// parses a fixed offset such as `+02:00`, `-0530`, or `UTC`
+pub fn parse_offset(s: &str) -> Option<FixedOffset> {
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Some(FixedOffset::east(0));
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = s[1..].replacen(':', "", 1);
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours = digits[..2].parse::<i32>().ok()?;
+    let minutes = digits[2..].parse::<i32>().ok()?;
+    if minutes >= 60 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// parses the percentage, and formats it back as a ratio
+    fn ratio(from: &str) -> String {
+        Percentage::from_str(from).unwrap().to_string()
+    }
+
+    #[test]
+    fn percentage_forms_are_the_same_ratio() {
+        assert_eq!(ratio("1.5%"), "0.015");
+        assert_eq!(ratio("1,5%"), "0.015");
+        assert_eq!(ratio("0.015"), "0.015");
+        assert_eq!(ratio(" 1.5 % "), "0.015");
+        assert_eq!(ratio("-2%"), "-0.02");
+    }
+
+    #[test]
+    fn malformed_percentages_are_rejected() {
+        for from in &["", "%", "1.5.0%", "abc", "1x%"] {
+            assert!(Percentage::from_str(from).is_err(), "{:?}", from);
+        }
+    }
+
+    #[test]
+    fn date_comes_from_the_file_name() {
+        let utc = FixedOffset::east(0);
+        let date = |path: &str, pattern: &str| date_from_filename(Path::new(path), pattern, utc);
+
+        assert_eq!(
+            date("statements/statement-2023-03.csv", "statement-%Y-%m"),
+            Some(utc.ymd(2023, 3, 1).and_hms(0, 0, 0))
+        );
+        assert_eq!(
+            date("statement-2023-03-15.csv", "statement-%Y-%m-%d"),
+            Some(utc.ymd(2023, 3, 15).and_hms(0, 0, 0))
+        );
+        assert_eq!(date("january.csv", "statement-%Y-%m"), None);
+    }
+
+    #[test]
+    fn dates_far_from_now_are_out_of_bounds() {
+        let utc = FixedOffset::east(0);
+        assert!(within_date_bounds(&utc.ymd(2021, 1, 31).and_hms(0, 0, 0)));
+        assert!(within_date_bounds(&utc.ymd(1970, 1, 1).and_hms(0, 0, 0)));
+        assert!(!within_date_bounds(&utc.ymd(9999, 12, 31).and_hms(0, 0, 0)));
+        assert!(!within_date_bounds(&utc.ymd(1969, 12, 31).and_hms(0, 0, 0)));
+    }
+
+    #[test]
+    fn dates_without_an_offset_take_the_given_one() {
+        let plus_two = parse_offset("+02:00").unwrap();
+        assert_eq!(parse_offset("+0200"), Some(plus_two));
+        assert_eq!(parse_offset("UTC"), Some(FixedOffset::east(0)));
+        assert_eq!(parse_offset("02:00"), None);
 
-    DateTime::parse_from_str(&formatted, "%m/%d/%Y %H:%M:%S %z").map_err(serde::de::Error::custom)
+        // a date alone is midnight in the offset, two hours before midnight UTC
+        let date = parse_date("01/31/2021", DEFAULT_DATE_FORMAT, &plus_two).unwrap();
+        assert_eq!(date.to_rfc3339(), "2021-01-31T00:00:00+02:00");
+        assert_eq!(
+            date.with_timezone(&Utc).to_rfc3339(),
+            "2021-01-30T22:00:00+00:00"
+        );
+        let utc = parse_date("01/31/2021", DEFAULT_DATE_FORMAT, &FixedOffset::east(0)).unwrap();
+        assert_eq!(utc.to_rfc3339(), "2021-01-31T00:00:00+00:00");
+
+        // times are kept, and an offset of the date's own wins over the given one
+        let date = parse_date("2021-01-31 13:45", "%Y-%m-%d %H:%M", &plus_two).unwrap();
+        assert_eq!(date.to_rfc3339(), "2021-01-31T13:45:00+02:00");
+        let date = parse_date("2021-01-31 13:45 -0500", "%Y-%m-%d %H:%M %z", &plus_two).unwrap();
+        assert_eq!(date.to_rfc3339(), "2021-01-31T13:45:00-05:00");
+    }
 }
@@ -0,0 +1,45 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+
+use log::error;
+
+use crate::domain::CsvRecord;
+
+/// An event of an import, passed to the observers of the `Importer`
+#[derive(Debug)]
+pub enum ImportEvent<'a> {
+    /// the rows of a csv file (or archive entry) begin loading
+    FileStarted { source: &'a Path },
+    /// a row of the file was parsed into a record
+    RowParsed { record: &'a CsvRecord },
+    /// rows were committed to the table; a chunk, or several with `--commit-every`. `last` is
+    /// the last committed row, in the order the rows were given.
+    ChunkCommitted {
+        table_name: &'a str,
+        rows: usize,
+        last: Option<&'a CsvRecord>,
+    },
+    /// a row could not be inserted
+    RowFailed { record: &'a CsvRecord },
+}
+
+/// Receives the events of an import, e.g. to drive a progress bar or custom logging
+pub trait ImportObserver: Send + Sync {
+    fn on_event(&self, event: &ImportEvent);
+}
+
+impl<F: Fn(&ImportEvent) + Send + Sync> ImportObserver for F {
+    fn on_event(&self, event: &ImportEvent) {
+        self(event)
+    }
+}
+
+/// passes the event to each observer; an observer that panics is logged, and doesn't fail the
+/// import
+pub fn emit(observers: &[Box<dyn ImportObserver>], event: &ImportEvent) {
+    for observer in observers {
+        if panic::catch_unwind(AssertUnwindSafe(|| observer.on_event(event))).is_err() {
+            error!("An import observer panicked on {:?}", event);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use std::error::Error;
+
+use crate::config::ConfigError;
+use crate::db::DatabaseError;
+use crate::report::ImportReport;
+
+/// The exit code of a run, for scripts reacting to its outcome
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitCode {
+    Success = 0,
+    /// any error not covered by another code
+    Failure = 1,
+    /// invalid arguments or configuration, including a missing or mismatched table
+    Config = 2,
+    /// the database could not be connected to
+    Connection = 3,
+    /// the import completed, but some rows were rejected or failed to insert
+    Partial = 4,
+    /// the import completed, but stored totals didn't match the rows read; or a self-test failed,
+    /// or the table has duplicate keys
+    Verification = 5,
+    /// the import stopped early, once `--max-runtime` was exceeded
+    TimedOut = 6,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// the exit code of a run that completed with the report
+    pub fn of_report(report: &ImportReport) -> Self {
+        if report.timed_out {
+            ExitCode::TimedOut
+        } else if report.totals_mismatched > 0 {
+            ExitCode::Verification
+        } else if report.failed > 0 || report.rows_rejected > 0 {
+            ExitCode::Partial
+        } else {
+            ExitCode::Success
+        }
+    }
+
+    /// the exit code of a run that failed with the error
+    pub fn of_error(e: &(dyn Error + 'static)) -> Self {
+        if e.is::<ConfigError>() {
+            return ExitCode::Config;
+        }
+        match e.downcast_ref::<DatabaseError>() {
+            Some(DatabaseError::ConnectionError) => return ExitCode::Connection,
+            Some(DatabaseError::TableNotFound(_)) | Some(DatabaseError::SchemaMismatch(..)) => {
+                return ExitCode::Config
+            }
+            None => (),
+        }
+        match e.downcast_ref::<sqlx::Error>() {
+            Some(e) if is_connection_error(e) => ExitCode::Connection,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+/// whether the error means the database couldn't be reached or logged into: the connection
+/// exception (08), invalid authorization (28), and invalid catalog name (3D) classes of
+/// SQLSTATE codes
+fn is_connection_error(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Configuration(_)
+        | sqlx::Error::Io(_)
+        | sqlx::Error::Tls(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed => true,
+        sqlx::Error::Database(e) => e.code().map_or(false, |code| {
+            ["08", "28", "3D"].iter().any(|c| code.starts_with(c))
+        }),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_of<E: Error + 'static>(e: E) -> ExitCode {
+        ExitCode::of_error(&e)
+    }
+
+    #[test]
+    fn each_outcome_has_its_code() {
+        assert_eq!(
+            ExitCode::of_report(&ImportReport::default()),
+            ExitCode::Success
+        );
+        assert_eq!(
+            code_of(ConfigError::FileNotFound("missing.csv".to_string())),
+            ExitCode::Config
+        );
+        assert_eq!(
+            code_of(DatabaseError::TableNotFound("transactions".to_string())),
+            ExitCode::Config
+        );
+        assert_eq!(
+            code_of(DatabaseError::ConnectionError),
+            ExitCode::Connection
+        );
+        assert_eq!(code_of(sqlx::Error::PoolTimedOut), ExitCode::Connection);
+        assert_eq!(code_of(sqlx::Error::RowNotFound), ExitCode::Failure);
+
+        let partial = ImportReport {
+            rows_rejected: 1,
+            ..ImportReport::default()
+        };
+        assert_eq!(ExitCode::of_report(&partial), ExitCode::Partial);
+        let mismatched = ImportReport {
+            failed: 1,
+            totals_mismatched: 1,
+            ..ImportReport::default()
+        };
+        assert_eq!(ExitCode::of_report(&mismatched), ExitCode::Verification);
+        let timed_out = ImportReport {
+            timed_out: true,
+            ..mismatched
+        };
+        assert_eq!(ExitCode::of_report(&timed_out), ExitCode::TimedOut);
+
+        assert_eq!(
+            [ExitCode::Success, ExitCode::Config, ExitCode::Connection]
+                .iter()
+                .map(|c| c.code())
+                .collect::<Vec<_>>(),
+            [0, 2, 3]
+        );
+        assert_eq!(
+            (ExitCode::Partial.code(), ExitCode::Verification.code()),
+            (4, 5)
+        );
+    }
+}
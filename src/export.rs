@@ -0,0 +1,229 @@
+use std::io::Write;
+
+use serde_json::{Map, Value};
+
+use crate::domain::CsvRecord;
+use crate::Result;
+
+/// The columns of exported records, in order; named like the columns of the table
+const COLUMNS: [&str; 17] = [
+    "account",
+    "tx_id",
+    "tx_date",
+    "posted_date",
+    "amount",
+    "balance",
+    "vendor",
+    "digits",
+    "transaction_type",
+    "category",
+    "subcategory",
+    "notes",
+    "is_income",
+    "is_fixed",
+    "is_spend",
+    "percentage",
+    "currency_code",
+];
+
+/// How records are written, with `--export`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// How the income, fixed, and spend flags are written, with `--export-bool`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BoolVocabulary {
+    TrueFalse,
+    YesNo,
+    OneZero,
+}
+
+impl Default for BoolVocabulary {
+    fn default() -> Self {
+        BoolVocabulary::TrueFalse
+    }
+}
+
+impl BoolVocabulary {
+    /// the flag as written; json keeps true and false as booleans
+    fn value(self, flag: bool, format: ExportFormat) -> Value {
+        match (self, flag) {
+            (BoolVocabulary::TrueFalse, _) if format == ExportFormat::Json => Value::Bool(flag),
+            (BoolVocabulary::TrueFalse, _) => Value::String(flag.to_string()),
+            (BoolVocabulary::YesNo, true) => Value::String("yes".to_string()),
+            (BoolVocabulary::YesNo, false) => Value::String("no".to_string()),
+            (BoolVocabulary::OneZero, true) => Value::String("1".to_string()),
+            (BoolVocabulary::OneZero, false) => Value::String("0".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    /// how missing values are written, with `--export-null`; otherwise blank in csv, and null in
+    /// json
+    pub null: Option<String>,
+    pub bools: BoolVocabulary,
+}
+
+impl ExportOptions {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            null: None,
+            bools: BoolVocabulary::default(),
+        }
+    }
+
+    /// the value written in place of a missing value
+    fn null_value(&self) -> Value {
+        match (&self.null, self.format) {
+            (Some(token), _) => Value::String(token.clone()),
+            (None, ExportFormat::Csv) => Value::String(String::new()),
+            (None, ExportFormat::Json) => Value::Null,
+        }
+    }
+
+    /// the columns of the record, in order, as written
+    fn values(&self, record: &CsvRecord) -> Result<Vec<(&'static str, Value)>> {
+        let mut serialized = match serde_json::to_value(record)? {
+            Value::Object(map) => map,
+            _ => Map::new(),
+        };
+
+        Ok(COLUMNS
+            .iter()
+            .map(|column| match serialized.remove(*column) {
+                Some(Value::Null) | None => (*column, self.null_value()),
+                Some(Value::Bool(flag)) => (*column, self.bools.value(flag, self.format)),
+                Some(value) => (*column, value),
+            })
+            .collect())
+    }
+}
+
+/// writes the records as csv, with a header row, or as a json array of objects
+pub fn write_records<'a, W: Write>(
+    records: impl Iterator<Item = &'a CsvRecord>,
+    options: &ExportOptions,
+    out: W,
+) -> Result<()> {
+    match options.format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            writer.write_record(&COLUMNS)?;
+            for record in records {
+                writer.write_record(options.values(record)?.iter().map(|(_, v)| text(v)))?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Json => {
+            let objects = records
+                .map(|r| {
+                    Ok(Value::Object(
+                        options
+                            .values(r)?
+                            .into_iter()
+                            .map(|(c, v)| (c.to_string(), v))
+                            .collect(),
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            serde_json::to_writer_pretty(out, &objects)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// the value as a csv field; strings are written without quotes
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exported(records: &[CsvRecord], options: &ExportOptions) -> String {
+        let mut out = Vec::new();
+        write_records(records.iter(), options, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    /// a record without a posted date, digits, category, notes, or percentage
+    fn record() -> CsvRecord {
+        let csv = concat!(
+            "ACCOUNT,ID,Date,Amount,Balance,Vendor,Type\n",
+            "BOFA,1,01/31/2021,0.00,0.00,Grocer,Debit\n"
+        );
+        csv::Reader::from_reader(csv.as_bytes())
+            .deserialize()
+            .next()
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn missing_values_are_written_as_the_null_token() {
+        let options = ExportOptions {
+            null: Some("NULL".to_string()),
+            ..ExportOptions::new(ExportFormat::Csv)
+        };
+        let csv = exported(&[record()], &options);
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.starts_with("BOFA,1,"));
+        assert!(row.contains(",NULL,0.00,0.00,Grocer,NULL,Debit,NULL,NULL,NULL,"));
+        assert!(row.ends_with(",NULL"));
+
+        let options = ExportOptions {
+            null: Some("NULL".to_string()),
+            ..ExportOptions::new(ExportFormat::Json)
+        };
+        assert!(exported(&[record()], &options).contains("\"notes\": \"NULL\""));
+    }
+
+    #[test]
+    fn missing_values_default_to_blank_and_null() {
+        let csv = exported(&[record()], &ExportOptions::new(ExportFormat::Csv));
+        assert!(csv.lines().nth(1).unwrap().contains(",Debit,,,,"));
+
+        let json = exported(&[record()], &ExportOptions::new(ExportFormat::Json));
+        assert!(json.contains("\"notes\": null"));
+    }
+
+    #[test]
+    fn flags_are_written_in_the_vocabulary() {
+        let mut income = record();
+        income.income = true;
+
+        for (bools, yes, no) in &[
+            (BoolVocabulary::TrueFalse, "true", "false"),
+            (BoolVocabulary::YesNo, "yes", "no"),
+            (BoolVocabulary::OneZero, "1", "0"),
+        ] {
+            let options = ExportOptions {
+                bools: *bools,
+                ..ExportOptions::new(ExportFormat::Csv)
+            };
+            let csv = exported(&[income.clone()], &options);
+            let flags = format!(",{},{},{},", yes, no, no);
+            assert!(csv.lines().nth(1).unwrap().contains(&flags), "{}", csv);
+        }
+
+        let options = ExportOptions {
+            bools: BoolVocabulary::YesNo,
+            ..ExportOptions::new(ExportFormat::Json)
+        };
+        assert!(exported(&[income.clone()], &options).contains("\"is_income\": \"yes\""));
+        let json = exported(&[income], &ExportOptions::new(ExportFormat::Json));
+        assert!(json.contains("\"is_income\": true"));
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Serialize, Serializer};
+
+use crate::currency::Currency;
+use crate::domain::CsvRecord;
+
+/// The category of rows without one
+const UNCATEGORIZED: &str = "(uncategorized)";
+
+/// How the group report is printed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupReportFormat {
+    Table,
+    Json,
+}
+
+/// The number and net amount of a group of rows
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GroupTotals {
+    pub rows: usize,
+    /// the sum of the amounts, in hundredths
+    #[serde(serialize_with = "serialize_minor_units")]
+    pub net: i64,
+}
+
+/// The rows loaded into the table, grouped by category and by month, as a quick spending overview
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct GroupReport {
+    pub by_category: BTreeMap<String, GroupTotals>,
+    /// keyed by `YYYY-MM`
+    pub by_month: BTreeMap<String, GroupTotals>,
+}
+
+impl GroupReport {
+    pub fn record(&mut self, record: &CsvRecord) {
+        let category = record.category.as_deref().unwrap_or(UNCATEGORIZED);
+        let month = record.date.format("%Y-%m").to_string();
+        let amount = record.amount.to_minor_units();
+
+        for totals in [
+            self.by_category.entry(category.to_string()).or_default(),
+            self.by_month.entry(month).or_default(),
+        ] {
+            totals.rows += 1;
+            totals.net += amount;
+        }
+    }
+}
+
+impl fmt::Display for GroupReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (heading, groups) in [("Category", &self.by_category), ("Month", &self.by_month)] {
+            writeln!(f, "{:<32} {:>8} {:>16}", heading, "Rows", "Net")?;
+            for (group, totals) in groups {
+                writeln!(
+                    f,
+                    "{:<32} {:>8} {:>16}",
+                    group,
+                    totals.rows,
+                    Currency::from_minor_units(totals.net)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// hundredths are serialized as a decimal string, such as `-12.34`
+fn serialize_minor_units<S: Serializer>(units: &i64, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(&Currency::from_minor_units(*units))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    fn record(month: u32, amount: &str, category: Option<&str>) -> CsvRecord {
+        let date = FixedOffset::east(0).ymd(2021, month, 15).and_hms(0, 0, 0);
+        let mut record = CsvRecord::builder("BOFA", 1, date)
+            .amount(amount.parse().unwrap())
+            .build();
+        record.category = category.map(|c| c.to_string());
+        record
+    }
+
+    #[test]
+    fn rows_are_totaled_by_category_and_month() {
+        let mut report = GroupReport::default();
+        for row in &[
+            record(1, "-10.00", Some("Groceries")),
+            record(1, "-2.50", Some("Dining")),
+            record(2, "-5.25", Some("Groceries")),
+            record(2, "100.00", None),
+        ] {
+            report.record(row);
+        }
+
+        let totals = |rows, net| GroupTotals { rows, net };
+        assert_eq!(report.by_category["Groceries"], totals(2, -1525));
+        assert_eq!(report.by_category["Dining"], totals(1, -250));
+        assert_eq!(report.by_category[UNCATEGORIZED], totals(1, 10000));
+        assert_eq!(report.by_month["2021-01"], totals(2, -1250));
+        assert_eq!(report.by_month["2021-02"], totals(2, 9475));
+        assert_eq!(report.by_month.len(), 2);
+    }
+}
@@ -0,0 +1,4084 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
+use csv::ReaderBuilder;
+use itertools::Itertools;
+use log::{debug, error, info, warn};
+
+use crate::checkpoint::Checkpoint;
+use crate::config::{self, Config, ConfigError, CsvConfig, Source};
+use crate::currency::{self, Currency, NullAmountPolicy, OverflowPolicy, UnknownSignPolicy};
+use crate::db::{DatabaseError, HookScope, InsertOutcome, PgPool, TableSchema, TooLongPolicy};
+use crate::dialect::{self, Encoding};
+use crate::domain::{
+    self, BalanceAnchor, CsvRecord, DateBoundsPolicy, DateOptions, LoadOptions, PrimaryDate,
+    UnmappedColumnsPolicy, ACCOUNT_HEADER, BALANCE_HEADER, CURRENCY_HEADER, DATE_HEADER, HEADERS,
+    ID_HEADER, PERCENT_HEADER, REQUIRED_HEADERS,
+};
+use crate::events::{self, ImportEvent, ImportObserver};
+use crate::export::{self, ExportOptions};
+use crate::groups::GroupReport;
+use crate::manifest::ManifestEntry;
+use crate::normalize::VendorCase;
+use crate::report::{DroppedDuplicate, ImportReport, NewRowsPreview, ParseBenchmark};
+use crate::state::StateFile;
+use crate::store::TransactionStore;
+use crate::Result;
+
+/// Imports csv sources into the database, according to the configuration
+pub struct Importer {
+    config: Config,
+    store: TransactionStore,
+    /// receives the same inserts as the store, e.g. a reporting replica
+    mirror: Option<TransactionStore>,
+    schema: Option<TableSchema>,
+    output_schema: Option<TableSchema>,
+    /// the running balance of each account, when deriving balances; carried from file to file
+    balances: Mutex<HashMap<String, Currency>>,
+    observers: Vec<Box<dyn ImportObserver>>,
+    /// the rows loaded into the table, by category and month
+    groups: Mutex<GroupReport>,
+    /// when rows were last imported into the table, with `--since-last-run`
+    last_run: Option<DateTime<Utc>>,
+    /// the rows dropped as duplicates, with `--dedup-report`
+    dropped: Mutex<Vec<DroppedDuplicate>>,
+    /// whether each account seen had rows in the table before the run, with
+    /// `--skip-existing-accounts`; each account is only selected once
+    existing_accounts: Mutex<HashMap<String, bool>>,
+    /// the ids of the (category, subcategory) pairs in the categories table, with
+    /// `--normalize-categories`; each pair is only upserted once
+    category_ids: Mutex<HashMap<(String, String), i32>>,
+    /// the lock of each (table, account), held from selecting where the account resumes until
+    /// its rows are inserted, so that concurrent loads of an account resume one after another
+    account_locks: Mutex<HashMap<(String, String), Arc<tokio::sync::Mutex<()>>>>,
+    /// when the first import started; `--max-runtime` counts from it, so that it covers the whole
+    /// import
+    started: Mutex<Option<Instant>>,
+}
+
+/// The (account, id) keys of the rows read so far in a run, with the file each was first read
+/// from
+type SeenKeys = HashMap<(String, u64), String>;
+
+/// The records parsed from a single csv source
+struct ParsedFile {
+    records: Vec<CsvRecord>,
+    /// the number of rows that could not be parsed
+    rejected: usize,
+    /// the number of rows with an amount that lost precision
+    precision_lost: usize,
+    /// the number of rows with a blank or unparseable amount imported as zero
+    amount_zeroed: usize,
+    /// whether the source has no balance column, so balances are derived from opening balances
+    derive_balances: bool,
+}
+
+/// The rows of an account to import when resuming, and those skipped as already imported
+struct Resumption<'a> {
+    to_import: Vec<&'a CsvRecord>,
+    skipped: Vec<&'a CsvRecord>,
+    /// where the import resumes, if the account has rows in the table
+    after: Option<String>,
+}
+
+/// The rows of a source that were not read as is
+#[derive(Default)]
+struct RowCounts {
+    bad: usize,
+    skipped: usize,
+    precision_lost: usize,
+    amount_zeroed: usize,
+}
+
+impl Importer {
+    pub fn new(config: Config, pool: PgPool) -> Self {
+        Self::with_store(config, TransactionStore::Postgres(pool))
+    }
+
+    pub fn with_store(config: Config, store: TransactionStore) -> Self {
+        Self {
+            config,
+            store,
+            mirror: None,
+            schema: None,
+            output_schema: None,
+            balances: Mutex::new(HashMap::new()),
+            observers: Vec::new(),
+            groups: Mutex::new(GroupReport::default()),
+            last_run: None,
+            dropped: Mutex::new(Vec::new()),
+            existing_accounts: Mutex::new(HashMap::new()),
+            category_ids: Mutex::new(HashMap::new()),
+            account_locks: Mutex::new(HashMap::new()),
+            started: Mutex::new(None),
+        }
+    }
+
+    /// mirrors every insert into the store; unless the mirror is required, failures of the mirror
+    /// are logged, but don't fail the import
+    pub fn with_mirror(mut self, mirror: TransactionStore) -> Self {
+        self.mirror = Some(mirror);
+        self
+    }
+
+    /// notifies the observer of the events of every import; see `ImportEvent`
+    pub fn with_observer(mut self, observer: Box<dyn ImportObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// the rows loaded into the table so far, by category and month
+    pub fn group_report(&self) -> GroupReport {
+        self.groups.lock().unwrap().clone()
+    }
+
+    /// the rows dropped as duplicates so far, when they are reported
+    pub fn dropped_duplicates(&self) -> Vec<DroppedDuplicate> {
+        self.dropped.lock().unwrap().clone()
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn store(&self) -> &TransactionStore {
+        &self.store
+    }
+
+    /// reads the columns of the target (and output) table, so that inserts only include the
+    /// columns that actually exist. Until loaded, or if the columns can't be found, all columns
+    /// are inserted. Fails if a table does not exist, rather than failing every insert. When
+    /// rows are routed by date, the tables are only known per row, and all columns are inserted.
+    pub async fn load_schema(&mut self) -> Result<()> {
+        if self.config.database.get_routing().is_none() {
+            let table_name = self.config.database.get_table_name();
+            self.schema = self.select_schema(&table_name).await?;
+        }
+
+        if let Some(output_table) = self.config.database.get_output_table() {
+            self.output_schema = self.select_schema(output_table).await?;
+        }
+
+        Ok(())
+    }
+
+    /// with `--load-then-swap`, creates the empty staging table rows are loaded into, like the
+    /// target table. Must be created before the schema is loaded.
+    pub async fn create_staging(&self) -> Result<()> {
+        if let Some(target) = self.config.database.get_swap_target() {
+            let staging = self.config.database.get_table_name();
+            info!(
+                "Loading into {}, which replaces {} once loaded.",
+                staging, target
+            );
+            self.store.create_staging(target, &staging).await?;
+        }
+        Ok(())
+    }
+
+    /// with `--load-then-swap`, replaces the target table with the staging table if the import
+    /// completed without failed rows, or else drops the staging table, leaving the target as it
+    /// was
+    async fn swap_staging(&self, imported: Result<ImportReport>) -> Result<ImportReport> {
+        let target = match self.config.database.get_swap_target() {
+            Some(target) => target,
+            None => return imported,
+        };
+        let staging = self.config.database.get_table_name();
+
+        match &imported {
+            Ok(report)
+                if report.failed == 0 && !report.timed_out && report.totals_mismatched == 0 =>
+            {
+                self.store.swap_tables(target, &staging).await?;
+                info!("Replaced {} with {}.", target, staging);
+            }
+            _ => {
+                warn!("The import did not complete; {} is unchanged.", target);
+                if let Err(e) = self.store.drop_table(&staging).await {
+                    error!("Could not drop {}: {}", staging, e);
+                }
+            }
+        }
+        imported
+    }
+
+    /// with `--since-last-run`, reads when rows were last imported into the table; rows dated
+    /// at or before then are skipped. Must be loaded before importing, since the import itself
+    /// updates the table.
+    pub async fn load_last_run(&mut self) -> Result<()> {
+        if !self.config.since_last_run {
+            return Ok(());
+        }
+
+        let table_name = self.config.database.get_table_name();
+        self.last_run = self.store.select_last_import(&table_name).await?;
+        match self.last_run {
+            Some(last_run) => info!(
+                "Importing rows dated after the last import into {}, at {}.",
+                table_name, last_run
+            ),
+            None => info!(
+                "Nothing was imported into {} yet; importing every row.",
+                table_name
+            ),
+        }
+        Ok(())
+    }
+
+    async fn select_schema(&self, table_name: &str) -> Result<Option<TableSchema>> {
+        if !self.store.table_exists(table_name).await? {
+            return Err(Box::new(DatabaseError::table_not_found(table_name)));
+        }
+
+        let schema = self.store.select_table_schema(table_name).await?;
+
+        if self.config.validate_schema {
+            let mismatches = schema.mismatches(
+                self.config.csv.get_percent_column().is_some(),
+                self.config.database.get_categories_table().is_some(),
+                self.config.database.is_store_source_position(),
+                self.config.database.is_jsonb(),
+            );
+            if !mismatches.is_empty() {
+                return Err(Box::new(DatabaseError::schema_mismatch(
+                    table_name, mismatches,
+                )));
+            }
+        }
+
+        if schema.columns().is_empty() {
+            warn!(
+                "Could not find the columns of table {}; all columns will be inserted.",
+                table_name
+            );
+            return Ok(None);
+        }
+
+        for column in schema.missing_columns(self.config.database.is_jsonb()) {
+            warn!(
+                "Table {} has no {} column; it will not be imported.",
+                table_name, column
+            );
+        }
+        Ok(Some(schema))
+    }
+
+    /// imports the configured source. With `--hook-scope run`, the before hook runs first, and
+    /// the after hook runs last, even if the import fails.
+    pub async fn import(&self) -> Result<ImportReport> {
+        self.start_run();
+        let database = &self.config.database;
+        let run_scope = database.get_hook_scope() == HookScope::Run;
+        if let (Some(sql), true) = (database.get_before_sql(), run_scope) {
+            info!("Running the before hook.");
+            self.store.execute_hook(sql).await?;
+        }
+
+        let imported = match &self.config.source {
+            Some(Source::File(f)) => self.import_file(f).await,
+            Some(Source::Directory(dir)) => self.import_directory(dir).await,
+            Some(Source::Archive(f)) => self.import_archive(f).await,
+            Some(Source::Manifest(entries)) => self.import_manifest(entries).await,
+            None => Err(Box::new(ConfigError::required_configuration_missing(
+                "file or directory",
+            ))),
+        };
+        let imported = self.swap_staging(imported).await;
+
+        if let (Some(sql), true) = (database.get_after_sql(), run_scope) {
+            info!("Running the after hook.");
+            match (self.store.execute_hook(sql).await, &imported) {
+                (Err(e), Ok(_)) => return Err(Box::new(e)),
+                (Err(e), Err(_)) => error!("The after hook failed too: {}", e),
+                (Ok(()), _) => (),
+            }
+        }
+        imported
+    }
+
+    /// imports every csv file in the directory, in file name order. With a state file, files
+    /// completed by a previous run are skipped, and each file is recorded once it completes
+    /// without failed rows. Files are parsed in parallel off the runtime, while the rows of
+    /// earlier files are loaded in file order; `max_in_flight_rows` caps the rows parsed but not
+    /// yet loaded.
+    pub async fn import_directory(&self, f: &Path) -> Result<ImportReport> {
+        self.start_run();
+        let start = Instant::now();
+        let mut report = ImportReport::default();
+        let mut seen = SeenKeys::new();
+        let mut state = match &self.config.state_file {
+            Some(path) => Some(StateFile::load(path)?),
+            None => None,
+        };
+
+        let mut paths = config::csv_files(f)?;
+        paths.retain(|path| {
+            let completed = state.as_ref().map_or(false, |s| s.is_completed(path));
+            if completed {
+                info!("Skipping file {:?}, completed by a previous run.", path);
+            }
+            !completed
+        });
+
+        let threads = self.config.threads;
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()?;
+
+        // files are parsed off the runtime, ahead of the rows being loaded
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(threads);
+        let csv_config = Arc::new(self.config.csv.clone());
+        let budget = self
+            .config
+            .max_in_flight_rows
+            .map(|max| Arc::new(RowBudget::new(max)));
+        let parser = tokio::task::spawn_blocking(move || {
+            parse_files(paths, csv_config, pool, budget, sender)
+        });
+
+        while let Some(file) = receiver.recv().await {
+            if self.stop_for_deadline(&mut report) {
+                break;
+            }
+            let path = file.path;
+            let parsed = file.parsed.await.map_err(|e| e.to_string());
+            let loaded = match parsed.and_then(|parsed| parsed) {
+                Ok(parsed) => self.load_rows(&path, parsed, &mut seen).await,
+                Err(e) => Err(e.into()),
+            };
+
+            match loaded {
+                Ok(file_report) => {
+                    // a file cut short by the deadline is not complete
+                    if let (Some(state), 0, false) =
+                        (state.as_mut(), file_report.failed, file_report.timed_out)
+                    {
+                        state.record_completed(&path)?;
+                    }
+                    report.merge(file_report)
+                }
+                Err(e) => error!("Could not import file {:?}: {}", path, e),
+            }
+        }
+        drop(receiver);
+        parser.await?;
+
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    /// imports each csv entry within a zip archive, as if the archive were a directory
+    pub async fn import_archive(&self, f: &Path) -> Result<ImportReport> {
+        self.start_run();
+        let start = Instant::now();
+        let mut report = ImportReport::default();
+        let mut seen = SeenKeys::new();
+
+        let entries = match read_archive(f, &self.config.csv) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Could not read zip archive. Aborting");
+                return Err(e);
+            }
+        };
+
+        for (name, parsed) in entries {
+            if self.stop_for_deadline(&mut report) {
+                break;
+            }
+            info!("Importing archive entry {}", name);
+            match self.load_rows(Path::new(&name), parsed, &mut seen).await {
+                Ok(entry_report) => report.merge(entry_report),
+                Err(e) => error!("Could not import archive entry {}: {}", name, e),
+            }
+        }
+
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    /// imports exactly the files listed by a manifest, in the order listed, each read with the
+    /// overrides of its entry
+    pub async fn import_manifest(&self, entries: &[ManifestEntry]) -> Result<ImportReport> {
+        self.start_run();
+        let start = Instant::now();
+        let mut report = ImportReport::default();
+        let mut seen = SeenKeys::new();
+
+        for entry in entries {
+            if self.stop_for_deadline(&mut report) {
+                break;
+            }
+            let path = entry.get_path();
+            let loaded = match read_file(path, &self.config.csv.for_manifest_entry(entry)) {
+                Ok(parsed) => self.load_rows(path, parsed, &mut seen).await,
+                Err(e) => Err(e),
+            };
+
+            match loaded {
+                Ok(file_report) => report.merge(file_report),
+                Err(e) => error!("Could not import file {:?}: {}", path, e),
+            }
+        }
+
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    /// starts counting the maximum runtime; only the first start counts, as an import may start
+    /// the imports of its files in turn
+    fn start_run(&self) {
+        let mut started = self.started.lock().unwrap();
+        if started.is_none() {
+            *started = Some(Instant::now());
+        }
+    }
+
+    /// the time after which no further files or chunks are started, if the runtime is limited
+    fn deadline(&self) -> Option<Instant> {
+        let started = *self.started.lock().unwrap();
+        started
+            .zip(self.config.database.get_max_runtime())
+            .map(|(started, max)| started + max)
+    }
+
+    /// whether to stop before starting another file, because `--max-runtime` was exceeded; the
+    /// report is marked as stopped early
+    fn stop_for_deadline(&self, report: &mut ImportReport) -> bool {
+        if self.deadline().map_or(true, |d| Instant::now() < d) {
+            return false;
+        }
+        if !report.timed_out {
+            warn!("The maximum runtime was exceeded; not starting the remaining files.");
+        }
+        report.timed_out = true;
+        true
+    }
+
+    /// counts the rows of the configured source that are new to the table, and those already
+    /// stored, with a single query of their keys; nothing is imported. Rows sharing a key are
+    /// counted once.
+    pub async fn preview_new(&self) -> Result<NewRowsPreview> {
+        let source = match &self.config.source {
+            Some(source) => source,
+            None => {
+                return Err(Box::new(ConfigError::required_configuration_missing(
+                    "file or directory",
+                )))
+            }
+        };
+
+        let keys = read_source(source, &self.config.csv)?
+            .into_iter()
+            .flat_map(|parsed| parsed.records)
+            .map(|r| (r.account, r.id as i64))
+            .unique()
+            .collect::<Vec<_>>();
+        let existing = self
+            .store
+            .select_existing_keys(&keys, &self.config.database.get_table_name())
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let mut preview = NewRowsPreview::default();
+        for key in &keys {
+            preview.record(&key.0, existing.contains(key));
+        }
+        Ok(preview)
+    }
+
+    pub async fn import_file(&self, f: &Path) -> Result<ImportReport> {
+        self.start_run();
+        self.load_file(f, &mut SeenKeys::new()).await
+    }
+
+    /// imports records built in memory, e.g. with `CsvRecord::builder`, like the rows of a file;
+    /// `source` names the records in logs, events, and checkpoints, in place of a file path. The
+    /// records are loaded as given: the options of reading csv files, such as default accounts
+    /// and currency codes, don't apply, while normalization and the load options do.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "memory")]
+    /// # fn main() -> csv_importer::Result<()> {
+    /// use std::path::Path;
+    ///
+    /// use chrono::{FixedOffset, TimeZone};
+    /// use csv_importer::config::Config;
+    /// use csv_importer::currency::Currency;
+    /// use csv_importer::domain::CsvRecord;
+    /// use csv_importer::memory::MemoryStore;
+    /// use csv_importer::store::TransactionStore;
+    /// use csv_importer::Importer;
+    ///
+    /// let date = FixedOffset::east(0).ymd(2021, 1, 31).and_hms(0, 0, 0);
+    /// let records = vec![CsvRecord::builder("BOFA", 1, date)
+    ///     .amount(Currency::new(-12, 34))
+    ///     .balance(Currency::from_minor_units(98_766))
+    ///     .vendor("Grocer")
+    ///     .transaction_type("Debit")
+    ///     .build()];
+    ///
+    /// let store = TransactionStore::Memory(MemoryStore::default());
+    /// let importer = Importer::with_store(Config::default(), store);
+    /// let runtime = tokio::runtime::Runtime::new()?;
+    /// let report = runtime.block_on(importer.import_records(Path::new("in-memory"), records))?;
+    /// assert_eq!(report.inserted, 1);
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(feature = "memory"))]
+    /// # fn main() {}
+    /// ```
+    pub async fn import_records(
+        &self,
+        source: &Path,
+        records: Vec<CsvRecord>,
+    ) -> Result<ImportReport> {
+        self.start_run();
+        let start = Instant::now();
+        let parsed = ParsedFile {
+            records,
+            rejected: 0,
+            precision_lost: 0,
+            amount_zeroed: 0,
+            derive_balances: false,
+        };
+
+        let mut report = self.load_rows(source, parsed, &mut SeenKeys::new()).await?;
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    async fn load_file(&self, f: &Path, seen: &mut SeenKeys) -> Result<ImportReport> {
+        let start = Instant::now();
+
+        let parsed = match read_file(f, &self.config.csv) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                error!("Could not read csv file. Aborting");
+                return Err(e);
+            }
+        };
+
+        let mut report = self.load_rows(f, parsed, seen).await?;
+        report.duration = start.elapsed();
+        Ok(report)
+    }
+
+    /// loads the parsed rows; with `--deduplicate-across-files`, rows whose key was already seen
+    /// in this run are counted as duplicates and not loaded again
+    async fn load_rows(
+        &self,
+        source: &Path,
+        parsed: ParsedFile,
+        seen: &mut SeenKeys,
+    ) -> Result<ImportReport> {
+        let config = &self.config;
+        let table_name = config.database.get_table_name();
+        let mut rows = parsed.records;
+
+        if config.require_unique_ids {
+            let duplicates = duplicate_keys(&rows);
+            if !duplicates.is_empty() {
+                return Err(format!(
+                    "{:?} has rows with duplicate ids: {}",
+                    source,
+                    duplicates.join(", ")
+                )
+                .into());
+            }
+        }
+
+        events::emit(&self.observers, &ImportEvent::FileStarted { source });
+        for record in &rows {
+            events::emit(&self.observers, &ImportEvent::RowParsed { record });
+        }
+
+        let mut report = ImportReport {
+            files: 1,
+            rows_rejected: parsed.rejected,
+            rows_precision_lost: parsed.precision_lost,
+            rows_amount_zeroed: parsed.amount_zeroed,
+            ..ImportReport::default()
+        };
+
+        match config.date_bounds {
+            DateBoundsPolicy::Off => (),
+            DateBoundsPolicy::Warn => rows
+                .iter()
+                .filter(|r| !domain::within_date_bounds(&r.date))
+                .for_each(|r| {
+                    warn!(
+                        "Row {}/{} has an unlikely date: {}",
+                        r.account, r.id, r.date
+                    )
+                }),
+            DateBoundsPolicy::Reject => rows.retain(|r| {
+                let within = domain::within_date_bounds(&r.date);
+                if !within {
+                    warn!(
+                        "Rejecting row {}/{} with an unlikely date: {}",
+                        r.account, r.id, r.date
+                    );
+                    report.rows_rejected += 1;
+                }
+                within
+            }),
+        }
+
+        if let Some(last_run) = self.last_run {
+            rows.retain(|r| {
+                let date = config.primary_date.map_or(r.date, |p| p.of(r));
+                let after = date.with_timezone(&Utc) > last_run;
+                if !after {
+                    report.record_read(&r.account);
+                    report.record_skipped(&r.account);
+                }
+                after
+            });
+        }
+
+        if config.skip_existing_accounts {
+            let existing = self.existing_accounts(&rows, &table_name).await?;
+            rows.retain(|r| {
+                let new = !existing.contains(&r.account);
+                if !new {
+                    report.record_read(&r.account);
+                    report.record_skipped(&r.account);
+                }
+                new
+            });
+        }
+
+        // amounts are signed as read, before balances are derived from them, and in both the
+        // table and any output table
+        if let Some(type_signs) = &config.type_signs {
+            rows.iter_mut()
+                .for_each(|r| r.amount = type_signs.apply(&r.amount, &r.transaction_type));
+        }
+
+        if parsed.derive_balances {
+            self.derive_balances(&mut rows, &mut report);
+        } else if let Some(BalanceAnchor::First) = config.balance_anchor {
+            self.anchor_balances(&mut rows, source, &mut report);
+        }
+
+        if !config.vendor_contains.is_empty() {
+            rows.retain(|r| {
+                let vendor = r.vendor.to_lowercase();
+                let matched = config.vendor_contains.iter().any(|s| vendor.contains(s));
+                if !matched {
+                    report.record_read(&r.account);
+                    report.record_skipped(&r.account);
+                }
+                matched
+            });
+        }
+
+        // tailing after deriving balances keeps the balances of the tail running from the rows
+        // before it
+        if let Some(n) = config.tail {
+            let start = rows.len().saturating_sub(n);
+            for r in rows.drain(..start) {
+                report.record_read(&r.account);
+                report.record_skipped(&r.account);
+            }
+        }
+
+        // with an output table, the table keeps the rows as read, and a cleaned copy of the rows
+        // is loaded into the output table; otherwise, the rows are cleaned in place
+        let mut cleaned = config.database.get_output_table().map(|_| rows.clone());
+        let mut output = ImportReport::default();
+
+        self.normalize(cleaned.as_mut().unwrap_or(&mut rows));
+
+        if let Some(masker) = &config.mask {
+            rows.iter_mut()
+                .chain(cleaned.iter_mut().flatten())
+                .for_each(|r| masker.apply(r));
+        }
+
+        rows.iter().for_each(|r| report.record_read(&r.account));
+        cleaned
+            .iter()
+            .flatten()
+            .for_each(|r| output.record_read(&r.account));
+
+        let mut dropped = Vec::new();
+        if config.deduplicate {
+            dropped.extend(match cleaned.as_mut() {
+                Some(cleaned) => deduplicate(cleaned, source, seen, &mut output),
+                None => deduplicate(&mut rows, source, seen, &mut report),
+            });
+        }
+
+        if let Some(days) = config.dedupe_window {
+            dropped.extend(match cleaned.as_mut() {
+                Some(cleaned) => deduplicate_window(cleaned, source, days, &mut output),
+                None => deduplicate_window(&mut rows, source, days, &mut report),
+            });
+        }
+
+        if config.dedup_report.is_some() {
+            self.dropped.lock().unwrap().extend(dropped);
+        }
+
+        if let Some(categories_table) = config.database.get_categories_table() {
+            self.resolve_categories(&mut rows, categories_table).await?;
+            if let Some(cleaned) = cleaned.as_mut() {
+                self.resolve_categories(cleaned, categories_table).await?;
+            }
+        }
+
+        if config.sort_rows {
+            sort_rows(&mut rows, config.primary_date);
+            if let Some(cleaned) = cleaned.as_mut() {
+                sort_rows(cleaned, config.primary_date);
+            }
+        }
+
+        enforce_lengths(
+            &mut rows,
+            self.schema.as_ref(),
+            config.on_too_long,
+            &mut report,
+        );
+        if let Some(path) = &config.checkpoint {
+            skip_to_checkpoint(&mut rows, source, path, &mut report)?;
+        }
+        // the JSONB layout has no columns to compare the stored rows by
+        if config.csv.is_id_from_hash() && !config.database.is_jsonb() {
+            self.reject_stored_collisions(&mut rows, &table_name, source, &mut report)
+                .await?;
+        }
+        match config.database.get_routing() {
+            Some(_) => {
+                let tables = rows
+                    .iter()
+                    .cloned()
+                    .into_group_map_by(|r| self.table_of(r, &table_name));
+                for (table_name, table_rows) in tables.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+                    debug!("Routing {} row(s) to {}", table_rows.len(), table_name);
+                    self.load_into(&table_rows, &table_name, None, &mut report)
+                        .await?;
+                }
+            }
+            None => {
+                self.load_into(&rows, &table_name, self.schema.as_ref(), &mut report)
+                    .await?
+            }
+        }
+
+        if config.verify_totals {
+            self.verify_totals(&rows, &table_name, &mut report).await?;
+        }
+
+        if let (Some(output_table), Some(mut cleaned)) =
+            (config.database.get_output_table(), cleaned)
+        {
+            enforce_lengths(
+                &mut cleaned,
+                self.output_schema.as_ref(),
+                config.on_too_long,
+                &mut output,
+            );
+            self.load_into(
+                &cleaned,
+                output_table,
+                self.output_schema.as_ref(),
+                &mut output,
+            )
+            .await?;
+            report.output = Some(Box::new(output));
+        }
+
+        // a completed file starts from scratch next time
+        if let (Some(path), 0, false) = (&config.checkpoint, report.failed, report.timed_out) {
+            Checkpoint::remove(path)?;
+        }
+
+        Ok(report)
+    }
+
+    /// sets the category id of each row with a category, upserting the pairs of category and
+    /// subcategory not yet seen in this run into the categories table
+    async fn resolve_categories(
+        &self,
+        rows: &mut [CsvRecord],
+        categories_table: &str,
+    ) -> Result<()> {
+        let pair = |r: &CsvRecord| {
+            r.category
+                .clone()
+                .map(|c| (c, r.subcategory.clone().unwrap_or_default()))
+        };
+
+        let unknown = {
+            let known = self.category_ids.lock().unwrap();
+            rows.iter()
+                .filter_map(pair)
+                .filter(|p| !known.contains_key(p))
+                .unique()
+                .collect::<Vec<_>>()
+        };
+        if !unknown.is_empty() {
+            let ids = self
+                .store
+                .upsert_categories(&unknown, categories_table)
+                .await?;
+            let mut known = self.category_ids.lock().unwrap();
+            known.extend(ids.into_iter().map(|(c, s, id)| ((c, s), id)));
+        }
+
+        let known = self.category_ids.lock().unwrap();
+        for r in rows.iter_mut() {
+            r.category_id = pair(r).and_then(|p| known.get(&p).copied());
+        }
+        Ok(())
+    }
+
+    /// the accounts of the rows that had rows in the table before the run. Accounts are
+    /// selected the first time they are seen, so an account new to the table stays new for
+    /// every file of the run.
+    async fn existing_accounts(
+        &self,
+        rows: &[CsvRecord],
+        table_name: &str,
+    ) -> Result<HashSet<String>> {
+        let unknown = {
+            let known = self.existing_accounts.lock().unwrap();
+            rows.iter()
+                .map(|r| r.account.as_str())
+                .filter(|account| !known.contains_key(*account))
+                .unique()
+                .map(|account| account.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        for account in unknown {
+            let max = self
+                .store
+                .select_max_tx_for_account(&account, table_name)
+                .await?;
+            if max.is_some() {
+                info!(
+                    "Skipping the rows of account {}, which already has rows.",
+                    account
+                );
+            }
+            self.existing_accounts
+                .lock()
+                .unwrap()
+                .insert(account, max.is_some());
+        }
+
+        let known = self.existing_accounts.lock().unwrap();
+        Ok(known
+            .iter()
+            .filter(|(_, existing)| **existing)
+            .map(|(account, _)| account.clone())
+            .collect())
+    }
+
+    /// recomputes the balance of each row from the stated balance of the first row of its
+    /// account, plus the amounts of the rows after it, in the same order as derived balances.
+    /// Stated balances that differ from the recomputed ones are logged and counted, and replaced.
+    /// Rows that would overflow the running balance are rejected.
+    fn anchor_balances(&self, rows: &mut Vec<CsvRecord>, source: &Path, report: &mut ImportReport) {
+        let mut order = (0..rows.len()).collect::<Vec<_>>();
+        if self.config.sort_rows {
+            let primary = self.config.primary_date;
+            order.sort_by(|a, b| row_order(&rows[*a], &rows[*b], primary));
+        }
+
+        let mut balances: HashMap<String, Currency> = HashMap::new();
+        let mut overflowed = HashSet::new();
+        for i in order {
+            let row = &mut rows[i];
+            let balance = match balances.entry(row.account.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(row.balance.clone());
+                    continue;
+                }
+            };
+            let expected = match balance.checked_add(&row.amount) {
+                Some(sum) => sum,
+                None => {
+                    warn!(
+                        "Rejecting row {}/{}: the running balance of the account overflows.",
+                        row.account, row.id
+                    );
+                    overflowed.insert(i);
+                    continue;
+                }
+            };
+            *balance = expected.clone();
+
+            if row.balance != expected {
+                warn!(
+                    "Row {}/{} of {:?} states a balance of {}, but {} follows from the anchor.",
+                    row.account, row.id, source, row.balance, expected
+                );
+                report.rows_balance_diverged += 1;
+                row.balance = expected;
+            }
+        }
+
+        let mut i = 0;
+        rows.retain(|_| {
+            let keep = !overflowed.contains(&i);
+            i += 1;
+            keep
+        });
+        report.rows_rejected += overflowed.len();
+    }
+
+    /// sets the balance of each row to the running balance of its account: the opening balance
+    /// of the account, plus the amounts of the rows so far. Rows are taken in account and id
+    /// order, unless rows are not sorted. Rows of accounts without an opening balance, and rows
+    /// that would overflow the running balance, are rejected.
+    fn derive_balances(&self, rows: &mut Vec<CsvRecord>, report: &mut ImportReport) {
+        let openings = self.config.csv.get_opening_balances();
+        let mut balances = self.balances.lock().unwrap();
+
+        let before = rows.len();
+        rows.retain(|r| {
+            let known = openings.iter().any(|(account, _)| *account == r.account);
+            if !known {
+                warn!(
+                    "Rejecting row {}/{}: no balance, and no opening balance to derive it from.",
+                    r.account, r.id
+                );
+            }
+            known
+        });
+        report.rows_rejected += before - rows.len();
+
+        let mut order = (0..rows.len()).collect::<Vec<_>>();
+        if self.config.sort_rows {
+            let primary = self.config.primary_date;
+            order.sort_by(|a, b| row_order(&rows[*a], &rows[*b], primary));
+        }
+
+        let mut overflowed = HashSet::new();
+        for i in order {
+            let row = &mut rows[i];
+            let balance = balances.entry(row.account.clone()).or_insert_with(|| {
+                openings
+                    .iter()
+                    .find(|(account, _)| *account == row.account)
+                    .map_or(Currency::from_minor_units(0), |(_, opening)| {
+                        opening.clone()
+                    })
+            });
+            match balance.checked_add(&row.amount) {
+                Some(sum) => {
+                    *balance = sum.clone();
+                    row.balance = sum;
+                }
+                None => {
+                    warn!(
+                        "Rejecting row {}/{}: the running balance of the account overflows.",
+                        row.account, row.id
+                    );
+                    overflowed.insert(i);
+                }
+            }
+        }
+
+        let mut i = 0;
+        rows.retain(|_| {
+            let keep = !overflowed.contains(&i);
+            i += 1;
+            keep
+        });
+        report.rows_rejected += overflowed.len();
+    }
+
+    /// canonicalizes the values of the rows, per the configuration
+    fn normalize(&self, rows: &mut [CsvRecord]) {
+        let config = &self.config;
+
+        if let Some(form) = config.unicode_form {
+            for r in rows.iter_mut() {
+                for (_, value) in r.text_columns_mut() {
+                    if let Some(value) = value {
+                        *value = form.apply(value);
+                    }
+                }
+            }
+        }
+
+        if config.vendor_case != VendorCase::None {
+            rows.iter_mut()
+                .for_each(|r| r.vendor = config.vendor_case.apply(&r.vendor));
+        }
+
+        if let Some(type_map) = &config.type_map {
+            rows.iter_mut()
+                .for_each(|r| r.transaction_type = type_map.apply(&r.transaction_type));
+        }
+
+        for transform in &config.transforms {
+            rows.iter_mut().for_each(|r| transform.apply(r));
+        }
+    }
+
+    /// loads the rows into the table, according to the load options
+    async fn load_into(
+        &self,
+        rows: &[CsvRecord],
+        table_name: &str,
+        schema: Option<&TableSchema>,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        match self.config.load_options {
+            LoadOptions::All => {
+                let refs = rows.iter().collect::<Vec<_>>();
+                report.merge(self.insert(&refs, table_name, schema).await?)
+            }
+            LoadOptions::New => {
+                self.load_new_rows(rows, table_name, schema, false, report)
+                    .await?
+            }
+            LoadOptions::NewInFileOrder => {
+                self.load_new_rows(rows, table_name, schema, true, report)
+                    .await?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// inserts the rows into the store, and the mirror, if any. The mirror may have a different
+    /// schema, so all columns are inserted into it. Rows the mirror fails to insert are counted,
+    /// like failed rows of the store; a failure of the mirror as a whole only fails the import if
+    /// the mirror is required.
+    async fn insert(
+        &self,
+        rows: &[&CsvRecord],
+        table_name: &str,
+        schema: Option<&TableSchema>,
+    ) -> Result<ImportReport> {
+        let mut report = self
+            .store
+            .import(
+                rows,
+                table_name,
+                &self.config.database,
+                schema,
+                &self.observers,
+                self.deadline(),
+            )
+            .await?;
+
+        if Some(table_name) != self.config.database.get_output_table() {
+            let mut groups = self.groups.lock().unwrap();
+            rows.iter().for_each(|r| groups.record(r));
+        }
+
+        let mirror = match &self.mirror {
+            Some(mirror) => mirror,
+            None => return Ok(report),
+        };
+
+        match mirror
+            .import(
+                rows,
+                table_name,
+                &self.config.database,
+                None,
+                &[],
+                self.deadline(),
+            )
+            .await
+        {
+            Ok(mirrored) => report.mirror_failed += mirrored.failed,
+            Err(e) if self.config.mirror_required => return Err(Box::new(e)),
+            Err(e) => {
+                error!("Could not mirror {} row(s): {}", rows.len(), e);
+                report.mirror_failed += rows.len();
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// the table the row is imported into: routed by date when configured, otherwise the table
+    /// of the import
+    fn table_of(&self, r: &CsvRecord, table_name: &str) -> String {
+        match self.config.database.get_routing() {
+            Some(routing) => routing.resolve(&self.config.primary_date.map_or(r.date, |p| p.of(r))),
+            None => table_name.to_string(),
+        }
+    }
+
+    /// rejects the rows whose synthetic id is already stored for a different row, i.e. one with a
+    /// different amount, vendor, or type, which the row would otherwise be taken for a re-import
+    /// of (or, with upserts, overwrite)
+    async fn reject_stored_collisions(
+        &self,
+        rows: &mut Vec<CsvRecord>,
+        table_name: &str,
+        source: &Path,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let tables = rows
+            .iter()
+            .map(|r| {
+                (
+                    self.table_of(r, table_name),
+                    (r.account.clone(), r.id as i64),
+                )
+            })
+            .into_group_map();
+        let mut stored = HashMap::new();
+        for (table, keys) in tables.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+            for (account, id, amount, vendor, transaction_type) in
+                self.store.select_stored_fields(&keys, &table).await?
+            {
+                stored.insert(
+                    (table.clone(), account, id),
+                    (amount, vendor, transaction_type),
+                );
+            }
+        }
+
+        rows.retain(|r| {
+            let key = (self.table_of(r, table_name), r.account.clone(), r.id as i64);
+            let collides = stored.get(&key).map_or(false, |(amount, vendor, transaction_type)| {
+                (*amount, vendor, transaction_type)
+                    != (r.amount.to_minor_units(), &r.vendor, &r.transaction_type)
+            });
+            if collides {
+                warn!(
+                    "Row {}/{} of {:?} collides with a different row stored in {} under the same synthetic id; it is rejected.",
+                    r.account, r.id, source, key.0
+                );
+                report.rows_rejected += 1;
+            }
+            !collides
+        });
+        Ok(())
+    }
+
+    /// compares the number of rows and the sum of the amounts of each account, as read, to the
+    /// rows stored in the tables they were routed to; a mismatch means rows were silently not
+    /// imported
+    async fn verify_totals(
+        &self,
+        rows: &[CsvRecord],
+        table_name: &str,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let groups = rows.iter().into_group_map_by(|r| r.account.as_str());
+
+        for (account, account_rows) in groups.into_iter().sorted_by_key(|(account, _)| *account) {
+            let expected = (
+                account_rows.len() as i64,
+                account_rows
+                    .iter()
+                    .map(|r| r.amount.to_minor_units())
+                    .sum::<i64>(),
+            );
+
+            let tables = account_rows
+                .iter()
+                .map(|r| (self.table_of(r, table_name), r.id as i64))
+                .into_group_map();
+            let mut stored = (0, 0);
+            for (table, ids) in tables.into_iter().sorted_by(|a, b| a.0.cmp(&b.0)) {
+                let (count, total) = self.store.select_totals(account, &ids, &table).await?;
+                stored = (stored.0 + count, stored.1 + total);
+            }
+            if stored == expected {
+                debug!("Totals of account {} verified.", account);
+            } else {
+                warn!(
+                    "Totals of account {} do not match: read {} rows totaling {}, but {} rows totaling {} are stored.",
+                    account,
+                    expected.0,
+                    expected.1 as f64 / 100.0,
+                    stored.0,
+                    stored.1 as f64 / 100.0
+                );
+                report.totals_mismatched += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_new_rows(
+        &self,
+        rows: &[CsvRecord],
+        table_name: &str,
+        schema: Option<&TableSchema>,
+        by_file_order: bool,
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        // group by account; rows for an account are not necessarily adjacent, so each account
+        // must be grouped as a whole to be resumed exactly once. Grouping keeps the file order.
+        let groups = rows.iter().into_group_map_by(|r| r.account.as_str());
+
+        for (account, account_rows) in groups.into_iter().sorted_by_key(|(account, _)| *account) {
+            // otherwise, a concurrent load of the account could select the same resumption
+            // point before either inserts, and both would insert the new rows
+            let lock = self.account_lock(table_name, account);
+            let _guard = lock.lock().await;
+
+            let resumed = match (by_file_order, self.config.primary_date) {
+                (true, _) => {
+                    self.resume_by_file_order(account, &account_rows, table_name)
+                        .await
+                }
+                (false, Some(primary)) => {
+                    self.resume_by_date(account, &account_rows, table_name, primary)
+                        .await
+                }
+                (false, None) => self.resume_by_id(account, &account_rows, table_name).await,
+            };
+            let resumption = match resumed {
+                Ok(resumption) => resumption,
+                Err(e) => {
+                    error!(
+                        "Could not select the last tx for account {}: {}",
+                        account, e
+                    );
+                    account_rows
+                        .iter()
+                        .for_each(|r| report.record_insert(&r.account, InsertOutcome::Failed));
+                    continue;
+                }
+            };
+
+            resumption
+                .skipped
+                .iter()
+                .for_each(|r| report.record_skipped(&r.account));
+
+            let to_import = resumption.to_import;
+            if to_import.is_empty() {
+                continue;
+            }
+
+            match resumption.after {
+                Some(after) => info!(
+                    "Resuming import for account {} after {}. Attempting to import {} new rows.",
+                    account,
+                    after,
+                    to_import.len()
+                ),
+                None => info!(
+                    "No existing rows for account {}. Attempting to import {} new rows.",
+                    account,
+                    to_import.len()
+                ),
+            }
+            report.merge(self.insert(&to_import, table_name, schema).await?);
+        }
+
+        Ok(())
+    }
+
+    /// the lock of the account in the table, created on first use
+    fn account_lock(&self, table_name: &str, account: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.account_locks.lock().unwrap();
+        locks
+            .entry((table_name.to_string(), account.to_string()))
+            .or_default()
+            .clone()
+    }
+
+    /// resumes after the max id of the account in the table
+    async fn resume_by_id<'a>(
+        &self,
+        account: &str,
+        rows: &[&'a CsvRecord],
+        table_name: &str,
+    ) -> std::result::Result<Resumption<'a>, sqlx::Error> {
+        let max = self
+            .store
+            .select_max_tx_for_account(account, table_name)
+            .await?;
+
+        let (to_import, skipped) = rows
+            .iter()
+            .copied()
+            .partition(|r| max.map_or(true, |max| r.id as i64 > max));
+        Ok(Resumption {
+            to_import,
+            skipped,
+            after: max.map(|max| format!("tx {}", max)),
+        })
+    }
+
+    /// resumes after the last of the rows, in file order, that is already in the table
+    async fn resume_by_file_order<'a>(
+        &self,
+        account: &str,
+        rows: &[&'a CsvRecord],
+        table_name: &str,
+    ) -> std::result::Result<Resumption<'a>, sqlx::Error> {
+        let ids = rows.iter().map(|r| r.id as i64).collect::<Vec<_>>();
+        let existing = self
+            .store
+            .select_existing_tx_ids(account, &ids, table_name)
+            .await?;
+
+        let last = ids.into_iter().rev().find(|id| existing.contains(id));
+        let start = last
+            .and_then(|last| rows.iter().rposition(|r| r.id == last as u64))
+            .map_or(0, |i| i + 1);
+        Ok(Resumption {
+            to_import: rows[start..].to_vec(),
+            skipped: rows[..start].to_vec(),
+            after: last.map(|last| format!("tx {}", last as u64)),
+        })
+    }
+
+    /// resumes from the latest primary date of the account in the table. Rows dated before it
+    /// are skipped; rows dated on it are skipped only if already in the table, since more rows
+    /// of the same day may have arrived since.
+    async fn resume_by_date<'a>(
+        &self,
+        account: &str,
+        rows: &[&'a CsvRecord],
+        table_name: &str,
+        primary: PrimaryDate,
+    ) -> std::result::Result<Resumption<'a>, sqlx::Error> {
+        let max = self
+            .store
+            .select_max_date_for_account(account, primary, table_name)
+            .await?;
+        let max = match max {
+            Some(max) => max,
+            None => {
+                return Ok(Resumption {
+                    to_import: rows.to_vec(),
+                    skipped: Vec::new(),
+                    after: None,
+                })
+            }
+        };
+
+        let same_day = rows
+            .iter()
+            .filter(|r| primary.of(r).naive_utc().date() == max)
+            .map(|r| r.id as i64)
+            .collect::<Vec<_>>();
+        let existing = self
+            .store
+            .select_existing_tx_ids(account, &same_day, table_name)
+            .await?;
+
+        let (to_import, skipped) = rows.iter().copied().partition(|r| {
+            let date = primary.of(r).naive_utc().date();
+            date > max || (date == max && !existing.contains(&(r.id as i64)))
+        });
+        Ok(Resumption {
+            to_import,
+            skipped,
+            after: Some(max.to_string()),
+        })
+    }
+}
+
+/// replaces the id of each row with its synthetic id, warning about rows of the file sharing an
+/// id: identical rows, which can't be told apart, and (very unlikely) collisions of different rows
+fn synthesize_ids(rows: &mut [CsvRecord], source: &Path) {
+    let mut first = HashMap::new();
+    for i in 0..rows.len() {
+        rows[i].id = domain::synthetic_id(&rows[i]);
+
+        let r = &rows[i];
+        let other = match first.get(&(r.account.clone(), r.id)) {
+            Some(other) => *other,
+            None => {
+                first.insert((r.account.clone(), r.id), i);
+                continue;
+            }
+        };
+        let o = &rows[other];
+        let identical = (&o.date, &o.vendor, &o.transaction_type)
+            == (&r.date, &r.vendor, &r.transaction_type)
+            && o.amount.to_minor_units() == r.amount.to_minor_units();
+        let shared = if identical {
+            "are identical, and share"
+        } else {
+            "differ, but collide on"
+        };
+        warn!(
+            "Rows {} and {} of {:?} {} the synthetic id {}/{}.",
+            other + 1,
+            i + 1,
+            source,
+            shared,
+            r.account,
+            r.id
+        );
+    }
+}
+
+/// the `account/id` keys shared by more than one of the rows, each listed once
+fn duplicate_keys(rows: &[CsvRecord]) -> Vec<String> {
+    let mut seen = HashSet::new();
+    rows.iter()
+        .filter(|r| !seen.insert((r.account.as_str(), r.id)))
+        .map(|r| format!("{}/{}", r.account, r.id))
+        .unique()
+        .collect()
+}
+
+/// removes the rows whose key was already seen in this run, counting them as duplicates;
+/// returns the rows removed
+fn deduplicate(
+    rows: &mut Vec<CsvRecord>,
+    source: &Path,
+    seen: &mut SeenKeys,
+    report: &mut ImportReport,
+) -> Vec<DroppedDuplicate> {
+    let mut dropped = Vec::new();
+    rows.retain(|r| match seen.get(&(r.account.clone(), r.id)) {
+        Some(kept) => {
+            debug!("Skipping duplicate row {}/{}", r.account, r.id);
+            report.record_duplicate(&r.account);
+            let key = format!("{}/{}", r.account, r.id);
+            dropped.push(DroppedDuplicate::new(source, key, kept.clone(), r));
+            false
+        }
+        None => {
+            seen.insert((r.account.clone(), r.id), source.display().to_string());
+            true
+        }
+    });
+    dropped
+}
+
+/// removes near-duplicates: rows with the same account, amount, and vendor, dated within the
+/// window of a later such row. Banks may emit a pending and a posted version of a transaction
+/// with different dates; the later (posted) row is kept. Returns the rows removed.
+fn deduplicate_window(
+    rows: &mut Vec<CsvRecord>,
+    source: &Path,
+    days: u32,
+    report: &mut ImportReport,
+) -> Vec<DroppedDuplicate> {
+    let window = chrono::Duration::days(days as i64);
+    let groups = rows.iter().enumerate().into_group_map_by(|(_, r)| {
+        (
+            r.account.clone(),
+            r.amount.to_minor_units(),
+            r.vendor.clone(),
+        )
+    });
+
+    // the near-duplicates by index, with the key of their group and of the row kept
+    let mut duplicates = HashMap::new();
+    for ((account, amount, vendor), mut group) in groups {
+        group.sort_by(|(_, a), (_, b)| (b.date, b.id).cmp(&(a.date, a.id)));
+
+        let mut kept: Option<&CsvRecord> = None;
+        for (i, r) in group {
+            match kept {
+                Some(k) if k.date - r.date <= window => {
+                    debug!("Skipping near-duplicate row {}/{}", r.account, r.id);
+                    let key = format!(
+                        "{}/{}/{}",
+                        account,
+                        Currency::from_minor_units(amount),
+                        vendor
+                    );
+                    duplicates.insert(i, (key, format!("{}/{}", k.account, k.id)));
+                }
+                _ => kept = Some(r),
+            }
+        }
+    }
+
+    let mut dropped = Vec::new();
+    let mut i = 0;
+    rows.retain(|r| {
+        let duplicate = duplicates.remove(&i);
+        i += 1;
+        match duplicate {
+            Some((key, kept)) => {
+                report.record_duplicate(&r.account);
+                dropped.push(DroppedDuplicate::new(source, key, kept, r));
+                false
+            }
+            None => true,
+        }
+    });
+    dropped
+}
+
+/// with a checkpoint of the source, skips the rows up to and including the checkpointed row, which
+/// an interrupted import already committed. Rows are loaded in the same order on every run, so
+/// the rows before it were committed too.
+fn skip_to_checkpoint(
+    rows: &mut Vec<CsvRecord>,
+    source: &Path,
+    path: &Path,
+    report: &mut ImportReport,
+) -> Result<()> {
+    let checkpoint = match Checkpoint::load(path)? {
+        Some(checkpoint) if checkpoint.is_of(source) => checkpoint,
+        _ => return Ok(()),
+    };
+
+    match rows
+        .iter()
+        .position(|r| r.account == checkpoint.account && r.id == checkpoint.tx_id)
+    {
+        Some(i) => {
+            info!(
+                "Resuming {:?} after the checkpointed row {}/{}; skipping {} row(s).",
+                source,
+                checkpoint.account,
+                checkpoint.tx_id,
+                i + 1
+            );
+            rows.drain(..=i)
+                .for_each(|r| report.record_skipped(&r.account));
+        }
+        None => warn!(
+            "The checkpointed row {}/{} is not in {:?}; importing every row.",
+            checkpoint.account, checkpoint.tx_id, source
+        ),
+    }
+    Ok(())
+}
+
+/// checks text values against the maximum lengths of their columns, truncating the values or
+/// rejecting the rows per the policy
+fn enforce_lengths(
+    rows: &mut Vec<CsvRecord>,
+    schema: Option<&TableSchema>,
+    policy: TooLongPolicy,
+    report: &mut ImportReport,
+) {
+    let schema = match (schema, policy) {
+        (Some(schema), TooLongPolicy::Truncate) | (Some(schema), TooLongPolicy::Reject) => schema,
+        _ => return,
+    };
+
+    let keep = rows
+        .iter_mut()
+        .map(|r| fits_lengths(r, schema, policy))
+        .collect::<Vec<_>>();
+    report.rows_rejected += keep.iter().filter(|k| !**k).count();
+
+    let mut keep = keep.into_iter();
+    rows.retain(|_| keep.next().unwrap_or(true));
+}
+
+/// truncates the text values of the row that are too long, or returns false if the row is
+/// rejected
+fn fits_lengths(row: &mut CsvRecord, schema: &TableSchema, policy: TooLongPolicy) -> bool {
+    let (account, id) = (row.account.clone(), row.id);
+
+    for (column, value) in row.text_columns_mut() {
+        let (max, value) = match (schema.max_length(column), value) {
+            (Some(max), Some(value)) if value.chars().count() > max => (max, value),
+            _ => continue,
+        };
+
+        if policy == TooLongPolicy::Reject {
+            warn!(
+                "Rejecting row {}/{}: {} is longer than {} characters.",
+                account, id, column, max
+            );
+            return false;
+        }
+
+        warn!(
+            "Truncating {} of row {}/{} to {} characters.",
+            column, account, id, max
+        );
+        *value = value.chars().take(max).collect();
+    }
+
+    true
+}
+
+/// A file of a directory import, parsed or being parsed on the thread pool
+struct PendingFile {
+    path: PathBuf,
+    parsed: tokio::sync::oneshot::Receiver<std::result::Result<ParsedFile, String>>,
+    /// the share of the row budget held by the rows of the file, until they are loaded
+    _permit: Option<tokio::sync::OwnedSemaphorePermit>,
+}
+
+/// Caps the rows parsed but not yet loaded. A file takes a permit for each of its lines, an upper
+/// bound of its rows, before it is parsed, and returns them once its rows are loaded. Files are
+/// read whole, so a file of more lines than the maximum takes every permit, and is in flight alone.
+struct RowBudget {
+    max: usize,
+    permits: Arc<tokio::sync::Semaphore>,
+    /// the most permits held at once
+    peak: AtomicUsize,
+}
+
+impl RowBudget {
+    fn new(max: usize) -> Self {
+        let max = max.min(u32::MAX as usize);
+        Self {
+            max,
+            permits: Arc::new(tokio::sync::Semaphore::new(max)),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// waits for the permits of the lines of the file; to be called off the runtime
+    fn acquire(&self, path: &Path) -> tokio::sync::OwnedSemaphorePermit {
+        // a file that can't be read holds no rows; reading it fails with an error later
+        let lines = count_lines(path).unwrap_or(0);
+        if lines > self.max {
+            warn!(
+                "{:?} has {} lines, more than the maximum of {} rows in flight; files are read whole.",
+                path, lines, self.max
+            );
+        }
+
+        let acquire = self
+            .permits
+            .clone()
+            .acquire_many_owned(lines.min(self.max) as u32);
+        let permit = tokio::runtime::Handle::current()
+            .block_on(acquire)
+            .expect("the row budget is never closed");
+        self.peak.fetch_max(self.in_flight(), Ordering::SeqCst);
+        permit
+    }
+
+    /// the permits held
+    fn in_flight(&self) -> usize {
+        self.max - self.permits.available_permits()
+    }
+}
+
+/// the number of lines of the file; every row takes at least one line, ended by `\n`, `\r\n`
+/// or `\r`
+fn count_lines(path: &Path) -> std::io::Result<usize> {
+    use std::io::BufRead;
+
+    let mut reader = std::io::BufReader::new(open_csv(path)?);
+    let mut lines = 0;
+    loop {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            // a last line without a line ending, or a `\r\n` counted twice; either way, a bound
+            return Ok(lines + 1);
+        }
+        lines += buffer
+            .iter()
+            .filter(|b| **b == b'\n' || **b == b'\r')
+            .count();
+        let read = buffer.len();
+        reader.consume(read);
+    }
+}
+
+/// parses the files on the thread pool, in file order, sending each to be loaded. With a row
+/// budget, a file is only parsed once the rows of the files before it fit; stops early once
+/// nothing receives the files.
+fn parse_files(
+    paths: Vec<PathBuf>,
+    csv_config: Arc<CsvConfig>,
+    pool: rayon::ThreadPool,
+    budget: Option<Arc<RowBudget>>,
+    sender: tokio::sync::mpsc::Sender<PendingFile>,
+) {
+    for path in paths {
+        let permit = budget.as_ref().map(|budget| budget.acquire(&path));
+
+        let (parsed_sender, parsed) = tokio::sync::oneshot::channel();
+        let (file, csv_config) = (path.clone(), csv_config.clone());
+        pool.spawn(move || {
+            let _ = parsed_sender.send(read_file(&file, &csv_config).map_err(|e| e.to_string()));
+        });
+
+        let pending = PendingFile {
+            path,
+            parsed,
+            _permit: permit,
+        };
+        if sender.blocking_send(pending).is_err() {
+            return;
+        }
+    }
+
+    if let Some(budget) = budget {
+        debug!(
+            "At most {} of {} rows were in flight.",
+            budget.peak.load(Ordering::SeqCst),
+            budget.max
+        );
+    }
+}
+
+/// whether the amounts and balances of the records look swapped, as a column map mistake might
+/// do. A balance changes by the amount of one of two consecutive rows of an account (depending on
+/// whether the file is oldest or newest first), so whichever column more often changes by the
+/// other is taken as the balance; failing that, balances are usually the larger of the two.
+fn looks_swapped(records: &[CsvRecord]) -> bool {
+    fn steps(
+        records: &[CsvRecord],
+        amount: fn(&CsvRecord) -> i64,
+        balance: fn(&CsvRecord) -> i64,
+    ) -> usize {
+        records
+            .iter()
+            .tuple_windows()
+            .filter(|(a, b)| a.account == b.account)
+            .filter(|(a, b)| {
+                let change = (balance(b) - balance(a)).abs();
+                change == amount(a).abs() || change == amount(b).abs()
+            })
+            .count()
+    }
+    let amount: fn(&CsvRecord) -> i64 = |r| r.amount.to_minor_units();
+    let balance: fn(&CsvRecord) -> i64 = |r| r.balance.to_minor_units();
+
+    let as_mapped = steps(records, amount, balance);
+    let swapped = steps(records, balance, amount);
+    if as_mapped != swapped {
+        return swapped > as_mapped;
+    }
+
+    let magnitude =
+        |value: fn(&CsvRecord) -> i64| records.iter().map(|r| value(r).abs()).sum::<i64>();
+    records.len() > 1 && magnitude(amount) > magnitude(balance)
+}
+
+/// sorts the rows by account and id (or by account, primary date, and id, with a primary date),
+/// so that rows are inserted in the same order regardless of the order of the source
+fn sort_rows(rows: &mut [CsvRecord], primary: Option<PrimaryDate>) {
+    rows.sort_by(|a, b| row_order(a, b, primary));
+}
+
+fn row_order(a: &CsvRecord, b: &CsvRecord, primary: Option<PrimaryDate>) -> std::cmp::Ordering {
+    let date = |r: &CsvRecord| primary.map(|p| p.of(r));
+    (&a.account, date(a), a.id).cmp(&(&b.account, date(b), b.id))
+}
+
+/// prints the first `n` parsed records from the source, without connecting to the database
+pub fn sample(source: &Source, csv_config: &CsvConfig, n: usize) -> Result<()> {
+    write_sample(source, csv_config, n, std::io::stdout().lock())
+}
+
+/// writes the first `n` parsed records from the source, one per line
+fn write_sample<W: Write>(
+    source: &Source,
+    csv_config: &CsvConfig,
+    n: usize,
+    mut out: W,
+) -> Result<()> {
+    let record_sets = read_source(source, csv_config)?;
+
+    for record in record_sets.iter().flat_map(|p| &p.records).take(n) {
+        writeln!(out, "{}", record)?;
+    }
+
+    Ok(())
+}
+
+/// writes the records of the source to standard output, as read; no database connection is made
+pub fn export(source: &Source, csv_config: &CsvConfig, options: &ExportOptions) -> Result<()> {
+    let record_sets = read_source(source, csv_config)?;
+    let records = record_sets.iter().flat_map(|p| &p.records);
+
+    export::write_records(records, options, std::io::stdout().lock())
+}
+
+/// parses the source `repeat` times, measuring the throughput of parsing alone; no database
+/// connection is made
+pub fn benchmark(source: &Source, csv_config: &CsvConfig, repeat: usize) -> Result<ParseBenchmark> {
+    let bytes = match source {
+        Source::File(f) | Source::Archive(f) => std::fs::metadata(f)?.len(),
+        Source::Directory(dir) => config::csv_files(dir)?
+            .iter()
+            .map(|path| std::fs::metadata(path).map_or(0, |m| m.len()))
+            .sum(),
+        Source::Manifest(entries) => entries
+            .iter()
+            .map(|e| std::fs::metadata(e.get_path()).map_or(0, |m| m.len()))
+            .sum(),
+    };
+
+    let start = Instant::now();
+    let mut rows = 0;
+    for _ in 0..repeat {
+        rows += read_source(source, csv_config)?
+            .iter()
+            .map(|parsed| parsed.records.len())
+            .sum::<usize>();
+    }
+
+    Ok(ParseBenchmark {
+        repeat,
+        rows,
+        bytes: bytes * repeat as u64,
+        duration: start.elapsed(),
+    })
+}
+
+/// reads every csv file of the source, in file name order, or the order of a manifest
+fn read_source(source: &Source, csv_config: &CsvConfig) -> Result<Vec<ParsedFile>> {
+    let record_sets = match source {
+        Source::File(f) => vec![read_file(f, csv_config)?],
+        Source::Directory(dir) => config::csv_files(dir)?
+            .iter()
+            .map(|path| read_file(path, csv_config))
+            .collect::<Result<Vec<_>>>()?,
+        Source::Archive(f) => read_archive(f, csv_config)?
+            .into_iter()
+            .map(|(_, parsed)| parsed)
+            .collect(),
+        Source::Manifest(entries) => entries
+            .iter()
+            .map(|e| read_file(e.get_path(), &csv_config.for_manifest_entry(e)))
+            .collect::<Result<Vec<_>>>()?,
+    };
+
+    Ok(record_sets)
+}
+
+fn reader_builder() -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder.trim(csv::Trim::Headers);
+    builder
+}
+
+/// opens a csv reader of the source, with the delimiter and encoding of its dialect, if any;
+/// returns the reader and the number of preamble lines skipped before the header row
+fn csv_reader<'a, R: std::io::Read + 'a>(
+    reader: R,
+    source: &Path,
+    csv_config: &CsvConfig,
+) -> Result<(csv::Reader<Box<dyn std::io::Read + 'a>>, u64)> {
+    let dialect = csv_config.get_dialect(source);
+
+    let mut builder = reader_builder();
+    if !csv_config.is_trim_headers() {
+        builder.trim(csv::Trim::None);
+    }
+    if csv_config.is_flexible() {
+        builder.flexible(true);
+    }
+    let delimiter = dialect.and_then(|d| d.get_delimiter()).unwrap_or(b',');
+    builder.delimiter(delimiter);
+    let encoding = dialect.map_or(Encoding::Utf8, |d| d.get_encoding());
+
+    let text = if encoding == Encoding::Utf8 && csv_config.is_require_utf8() {
+        dialect::require_utf8(reader).map_err(|e| format!("{:?} is {}", source, e))?
+    } else {
+        dialect::decode(encoding, reader)?
+    };
+
+    let lines = match csv_config.get_header_detect_lines() {
+        Some(lines) => lines,
+        None => return Ok((builder.from_reader(text), 0)),
+    };
+    match dialect::skip_preamble(text, lines, delimiter, &csv_config.expected_headers())? {
+        Some((skipped, text)) => {
+            if skipped > 0 {
+                info!("Skipping {} preamble line(s) of {:?}", skipped, source);
+            }
+            Ok((builder.from_reader(text), skipped as u64))
+        }
+        None => Err(format!(
+            "{:?} has no header row in its first {} lines",
+            source, lines
+        )
+        .into()),
+    }
+}
+
+fn read_file(f: &Path, csv_config: &CsvConfig) -> Result<ParsedFile> {
+    let abs_path = f.canonicalize()?;
+    info!("Reading csv records from file {:?}", abs_path);
+
+    let file = buffered(open_csv(f)?, csv_config);
+    let (reader, preamble) = csv_reader(file, f, csv_config)?;
+    read_records(reader, preamble, f, csv_config)
+}
+
+/// buffers the reader with the configured capacity, or the default one
+fn buffered<R: std::io::Read>(reader: R, csv_config: &CsvConfig) -> std::io::BufReader<R> {
+    match csv_config.get_read_buffer_bytes() {
+        Some(capacity) => std::io::BufReader::with_capacity(capacity, reader),
+        None => std::io::BufReader::new(reader),
+    }
+}
+
+/// opens the csv file, decompressing it as it is read if it is gzipped
+fn open_csv(f: &Path) -> std::io::Result<Box<dyn std::io::Read>> {
+    let file = std::fs::File::open(f)?;
+    if config::is_gzipped_csv(f) {
+        Ok(Box::new(flate2::read::GzDecoder::new(file)))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+/// reads the csv entries of a zip archive, returning the records of each entry by name;
+/// entries that are not csv files are skipped
+fn read_archive(f: &Path, csv_config: &CsvConfig) -> Result<Vec<(String, ParsedFile)>> {
+    let abs_path = f.canonicalize()?;
+    info!("Reading csv records from archive {:?}", abs_path);
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(f)?)?;
+    let mut entries = Vec::new();
+
+    for i in 0..archive.len() {
+        let entry = archive.by_index(i)?;
+        let name = entry.name().to_string();
+
+        if entry.is_dir() || !config::is_csv(Path::new(&name)) {
+            debug!("Skipping archive entry {}", name);
+            continue;
+        }
+
+        info!("Reading csv records from archive entry {}", name);
+        let (reader, preamble) = csv_reader(entry, Path::new(&name), csv_config)?;
+        let parsed = read_records(reader, preamble, Path::new(&name), csv_config)?;
+        entries.push((name, parsed));
+    }
+
+    Ok(entries)
+}
+
+/// the source columns that no field is read from, other than unnamed ones and the sign column;
+/// `headers` are the fields the source columns are read into
+fn unmapped_columns<'a>(
+    sources: &'a csv::StringRecord,
+    headers: &csv::StringRecord,
+    sign_index: Option<usize>,
+) -> Vec<&'a str> {
+    sources
+        .iter()
+        .zip(headers.iter())
+        .enumerate()
+        .filter(|(i, (source, header))| {
+            !source.trim().is_empty() && !HEADERS.contains(header) && Some(*i) != sign_index
+        })
+        .map(|(_, (source, _))| source)
+        .collect()
+}
+
+/// reads the records of a csv source; `source` is the path or archive entry name of the csv file,
+/// and `preamble` the number of lines skipped before its header row
+fn read_records<R: std::io::Read>(
+    mut reader: csv::Reader<R>,
+    preamble: u64,
+    source: &Path,
+    csv_config: &CsvConfig,
+) -> Result<ParsedFile> {
+    let mut records = Vec::new();
+
+    let mut headers = reader.headers()?.clone();
+    // with flexible rows, each row is padded or cut to the width of the header row before
+    // any fields are appended
+    let width = headers.len();
+    let flexible = csv_config.is_flexible();
+    if let Some(column) = csv_config.get_percent_column() {
+        headers = headers
+            .iter()
+            .map(|h| if h == column { PERCENT_HEADER } else { h })
+            .collect();
+    }
+    if let Some(column) = csv_config.get_currency_column() {
+        headers = headers
+            .iter()
+            .map(|h| if h == column { CURRENCY_HEADER } else { h })
+            .collect();
+    }
+    // the column map may be partial; unmapped columns keep their names, unless a mapped column
+    // takes the name, in which case the unmapped column is ignored
+    let column_map = csv_config.get_column_map();
+    if !column_map.is_empty() {
+        headers = headers
+            .iter()
+            .map(
+                |h| match column_map.iter().find(|(source, _)| source == h) {
+                    Some((_, field)) => field.as_str(),
+                    None if column_map.iter().any(|(_, field)| field == h) => "",
+                    None => h,
+                },
+            )
+            .collect();
+    }
+
+    let mut date_options = DateOptions {
+        format: csv_config
+            .get_dialect(source)
+            .and_then(|d| d.get_date_format())
+            .map(|f| f.to_string()),
+        offset: csv_config.get_timezone(),
+        ..DateOptions::default()
+    };
+    if let Some(pattern) = csv_config.get_date_from_filename() {
+        date_options.fallback =
+            domain::date_from_filename(source, pattern, date_options.get_offset());
+        match date_options.fallback {
+            Some(date) => info!("Rows without a date default to {} from {:?}", date, source),
+            None => warn!(
+                "Could not derive a date from {:?} using {}",
+                source, pattern
+            ),
+        }
+    }
+
+    // a date derived from the file name stands in for a missing date column
+    let missing_date = date_options.fallback.is_some() && !headers.iter().any(|h| h == DATE_HEADER);
+    if missing_date {
+        headers.push_field(DATE_HEADER);
+    }
+
+    // an account given on the command line, or derived from the file name, stands in for a
+    // missing account column; a present column always wins
+    let missing_account = if headers.iter().any(|h| h == ACCOUNT_HEADER) {
+        None
+    } else if let Some(account) = csv_config.get_account() {
+        Some(account.to_string())
+    } else if let Some(pattern) = csv_config.get_account_from_filename() {
+        let account = domain::account_from_filename(source, pattern);
+        match &account {
+            Some(account) => info!("Rows default to account {} from {:?}", account, source),
+            None => warn!(
+                "Could not derive an account from {:?} using {}",
+                source, pattern
+            ),
+        }
+        account
+    } else {
+        csv_config.get_default_account().map(|a| a.to_string())
+    };
+    if missing_account.is_some() {
+        headers.push_field(ACCOUNT_HEADER);
+    }
+
+    // synthetic ids replace the id column, if any, which is ignored
+    let synthetic_ids = csv_config.is_id_from_hash();
+    if synthetic_ids {
+        headers = headers
+            .iter()
+            .map(|h| if h == ID_HEADER { "" } else { h })
+            .collect();
+        headers.push_field(ID_HEADER);
+    }
+
+    // with opening balances, running balances stand in for a missing balance column
+    let missing_balance = !csv_config.get_opening_balances().is_empty()
+        && !headers.iter().any(|h| h == BALANCE_HEADER);
+    if missing_balance {
+        headers.push_field(BALANCE_HEADER);
+    }
+
+    // dates are parsed in the zone of the row's account, if it has one
+    let account_index = headers.iter().position(|h| h == ACCOUNT_HEADER);
+    let account_zones = csv_config.has_account_zones();
+
+    let missing_headers = REQUIRED_HEADERS
+        .iter()
+        .filter(|required| !headers.iter().any(|h| h == **required))
+        .join(", ");
+    if !missing_headers.is_empty() {
+        return Err(format!("{:?} has no {} column(s)", source, missing_headers).into());
+    }
+
+    // the sign column is found by its source header, as it isn't a field of a record
+    let sign_index = match csv_config.get_sign_column() {
+        Some(column) => match reader.headers()?.iter().position(|h| h == column) {
+            Some(index) => Some(index),
+            None => return Err(format!("Sign column {} not found in {:?}", column, source).into()),
+        },
+        None => None,
+    };
+    let on_unknown_sign = csv_config.get_unknown_sign_policy();
+
+    // line numbers count from the start of the file, including any preamble
+    let source_file = if csv_config.is_store_source_position() {
+        Some(source.to_string_lossy().to_string())
+    } else {
+        None
+    };
+
+    let policy = csv_config.get_unmapped_columns_policy();
+    if policy != UnmappedColumnsPolicy::Ignore {
+        let unmapped = unmapped_columns(reader.headers()?, &headers, sign_index).join(", ");
+        match policy {
+            _ if unmapped.is_empty() => (),
+            UnmappedColumnsPolicy::Reject => {
+                return Err(format!("{:?} has unmapped column(s) {}", source, unmapped).into())
+            }
+            _ => info!("{:?} has unmapped column(s) {}", source, unmapped),
+        }
+    }
+
+    let currency_options = csv_config.get_currency_options();
+    let counts = domain::with_date_options(date_options, || {
+        currency::with_options(currency_options, || -> Result<RowCounts> {
+            let mut counts = RowCounts::default();
+
+            for (line, result) in reader.records().enumerate() {
+                let result = result.and_then(|mut record| {
+                    if flexible {
+                        record.truncate(width);
+                        while record.len() < width {
+                            record.push_field("");
+                        }
+                    }
+                    if missing_date {
+                        record.push_field("");
+                    }
+                    if let Some(account) = &missing_account {
+                        record.push_field(account);
+                    }
+                    if synthetic_ids {
+                        record.push_field("0");
+                    }
+                    if missing_balance {
+                        record.push_field("0.00");
+                    }
+                    if account_zones {
+                        domain::set_zone(
+                            account_index
+                                .and_then(|i| record.get(i))
+                                .and_then(|account| csv_config.get_account_zone(account)),
+                        );
+                    }
+                    let sign = sign_index
+                        .and_then(|i| record.get(i))
+                        .map(|s| s.to_string());
+                    let source_line = record.position().map(|p| p.line() + preamble);
+                    record
+                        .deserialize::<CsvRecord>(Some(&headers))
+                        .map(|mut r| {
+                            if source_file.is_some() {
+                                r.source_file = source_file.clone();
+                                r.source_line = source_line;
+                            }
+                            (r, sign)
+                        })
+                });
+                let overflowed = currency::take_overflowed();
+                let null_amount = currency::take_null_amount();
+                if currency::take_precision_lost() {
+                    counts.precision_lost += 1;
+                }
+                match result {
+                    Ok((mut record, Some(sign))) => {
+                        let negative =
+                            match (currency::parse_sign_indicator(&sign), on_unknown_sign) {
+                                (Some(negative), _) => negative,
+                                (None, UnknownSignPolicy::Credit) => false,
+                                (None, UnknownSignPolicy::Debit) => true,
+                                (None, UnknownSignPolicy::Error) => {
+                                    error!(
+                                        "Skipping row {}: unknown sign indicator {:?}",
+                                        line + 1,
+                                        sign
+                                    );
+                                    counts.bad += 1;
+                                    continue;
+                                }
+                            };
+                        record.amount = record.amount.with_sign(negative);
+                        if null_amount {
+                            counts.amount_zeroed += 1;
+                        }
+                        records.push(record)
+                    }
+                    Ok((record, None)) => {
+                        if null_amount {
+                            counts.amount_zeroed += 1;
+                        }
+                        records.push(record)
+                    }
+                    Err(e)
+                        if overflowed && currency_options.on_overflow == OverflowPolicy::Skip =>
+                    {
+                        warn!("Skipping row with an amount out of range: {}", e);
+                        counts.skipped += 1;
+                    }
+                    Err(e)
+                        if null_amount
+                            && currency_options.on_null_amount == NullAmountPolicy::Error =>
+                    {
+                        error!("Row {} has no amount. Aborting", line + 1);
+                        return Err(Box::new(e));
+                    }
+                    Err(e) => {
+                        error!("Skipping row Could not read row: {}", e);
+                        counts.bad += 1;
+                    }
+                }
+            }
+
+            Ok(counts)
+        })
+    })?;
+
+    if counts.precision_lost > 0 {
+        warn!(
+            "{} rows had amounts with more fractional digits than are kept; precision was lost.",
+            counts.precision_lost
+        );
+    }
+    if counts.amount_zeroed > 0 {
+        warn!(
+            "{} rows had a blank or unparseable amount, imported as zero.",
+            counts.amount_zeroed
+        );
+    }
+    if synthetic_ids {
+        synthesize_ids(&mut records, source);
+    }
+    for record in records.iter_mut() {
+        match record.currency_code.as_deref().map(str::trim) {
+            None | Some("") => {
+                record.currency_code = csv_config.get_currency_code().map(|c| c.to_string())
+            }
+            Some(code) if !currency::is_currency_code(code) => warn!(
+                "Row {}/{} has an invalid currency code: {:?}",
+                record.account, record.id, code
+            ),
+            Some(_) => (),
+        }
+    }
+
+    if !missing_balance && looks_swapped(&records) {
+        warn!(
+            "The amounts and balances of {:?} look swapped; check the column map.",
+            source
+        );
+    }
+
+    info!(
+        "Read {} records from file. {} rows ignored because they could not be loaded. {} rows skipped because an amount was out of range.",
+        records.len(),
+        counts.bad,
+        counts.skipped
+    );
+    Ok(ParsedFile {
+        records,
+        rejected: counts.bad + counts.skipped,
+        precision_lost: counts.precision_lost,
+        amount_zeroed: counts.amount_zeroed,
+        derive_balances: missing_balance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    const HEADER: &str = "ACCOUNT,ID,Date,Amount,Balance,Vendor,Type";
+
+    /// a fresh, empty directory for the files of the test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("csv-importer-{}-{}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// writes the rows, under the header row, to a csv file in a fresh directory of the test
+    fn csv_file(test: &str, rows: &[&str]) -> PathBuf {
+        write_csv(&temp_dir(test), "transactions.csv", rows)
+    }
+
+    /// writes the rows, under the header row, to the named csv file of the directory
+    fn write_csv(dir: &Path, name: &str, rows: &[&str]) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, csv_text(rows)).unwrap();
+        path
+    }
+
+    /// the rows under the header row
+    fn csv_text(rows: &[&str]) -> String {
+        std::iter::once(HEADER)
+            .chain(rows.iter().copied())
+            .map(|row| format!("{}\n", row))
+            .collect()
+    }
+
+    /// the csv options of the arguments
+    fn csv_config(args: &[&str]) -> CsvConfig {
+        let args = std::iter::once("csv-importer").chain(args.iter().copied());
+        CsvConfig::from(&config::app().get_matches_from(args))
+    }
+
+    /// a record of the account and id, dated the day of January 2021
+    fn record(account: &str, id: u64, day: u32) -> CsvRecord {
+        let date = FixedOffset::east(0).ymd(2021, 1, day).and_hms(0, 0, 0);
+        CsvRecord::builder(account, id, date).build()
+    }
+
+    /// the `account/id` keys of the rows, in order
+    fn keys(rows: &[CsvRecord]) -> Vec<String> {
+        rows.iter()
+            .map(|r| format!("{}/{}", r.account, r.id))
+            .collect()
+    }
+
+    #[cfg(feature = "memory")]
+    fn memory_importer(config: Config) -> Importer {
+        let store = TransactionStore::Memory(crate::memory::MemoryStore::default());
+        Importer::with_store(config, store)
+    }
+
+    #[cfg(feature = "memory")]
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Runtime::new().unwrap().block_on(future)
+    }
+
+    /// the rows stored in the table of the in-memory store, by account and id
+    #[cfg(feature = "memory")]
+    fn stored(importer: &Importer, table_name: &str) -> Vec<CsvRecord> {
+        let tables = match importer.store() {
+            TransactionStore::Memory(memory) => memory.tables(),
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        tables
+            .into_iter()
+            .find(|(name, _)| name == table_name)
+            .map_or_else(Vec::new, |(_, rows)| rows)
+    }
+
+    #[test]
+    fn sample_writes_the_first_records() {
+        let path = csv_file(
+            "sample",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+                "BOFA,3,01/03/2021,-3.00,94.00,Cinema,Debit",
+            ],
+        );
+
+        let mut out = Vec::new();
+        write_sample(&Source::File(path), &CsvConfig::default(), 2, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines = out.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("Id: 1, "), "{}", lines[0]);
+        assert!(lines[0].contains("Vendor: Grocer"), "{}", lines[0]);
+        assert!(lines[1].contains("Id: 2, "), "{}", lines[1]);
+    }
+
+    #[test]
+    fn archive_csv_entries_are_read_in_order() {
+        let path = temp_dir("archive").join("statements.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        for (name, row) in &[
+            ("january.csv", "BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit"),
+            ("readme.txt", "not a csv file"),
+            ("february.csv", "BOFA,2,02/28/2021,-2.00,97.00,Cafe,Debit"),
+        ] {
+            zip.start_file(*name, zip::write::FileOptions::default())
+                .unwrap();
+            zip.write_all(csv_text(&[*row]).as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let entries = read_archive(&path, &CsvConfig::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["january.csv", "february.csv"]);
+        assert_eq!(entries[0].1.records[0].id, 1);
+        assert_eq!(entries[1].1.records[0].id, 2);
+    }
+    #[test]
+    fn rows_sort_the_same_in_any_source_order() {
+        let rows = vec![
+            record("B", 2, 1),
+            record("A", 3, 2),
+            record("B", 1, 3),
+            record("A", 1, 4),
+        ];
+        let mut shuffled = rows.clone();
+        shuffled.reverse();
+        shuffled.swap(0, 2);
+
+        let mut sorted = rows.clone();
+        sort_rows(&mut sorted, None);
+        sort_rows(&mut shuffled, None);
+        assert_eq!(keys(&sorted), ["A/1", "A/3", "B/1", "B/2"]);
+        assert_eq!(keys(&shuffled), keys(&sorted));
+
+        // with a primary date, rows of an account are in date order
+        let mut by_date = rows;
+        sort_rows(&mut by_date, Some(PrimaryDate::Transaction));
+        assert_eq!(keys(&by_date), ["A/3", "A/1", "B/2", "B/1"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn interleaved_accounts_resume_once_each() {
+        let config = Config {
+            load_options: LoadOptions::New,
+            ..Config::default()
+        };
+        let importer = memory_importer(config);
+        let source = Path::new("interleaved");
+
+        let first = vec![record("A", 1, 1), record("B", 1, 1), record("A", 2, 2)];
+        block_on(importer.import_records(source, first)).unwrap();
+
+        let second = vec![
+            record("A", 1, 1),
+            record("B", 1, 1),
+            record("A", 2, 2),
+            record("B", 2, 2),
+            record("A", 3, 3),
+            record("B", 3, 3),
+        ];
+        let report = block_on(importer.import_records(source, second)).unwrap();
+        assert_eq!(report.inserted, 3);
+        assert_eq!(report.skipped_by_filter, 3);
+        assert_eq!(
+            keys(&stored(&importer, "")),
+            ["A/1", "A/2", "A/3", "B/1", "B/2", "B/3"]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn files_import_into_the_memory_store() {
+        let path = csv_file(
+            "memory",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        let importer = memory_importer(Config::default());
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+        assert_eq!((report.files, report.rows_read, report.inserted), (1, 2, 2));
+        let rows = stored(&importer, "");
+        assert_eq!(keys(&rows), ["BOFA/1", "BOFA/2"]);
+        assert_eq!(rows[1].amount.to_string(), "-2.00");
+        assert_eq!(rows[1].vendor, "Cafe");
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+        assert_eq!((report.inserted, report.conflicted), (0, 2));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn rows_read_from_an_earlier_file_are_duplicates() {
+        let dir = temp_dir("dedupe-across-files");
+        write_csv(
+            &dir,
+            "a.csv",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        write_csv(
+            &dir,
+            "b.csv",
+            &[
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+                "BOFA,3,01/03/2021,-3.00,94.00,Cinema,Debit",
+            ],
+        );
+        let importer = memory_importer(Config {
+            deduplicate: true,
+            ..Config::default()
+        });
+
+        let report = block_on(importer.import_directory(&dir)).unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!((report.inserted, report.duplicates), (3, 1));
+        assert_eq!(report.conflicted, 0);
+    }
+
+    #[test]
+    fn flags_default_to_false_and_columns_are_renamed() {
+        let path = temp_dir("flags").join("flags.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Amount,Balance,Merchant,Type,Income\n",
+                "BOFA,1,01/31/2021,5.00,5.00,Employer,Credit,yes\n",
+                "BOFA,2,01/31/2021,-1.00,4.00,Grocer,Debit,\n",
+            ),
+        )
+        .unwrap();
+
+        let parsed = read_file(&path, &csv_config(&["--column-map", "Merchant=Vendor"])).unwrap();
+        let records = &parsed.records;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].vendor, "Employer");
+        assert!(records[0].income);
+        assert!(!records[0].fixed && !records[0].spend);
+        assert!(!records[1].income);
+    }
+
+    #[test]
+    fn amounts_losing_precision_are_counted() {
+        let path = csv_file(
+            "precision",
+            &[
+                "BOFA,1,01/01/2021,-1.005,98.995,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.000,96.995,Cafe,Debit",
+                "BOFA,3,01/03/2021,-3.00,93.99,Cinema,Debit",
+            ],
+        );
+
+        let parsed = read_file(&path, &csv_config(&[])).unwrap();
+        assert_eq!(parsed.records.len(), 3);
+        assert_eq!(parsed.precision_lost, 2);
+        assert_eq!(parsed.records[0].amount.to_string(), "-1.00");
+    }
+
+    #[test]
+    fn account_less_files_take_the_account_given() {
+        let dir = temp_dir("account");
+        let path = dir.join("chase-statement-2021.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ID,Date,Amount,Balance,Vendor,Type\n",
+                "1,01/31/2021,-1.00,99.00,Grocer,Debit\n",
+            ),
+        )
+        .unwrap();
+
+        let parsed = read_file(&path, &csv_config(&["--account", "CHECKING"])).unwrap();
+        assert_eq!(parsed.records[0].account, "CHECKING");
+
+        let config = csv_config(&["--account-from-filename", "{account}-statement-*"]);
+        let parsed = read_file(&path, &config).unwrap();
+        assert_eq!(parsed.records[0].account, "chase");
+
+        // a present column always wins
+        let path = write_csv(
+            &dir,
+            "bofa.csv",
+            &["BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit"],
+        );
+        let parsed = read_file(&path, &csv_config(&["--account", "CHECKING"])).unwrap();
+        assert_eq!(parsed.records[0].account, "BOFA");
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn rows_dated_in_year_9999_are_rejected() {
+        let importer = memory_importer(Config {
+            date_bounds: DateBoundsPolicy::Reject,
+            ..Config::default()
+        });
+        let far = FixedOffset::east(0).ymd(9999, 12, 31).and_hms(0, 0, 0);
+        let records = vec![
+            record("BOFA", 1, 31),
+            CsvRecord::builder("BOFA", 2, far).build(),
+        ];
+
+        let report = block_on(importer.import_records(Path::new("bounds"), records)).unwrap();
+        assert_eq!((report.inserted, report.rows_rejected), (1, 1));
+        assert_eq!(keys(&stored(&importer, "")), ["BOFA/1"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn threads_import_the_same_rows() {
+        let dir = temp_dir("threads");
+        for month in 1..=6 {
+            let first = format!(
+                "BOFA,{}1,{:02}/01/2021,-1.00,99.00,Grocer,Debit",
+                month, month
+            );
+            let second = format!(
+                "BOFA,{}2,{:02}/02/2021,-2.00,97.00,Cafe,Debit",
+                month, month
+            );
+            write_csv(&dir, &format!("{:02}.csv", month), &[&first, &second]);
+        }
+        let import = |threads| {
+            let importer = memory_importer(Config {
+                threads,
+                ..Config::default()
+            });
+            let mut report = block_on(importer.import_directory(&dir)).unwrap();
+            report.duration = std::time::Duration::default();
+            (report, keys(&stored(&importer, "")))
+        };
+
+        let (one, one_rows) = import(1);
+        let (four, four_rows) = import(4);
+        assert_eq!(one.inserted, 12);
+        assert_eq!(one, four);
+        assert_eq!(one_rows, four_rows);
+    }
+
+    #[test]
+    fn blank_amounts_follow_the_null_amount_policy() {
+        let path = csv_file(
+            "null-amount",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,,99.00,Cafe,Debit",
+            ],
+        );
+
+        let zero = read_file(&path, &csv_config(&["--null-amount-policy", "zero"])).unwrap();
+        assert_eq!((zero.records.len(), zero.amount_zeroed), (2, 1));
+        assert_eq!(zero.records[1].amount.to_string(), "0.00");
+
+        let skip = read_file(&path, &csv_config(&["--null-amount-policy", "skip"])).unwrap();
+        assert_eq!((skip.records.len(), skip.rejected), (1, 1));
+        assert_eq!(skip.amount_zeroed, 0);
+
+        assert!(read_file(&path, &csv_config(&["--null-amount-policy", "error"])).is_err());
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_dropped_insert_mismatches_the_totals() {
+        let importer = memory_importer(Config {
+            verify_totals: true,
+            ..Config::default()
+        });
+        let rows = vec![record("BOFA", 1, 1), record("BOFA", 2, 2)];
+        let memory = match importer.store() {
+            TransactionStore::Memory(memory) => memory,
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        // the second row is silently not stored
+        memory.insert("", &[&rows[0]], &crate::db::ConflictPolicy::Nothing);
+
+        let mut report = ImportReport::default();
+        block_on(importer.verify_totals(&rows, "", &mut report)).unwrap();
+        assert_eq!(report.totals_mismatched, 1);
+
+        memory.insert("", &[&rows[1]], &crate::db::ConflictPolicy::Nothing);
+        let mut report = ImportReport::default();
+        block_on(importer.verify_totals(&rows, "", &mut report)).unwrap();
+        assert_eq!(report.totals_mismatched, 0);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn the_output_table_holds_the_cleaned_rows() {
+        let path = csv_file(
+            "output-table",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,grocer,Debit",
+                "BOFA,1,01/01/2021,-1.00,99.00,grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,cafe,Debit",
+            ],
+        );
+        let importer = memory_importer(Config {
+            database: config::DatabaseConfig::from(config::app().get_matches_from(vec![
+                "csv-importer",
+                "--output-table",
+                "cleaned",
+            ])),
+            vendor_case: VendorCase::Upper,
+            deduplicate: true,
+            ..Config::default()
+        });
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+        assert_eq!(report.duplicates, 0);
+        assert_eq!(report.output.as_ref().unwrap().duplicates, 1);
+
+        let vendors = |table| {
+            stored(&importer, table)
+                .into_iter()
+                .map(|r| r.vendor)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vendors("transactions"), vec!["grocer", "cafe"]);
+        assert_eq!(vendors("cleaned"), vec!["GROCER", "CAFE"]);
+    }
+
+    #[test]
+    fn dr_cr_indicators_sign_the_amounts() {
+        let path = temp_dir("sign-column").join("transactions.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Amount,DRCR,Balance,Vendor,Type\n",
+                "BOFA,1,01/01/2021,12.50,DR,87.50,Grocer,Debit\n",
+                "BOFA,2,01/02/2021,100.00,CR,187.50,Payroll,Credit\n",
+                "BOFA,3,01/03/2021,2.00,d,185.50,Cafe,Debit\n",
+                "BOFA,4,01/04/2021,3.00,??,182.50,Cinema,Debit\n",
+            ),
+        )
+        .unwrap();
+
+        let parsed = read_file(&path, &csv_config(&["--sign-column", "DRCR"])).unwrap();
+        let amounts = parsed
+            .records
+            .iter()
+            .map(|r| r.amount.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec!["-12.50", "100.00", "-2.00"]);
+        assert_eq!(parsed.rejected, 1);
+
+        let parsed = read_file(
+            &path,
+            &csv_config(&["--sign-column", "DRCR", "--unknown-sign", "debit"]),
+        )
+        .unwrap();
+        assert_eq!(parsed.records[3].amount.to_string(), "-3.00");
+    }
+
+    #[test]
+    fn a_single_entry_map_remaps_only_its_column() {
+        let path = temp_dir("partial-map").join("transactions.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Value,Balance,Vendor,Type\n",
+                "BOFA,1,01/31/2021,-1.25,98.75,Grocer,Debit\n",
+            ),
+        )
+        .unwrap();
+
+        let parsed = read_file(&path, &csv_config(&["--column-map", "Value=Amount"])).unwrap();
+        assert_eq!(parsed.records[0].amount.to_string(), "-1.25");
+        assert_eq!(parsed.records[0].vendor, "Grocer");
+        assert_eq!(parsed.records[0].account, "BOFA");
+
+        // the amount is still required once the map is merged
+        assert!(read_file(&path, &csv_config(&["--column-map", "Shop=Vendor"])).is_err());
+    }
+
+    #[test]
+    fn a_pending_and_posted_pair_collapses_to_the_posted_row() {
+        let row = |id, day| {
+            let date = FixedOffset::east(0).ymd(2021, 1, day).and_hms(0, 0, 0);
+            CsvRecord::builder("BOFA", id, date)
+                .amount("-12.00".parse().unwrap())
+                .vendor("Grocer")
+                .build()
+        };
+        let mut rows = vec![row(1, 1), row(2, 3), row(3, 20)];
+        let mut report = ImportReport::default();
+
+        let dropped = deduplicate_window(&mut rows, Path::new("a.csv"), 3, &mut report);
+        assert_eq!(keys(&rows), vec!["BOFA/2", "BOFA/3"]);
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(report.duplicates, 1);
+    }
+
+    #[test]
+    fn overlong_vendors_follow_the_length_policy() {
+        let schema = TableSchema::from(vec![crate::db::TableColumn {
+            name: "vendor".to_string(),
+            max_length: Some(6),
+            data_type: Some("character varying".to_string()),
+        }]);
+        let rows = || {
+            let date = FixedOffset::east(0).ymd(2021, 1, 1).and_hms(0, 0, 0);
+            vec![
+                CsvRecord::builder("BOFA", 1, date).vendor("Grocer").build(),
+                CsvRecord::builder("BOFA", 2, date)
+                    .vendor("Supermarket")
+                    .build(),
+            ]
+        };
+
+        let mut truncated = rows();
+        let mut report = ImportReport::default();
+        enforce_lengths(
+            &mut truncated,
+            Some(&schema),
+            TooLongPolicy::Truncate,
+            &mut report,
+        );
+        assert_eq!(truncated[1].vendor, "Superm");
+        assert_eq!(report.rows_rejected, 0);
+
+        let mut rejected = rows();
+        let mut report = ImportReport::default();
+        enforce_lengths(
+            &mut rejected,
+            Some(&schema),
+            TooLongPolicy::Reject,
+            &mut report,
+        );
+        assert_eq!(keys(&rejected), vec!["BOFA/1"]);
+        assert_eq!(report.rows_rejected, 1);
+
+        let mut unchecked = rows();
+        enforce_lengths(
+            &mut unchecked,
+            Some(&schema),
+            TooLongPolicy::Off,
+            &mut report,
+        );
+        assert_eq!(unchecked[1].vendor, "Supermarket");
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn file_order_resume_imports_the_tail() {
+        let importer = memory_importer(Config {
+            load_options: LoadOptions::NewInFileOrder,
+            sort_rows: false,
+            ..Config::default()
+        });
+        let source = Path::new("file-order");
+
+        let first = vec![record("A", 30, 1), record("A", 10, 2)];
+        block_on(importer.import_records(source, first)).unwrap();
+
+        // the ids are not in order; resuming by id would skip 20 and 5
+        let second = vec![
+            record("A", 30, 1),
+            record("A", 10, 2),
+            record("A", 20, 3),
+            record("A", 5, 4),
+        ];
+        let report = block_on(importer.import_records(source, second)).unwrap();
+        assert_eq!(report.inserted, 2);
+        assert_eq!(report.skipped_by_filter, 2);
+        assert_eq!(
+            keys(&stored(&importer, "")),
+            ["A/5", "A/10", "A/20", "A/30"]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn files_of_a_directory_take_the_dialect_of_their_name() {
+        let dir = temp_dir("dialect-map");
+        write_csv(
+            &dir,
+            "a.csv",
+            &["BOFA,1,01/02/2021,-1.00,99.00,Grocer,Debit"],
+        );
+        std::fs::write(
+            dir.join("b.csv"),
+            concat!(
+                "ACCOUNT;ID;Date;Amount;Balance;Vendor;Type\n",
+                "BOFA;2;03.02.2021;-2.50;96.50;Cafe;Debit\n",
+            ),
+        )
+        .unwrap();
+        let dialects = temp_dir("dialect-map-rules").join("dialects.json");
+        std::fs::write(
+            &dialects,
+            r#"[{"pattern": "b*.csv", "delimiter": ";", "date_format": "%d.%m.%Y"}]"#,
+        )
+        .unwrap();
+
+        let importer = memory_importer(Config {
+            csv: csv_config(&["--dialect-map", dialects.to_str().unwrap()]),
+            ..Config::default()
+        });
+        let report = block_on(importer.import_directory(&dir)).unwrap();
+        assert_eq!((report.files, report.inserted), (2, 2));
+
+        let rows = stored(&importer, "");
+        assert_eq!(rows[1].vendor, "Cafe");
+        assert_eq!(rows[1].amount.to_string(), "-2.50");
+        assert_eq!(
+            rows[1].date.naive_local().date(),
+            chrono::NaiveDate::from_ymd(2021, 2, 3)
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn the_mirror_receives_the_same_rows() {
+        let mirror = TransactionStore::Memory(crate::memory::MemoryStore::default());
+        let importer = memory_importer(Config::default()).with_mirror(mirror);
+        let rows = vec![record("A", 1, 1), record("B", 2, 2)];
+
+        let report = block_on(importer.import_records(Path::new("mirrored"), rows)).unwrap();
+        assert_eq!((report.inserted, report.mirror_failed), (2, 0));
+
+        let mirrored = match &importer.mirror {
+            Some(TransactionStore::Memory(memory)) => memory.tables(),
+            _ => unreachable!(),
+        };
+        assert_eq!(mirrored.len(), 1);
+        assert_eq!(keys(&mirrored[0].1), keys(&stored(&importer, "")));
+        assert_eq!(keys(&mirrored[0].1), ["A/1", "B/2"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn running_balances_start_from_the_opening_balance() {
+        let importer = memory_importer(Config {
+            csv: csv_config(&["--opening-balance", "BOFA=100.00"]),
+            sort_rows: true,
+            ..Config::default()
+        });
+        let row = |account, id, day, amount: &str| {
+            let date = FixedOffset::east(0).ymd(2021, 1, day).and_hms(0, 0, 0);
+            CsvRecord::builder(account, id, date)
+                .amount(amount.parse().unwrap())
+                .build()
+        };
+        let mut rows = vec![
+            row("BOFA", 2, 2, "-20.00"),
+            row("BOFA", 1, 1, "-10.00"),
+            row("CHASE", 1, 1, "-1.00"),
+            row("BOFA", 3, 3, "5.00"),
+        ];
+        let mut report = ImportReport::default();
+
+        importer.derive_balances(&mut rows, &mut report);
+        let balances = rows
+            .iter()
+            .map(|r| (r.id, r.balance.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            balances,
+            vec![
+                (2, "70.00".to_string()),
+                (1, "90.00".to_string()),
+                (3, "75.00".to_string())
+            ]
+        );
+        assert_eq!(report.rows_rejected, 1);
+
+        // the balance carries over to the next file; a sum out of range rejects the row
+        let mut rows = vec![
+            row("BOFA", 4, 4, "-5.00"),
+            CsvRecord::builder(
+                "BOFA",
+                5,
+                FixedOffset::east(0).ymd(2021, 1, 5).and_hms(0, 0, 0),
+            )
+            .amount(Currency::from_minor_units(i64::MAX))
+            .build(),
+        ];
+        importer.derive_balances(&mut rows, &mut report);
+        assert_eq!(keys(&rows), vec!["BOFA/4"]);
+        assert_eq!(rows[0].balance.to_string(), "70.00");
+        assert_eq!(report.rows_rejected, 2);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn balances_follow_from_the_first_row() {
+        let importer = memory_importer(Config {
+            balance_anchor: Some(BalanceAnchor::First),
+            sort_rows: true,
+            ..Config::default()
+        });
+        let row = |id, day, amount: Currency, balance: &str| {
+            let date = FixedOffset::east(0).ymd(2021, 1, day).and_hms(0, 0, 0);
+            CsvRecord::builder("BOFA", id, date)
+                .amount(amount)
+                .balance(balance.parse().unwrap())
+                .build()
+        };
+        let mut rows = vec![
+            row(2, 2, "-10.00".parse().unwrap(), "90.00"),
+            row(1, 1, "-1.00".parse().unwrap(), "100.00"),
+            row(3, 3, "5.00".parse().unwrap(), "99.00"),
+            row(4, 4, Currency::from_minor_units(i64::MAX), "0.00"),
+        ];
+        let mut report = ImportReport::default();
+
+        importer.anchor_balances(&mut rows, Path::new("anchored.csv"), &mut report);
+        let balances = rows
+            .iter()
+            .map(|r| (r.id, r.balance.to_string()))
+            .collect::<Vec<_>>();
+        // the first row anchors the account, and the third states a balance that doesn't follow
+        assert_eq!(
+            balances,
+            vec![
+                (2, "90.00".to_string()),
+                (1, "100.00".to_string()),
+                (3, "95.00".to_string())
+            ]
+        );
+        assert_eq!(report.rows_balance_diverged, 1);
+        assert_eq!(report.rows_rejected, 1);
+    }
+
+    #[test]
+    fn rows_in_flight_never_exceed_the_maximum() {
+        let dir = temp_dir("in-flight");
+        let paths = (1..=8)
+            .map(|n| {
+                let rows = (0..n)
+                    .map(|i| format!("BOFA,{},01/01/2021,-1.00,99.00,Grocer,Debit", n * 10 + i))
+                    .collect::<Vec<_>>();
+                let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+                write_csv(&dir, &format!("{:02}.csv", n), &rows)
+            })
+            .collect::<Vec<_>>();
+        // a file of 8 rows has 10 lines, so only one or two files fit at a time
+        let budget = Arc::new(RowBudget::new(12));
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(4);
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let parser = runtime.spawn_blocking({
+            let (paths, budget) = (paths.clone(), budget.clone());
+            move || parse_files(paths, Arc::new(csv_config(&[])), pool, Some(budget), sender)
+        });
+        let loaded = runtime.block_on(async {
+            let mut loaded = Vec::new();
+            while let Some(file) = receiver.recv().await {
+                let parsed = file.parsed.await.ok().and_then(|p| p.ok()).unwrap();
+                assert!(budget.in_flight() <= 12);
+                // a slow load keeps the parser waiting on the budget
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                loaded.push((file.path, parsed.records.len()));
+            }
+            loaded
+        });
+        runtime.block_on(parser).unwrap();
+
+        assert_eq!(loaded, paths.into_iter().zip(1..=8).collect::<Vec<_>>());
+        let peak = budget.peak.load(Ordering::SeqCst);
+        assert!(peak > 0 && peak <= 12, "{}", peak);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn observers_count_the_events_of_an_import() {
+        let path = csv_file(
+            "events",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        /// counts the files started, rows parsed, chunks committed, and rows failed
+        struct Counter(Arc<Mutex<[usize; 4]>>);
+        impl ImportObserver for Counter {
+            fn on_event(&self, event: &ImportEvent) {
+                let i = match event {
+                    ImportEvent::FileStarted { .. } => 0,
+                    ImportEvent::RowParsed { .. } => 1,
+                    ImportEvent::ChunkCommitted { .. } => 2,
+                    ImportEvent::RowFailed { .. } => 3,
+                };
+                self.0.lock().unwrap()[i] += 1;
+            }
+        }
+        struct Faulty;
+        impl ImportObserver for Faulty {
+            fn on_event(&self, _: &ImportEvent) {
+                panic!("a faulty observer");
+            }
+        }
+
+        let counts = Arc::new(Mutex::new([0; 4]));
+        let importer = memory_importer(Config::default())
+            .with_observer(Box::new(Counter(counts.clone())))
+            .with_observer(Box::new(Faulty));
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+        assert_eq!(report.inserted, 2);
+        assert_eq!(*counts.lock().unwrap(), [1, 2, 1, 0]);
+    }
+
+    #[test]
+    fn spaced_headers_match_the_map_only_untrimmed() {
+        let path = temp_dir("no-trim-headers").join("transactions.csv");
+        // the spaced column holds the notes; the other is an unrelated column of the same name
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Amount,Balance,Vendor,Type,Memo, Memo\n",
+                "BOFA,1,01/31/2021,-1.00,99.00,Cafe,Debit,ref-77,coffee\n",
+            ),
+        )
+        .unwrap();
+        let notes = |args: &[&str]| {
+            read_file(&path, &csv_config(args))
+                .map(|parsed| {
+                    parsed
+                        .records
+                        .into_iter()
+                        .map(|r| r.notes)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default()
+        };
+
+        assert_eq!(
+            notes(&["--column-map", " Memo=Notes", "--no-trim-headers"]),
+            vec![Some("coffee".to_string())]
+        );
+        assert_ne!(
+            notes(&["--column-map", " Memo=Notes"]),
+            vec![Some("coffee".to_string())]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn the_preview_splits_new_and_stored_rows() {
+        let path = csv_file(
+            "preview-new",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,3,01/02/2021,-2.00,97.00,Cafe,Debit",
+                "BOFA,3,01/02/2021,-2.00,97.00,Cafe,Debit",
+                "CHASE,2,01/03/2021,-3.00,94.00,Cinema,Debit",
+            ],
+        );
+        let importer = memory_importer(Config {
+            source: Some(Source::File(path)),
+            ..Config::default()
+        });
+        // the ids are not in order, so only the keys tell which rows are stored
+        block_on(importer.import_records(
+            Path::new("seed"),
+            vec![record("BOFA", 3, 2), record("CHASE", 1, 1)],
+        ))
+        .unwrap();
+
+        let preview = block_on(importer.preview_new()).unwrap();
+        assert_eq!((preview.new, preview.existing), (2, 1));
+        assert_eq!(preview.accounts["BOFA"], (1, 1));
+        assert_eq!(preview.accounts["CHASE"], (1, 0));
+        assert_eq!(stored(&importer, "").len(), 2);
+    }
+
+    #[test]
+    fn the_benchmark_reports_a_positive_throughput() {
+        let path = csv_file(
+            "benchmark",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        let length = std::fs::metadata(&path).unwrap().len();
+
+        let benchmark = benchmark(&Source::File(path), &csv_config(&[]), 3).unwrap();
+        assert_eq!((benchmark.repeat, benchmark.rows), (3, 6));
+        assert_eq!(benchmark.bytes, 3 * length);
+        assert!(benchmark.rows_per_second() > 0.0);
+        assert!(benchmark.megabytes_per_second() > 0.0);
+    }
+
+    #[test]
+    fn the_primary_date_drives_the_order() {
+        let path = temp_dir("primary-date").join("transactions.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Posted,Amount,Balance,Vendor,Type\n",
+                "BOFA,1,01/05/2021,01/06/2021,-1.00,99.00,Grocer,Debit\n",
+                "BOFA,2,01/03/2021,01/08/2021,-2.00,97.00,Cafe,Debit\n",
+                "BOFA,3,01/04/2021,,-3.00,94.00,Cinema,Debit\n",
+            ),
+        )
+        .unwrap();
+        let mut rows = read_file(&path, &csv_config(&[])).unwrap().records;
+        assert!(rows[0].posted_date.is_some() && rows[2].posted_date.is_none());
+
+        sort_rows(&mut rows, Some(PrimaryDate::Transaction));
+        assert_eq!(keys(&rows), ["BOFA/2", "BOFA/3", "BOFA/1"]);
+        // a row without a posted date is ordered by its transaction date
+        sort_rows(&mut rows, Some(PrimaryDate::Posted));
+        assert_eq!(keys(&rows), ["BOFA/3", "BOFA/1", "BOFA/2"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_second_run_imports_rows_dated_after_the_first() {
+        let mut importer = memory_importer(Config {
+            since_last_run: true,
+            ..Config::default()
+        });
+        let source = Path::new("since-last-run");
+
+        // an empty table imports every row
+        block_on(importer.load_last_run()).unwrap();
+        let first = vec![record("A", 1, 1), record("A", 2, 2)];
+        let report = block_on(importer.import_records(source, first)).unwrap();
+        assert_eq!(report.inserted, 2);
+
+        block_on(importer.load_last_run()).unwrap();
+        let tomorrow =
+            (Utc::now() + chrono::Duration::days(1)).with_timezone(&FixedOffset::east(0));
+        let second = vec![
+            record("A", 1, 1),
+            record("A", 2, 2),
+            CsvRecord::builder("A", 3, tomorrow).build(),
+        ];
+        let report = block_on(importer.import_records(source, second)).unwrap();
+        assert_eq!((report.inserted, report.skipped_by_filter), (1, 2));
+        assert_eq!(keys(&stored(&importer, "")), ["A/1", "A/2", "A/3"]);
+    }
+
+    #[test]
+    fn swapped_amounts_and_balances_are_detected() {
+        let rows = [
+            ("-1.00", "99.00"),
+            ("-2.00", "97.00"),
+            ("-3.00", "94.00"),
+            ("10.00", "104.00"),
+        ];
+        let swapped = |swap: bool| {
+            let lines = rows
+                .iter()
+                .enumerate()
+                .map(|(i, (amount, balance))| {
+                    let (amount, balance) = if swap {
+                        (balance, amount)
+                    } else {
+                        (amount, balance)
+                    };
+                    format!(
+                        "BOFA,{},01/{:02}/2021,{},{},Cafe,Debit",
+                        i + 1,
+                        i + 1,
+                        amount,
+                        balance
+                    )
+                })
+                .collect::<Vec<_>>();
+            let lines = lines.iter().map(String::as_str).collect::<Vec<_>>();
+            let test = if swap {
+                "swapped-columns"
+            } else {
+                "mapped-columns"
+            };
+            let path = csv_file(test, &lines);
+            read_file(&path, &csv_config(&[]))
+                .map(|parsed| looks_swapped(&parsed.records))
+                .unwrap_or(false)
+        };
+
+        assert!(swapped(true));
+        assert!(!swapped(false));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn only_the_final_rows_are_tailed() {
+        let importer = memory_importer(Config {
+            tail: Some(2),
+            ..Config::default()
+        });
+        let records = (1..=4).map(|id| record("A", id, id as u32)).collect();
+
+        let report = block_on(importer.import_records(Path::new("tail"), records)).unwrap();
+
+        assert_eq!((report.inserted, report.skipped_by_filter), (2, 2));
+        assert_eq!(keys(&stored(&importer, "")), ["A/3", "A/4"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn only_rows_of_a_vendor_substring_are_imported() {
+        let importer = memory_importer(Config {
+            vendor_contains: vec!["amazon".to_string()],
+            ..Config::default()
+        });
+        let vendors = ["AMAZON MKTP US", "Corner Cafe", "www.Amazon.com"];
+        let records = vendors
+            .iter()
+            .zip(1..)
+            .map(|(vendor, id)| {
+                let date = FixedOffset::east(0)
+                    .ymd(2021, 1, id as u32)
+                    .and_hms(0, 0, 0);
+                CsvRecord::builder("A", id, date).vendor(vendor).build()
+            })
+            .collect();
+
+        let report = block_on(importer.import_records(Path::new("vendors"), records)).unwrap();
+
+        assert_eq!((report.inserted, report.skipped_by_filter), (2, 1));
+        assert_eq!(keys(&stored(&importer, "")), ["A/1", "A/3"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_tx_id_key_imports_a_single_ledger() {
+        let path = temp_dir("single-ledger").join("ledger.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ID,Date,Amount,Balance,Vendor,Type\n",
+                "1,01/30/2021,-1.00,99.00,Grocer,Debit\n",
+                "2,01/31/2021,-2.00,97.00,Cafe,Debit\n",
+            ),
+        )
+        .unwrap();
+        let importer = memory_importer(Config {
+            csv: csv_config(&["--key-columns", "tx_id"]),
+            ..Config::default()
+        });
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+
+        assert_eq!(report.inserted, 2);
+        assert_eq!(
+            keys(&stored(&importer, "")),
+            [
+                format!("{}/1", domain::LEDGER_ACCOUNT),
+                format!("{}/2", domain::LEDGER_ACCOUNT)
+            ]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn decomposed_vendors_are_composed() {
+        let importer = memory_importer(Config {
+            unicode_form: Some(crate::normalize::UnicodeForm::Nfc),
+            ..Config::default()
+        });
+        let date = FixedOffset::east(0).ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let decomposed = CsvRecord::builder("A", 1, date)
+            .vendor("Cafe\u{301}\u{200B} Paris")
+            .build();
+
+        block_on(importer.import_records(Path::new("unicode"), vec![decomposed])).unwrap();
+
+        let rows = stored(&importer, "");
+        assert_eq!(rows[0].vendor, "Caf\u{e9} Paris");
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn duplicate_ids_fail_the_file_when_required_unique() {
+        let records = || {
+            vec![
+                record("A", 1, 1),
+                record("A", 2, 2),
+                record("A", 1, 3),
+                record("B", 2, 4),
+                record("B", 2, 5),
+            ]
+        };
+
+        let importer = memory_importer(Config {
+            require_unique_ids: true,
+            ..Config::default()
+        });
+        let error = block_on(importer.import_records(Path::new("ids"), records()))
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default();
+        assert!(error.ends_with("duplicate ids: A/1, B/2"), "{}", error);
+        assert!(stored(&importer, "").is_empty());
+
+        // without the flag, the file imports
+        let importer = memory_importer(Config::default());
+        assert!(block_on(importer.import_records(Path::new("ids"), records())).is_ok());
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_currency_column_fills_the_currency_codes() {
+        let path = temp_dir("currency-column").join("transactions.csv");
+        std::fs::write(
+            &path,
+            concat!(
+                "ACCOUNT,ID,Date,Amount,Balance,Vendor,Type,Ccy\n",
+                "BOFA,1,01/30/2021,-1.00,99.00,Grocer,Debit,EUR\n",
+                "BOFA,2,01/31/2021,-2.00,97.00,Cafe,Debit,\n",
+            ),
+        )
+        .unwrap();
+        let importer = memory_importer(Config {
+            csv: csv_config(&["--currency-column", "Ccy", "--currency-code", "USD"]),
+            ..Config::default()
+        });
+
+        block_on(importer.import_file(&path)).unwrap();
+
+        let codes = stored(&importer, "")
+            .into_iter()
+            .map(|r| r.currency_code)
+            .collect::<Vec<_>>();
+        assert_eq!(codes, [Some("EUR".to_string()), Some("USD".to_string())]);
+    }
+
+    #[test]
+    fn invalid_utf8_fails_when_utf8_is_required() {
+        let path = temp_dir("require-utf8").join("transactions.csv");
+        let mut text = csv_text(&["BOFA,1,01/31/2021,-1.00,99.00,Caf"]).into_bytes();
+        // a latin-1 "é", as a corrupted download might have it
+        text.insert(text.len() - 1, 0xE9);
+        std::fs::write(&path, text).unwrap();
+
+        let error = read_file(&path, &csv_config(&["--require-utf8"]))
+            .err()
+            .map(|e| e.to_string())
+            .unwrap_or_default();
+        assert!(error.contains("not valid utf-8"), "{}", error);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn manifest_files_take_their_own_settings() {
+        let dir = temp_dir("manifest");
+        std::fs::write(
+            dir.join("chase.csv"),
+            concat!(
+                "ID,Date,Amount,Balance,Vendor,Type\n",
+                "1,01/31/2021,-1.00,99.00,Grocer,Debit\n",
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("amex.csv"),
+            concat!(
+                "ACCOUNT;ID;Date;Amount;Balance;Vendor;Type\n",
+                "AMEX;1;31.01.2021;-2.00;98.00;Cafe;Debit\n",
+            ),
+        )
+        .unwrap();
+        let manifest = dir.join("manifest.jsonl");
+        std::fs::write(
+            &manifest,
+            concat!(
+                "{\"path\": \"chase.csv\", \"account\": \"chase\"}\n",
+                "# european exports\n",
+                "{\"path\": \"amex.csv\", \"delimiter\": \";\", \"date_format\": \"%d.%m.%Y\"}\n",
+            ),
+        )
+        .unwrap();
+        let importer = memory_importer(Config::default());
+
+        let entries = crate::manifest::load(&manifest).unwrap();
+        let report = block_on(importer.import_manifest(&entries)).unwrap();
+
+        assert_eq!((report.files, report.inserted), (2, 2));
+        let rows = stored(&importer, "");
+        assert_eq!(keys(&rows), ["AMEX/1", "chase/1"]);
+        assert!(rows
+            .iter()
+            .all(|r| r.date.date().naive_local() == chrono::NaiveDate::from_ymd(2021, 1, 31)));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn the_dedup_report_lists_the_dropped_rows() {
+        let dir = temp_dir("dedup-report");
+        let a = write_csv(
+            &dir,
+            "a.csv",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        let b = write_csv(
+            &dir,
+            "b.csv",
+            &[
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+                "BOFA,3,01/03/2021,-3.00,94.00,Cinema,Debit",
+            ],
+        );
+        let path = dir.join("dropped.txt");
+        let importer = memory_importer(Config {
+            deduplicate: true,
+            dedup_report: Some(path.clone()),
+            ..Config::default()
+        });
+
+        block_on(importer.import_directory(&dir)).unwrap();
+        crate::report::write_dedup_report(&path, &importer.dropped_duplicates()).unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        let lines = written.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], "source,key,kept,account,id,date,amount,vendor");
+        assert_eq!(
+            lines[1..],
+            [format!(
+                "{},BOFA/2,{},BOFA,2,2021-01-02T00:00:00+00:00,-2.00,Cafe",
+                b.display(),
+                a.display()
+            )]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn rows_of_existing_accounts_are_skipped() {
+        let importer = memory_importer(Config {
+            skip_existing_accounts: true,
+            ..Config::default()
+        });
+        match importer.store() {
+            TransactionStore::Memory(memory) => memory.insert(
+                "",
+                &[&record("A", 1, 1)],
+                &crate::db::ConflictPolicy::Nothing,
+            ),
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        let records = vec![record("A", 2, 2), record("B", 1, 2), record("B", 2, 3)];
+
+        let report = block_on(importer.import_records(Path::new("new"), records)).unwrap();
+
+        assert_eq!((report.inserted, report.skipped_by_filter), (2, 1));
+        assert_eq!(keys(&stored(&importer, "")), ["A/1", "B/1", "B/2"]);
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn categories_are_deduplicated_into_the_lookup_table() {
+        let args = [
+            "csv-importer",
+            "--db_table",
+            "transactions",
+            "--normalize-categories",
+        ];
+        let importer = memory_importer(Config {
+            database: config::DatabaseConfig::from(config::app().get_matches_from(args)),
+            ..Config::default()
+        });
+        let categorized = |id: u64, category: &str, subcategory: &str| {
+            let date = FixedOffset::east(0).ymd(2021, 1, 1).and_hms(0, 0, 0);
+            CsvRecord::builder("A", id, date)
+                .category(category)
+                .subcategory(subcategory)
+                .build()
+        };
+        let records = vec![
+            categorized(1, "Food", "Groceries"),
+            categorized(2, "Food", "Dining"),
+            categorized(3, "Food", "Groceries"),
+            record("A", 4, 1),
+        ];
+
+        block_on(importer.import_records(Path::new("categories"), records)).unwrap();
+
+        let ids = stored(&importer, "transactions")
+            .into_iter()
+            .map(|r| r.category_id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [Some(1), Some(2), Some(1), None]);
+        // the lookup table holds each pair once
+        let pairs = [
+            ("Food".to_string(), "Dining".to_string()),
+            ("Food".to_string(), "Groceries".to_string()),
+        ];
+        let upserted = match importer.store() {
+            TransactionStore::Memory(memory) => memory.upsert_categories("categories", &pairs),
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        assert_eq!(
+            upserted
+                .into_iter()
+                .map(|(_, _, id)| id)
+                .collect::<Vec<_>>(),
+            [2, 1]
+        );
+    }
+
+    #[test]
+    fn files_are_read_through_the_configured_buffer() {
+        let config = csv_config(&["--read-buffer-bytes", "1048576"]);
+        assert_eq!(buffered(std::io::empty(), &config).capacity(), 1048576);
+
+        let path = csv_file(
+            "read-buffer",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        let read = read_file(&path, &config).map(|parsed| keys(&parsed.records));
+        assert_eq!(
+            read.ok(),
+            Some(vec!["BOFA/1".to_string(), "BOFA/2".to_string()])
+        );
+    }
+
+    #[test]
+    fn synthetic_ids_are_stable_across_parses() {
+        let rows = [
+            "BOFA,,01/01/2021,-1.00,99.00,Grocer,Debit",
+            "BOFA,,01/02/2021,-2.00,97.00,Cafe,Debit",
+            "BOFA,,01/03/2021,-3.00,94.00,Cinema,Debit",
+        ];
+        let first = csv_file("synthetic-ids-first", &rows);
+        let second = csv_file("synthetic-ids-second", &rows);
+        let config = csv_config(&["--id-from", "hash"]);
+        let ids = |path: &Path| {
+            read_file(path, &config)
+                .map(|parsed| parsed.records.iter().map(|r| r.id).collect::<Vec<_>>())
+                .unwrap_or_default()
+        };
+
+        let ids_first = ids(&first);
+        assert_eq!(ids_first.len(), 3);
+        assert_eq!(ids_first, ids(&second));
+        assert_eq!(ids_first.iter().unique().count(), 3);
+        // the whole 64-bit hash is kept
+        assert!(ids_first.iter().any(|id| *id > i32::MAX as u64));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_different_stored_row_with_the_synthetic_id_is_detected() {
+        let importer = memory_importer(Config {
+            csv: csv_config(&["--id-from", "hash"]),
+            ..Config::default()
+        });
+        let date = FixedOffset::east(0).ymd(2021, 1, 1).and_hms(0, 0, 0);
+        let mut row = CsvRecord::builder("A", 0, date).vendor("Grocer").build();
+        row.id = domain::synthetic_id(&row);
+        let other = CsvRecord::builder("A", row.id, date).vendor("Cafe").build();
+        match importer.store() {
+            TransactionStore::Memory(memory) => {
+                memory.insert("", &[&other], &crate::db::ConflictPolicy::Nothing)
+            }
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+
+        let report =
+            block_on(importer.import_records(Path::new("collision"), vec![row.clone()])).unwrap();
+        assert_eq!((report.rows_rejected, report.inserted), (1, 0));
+        assert_eq!(stored(&importer, "")[0].vendor, "Cafe");
+
+        // a re-import of the stored row is not a collision
+        let report = block_on(importer.import_records(Path::new("reimport"), vec![other])).unwrap();
+        assert_eq!((report.rows_rejected, report.conflicted), (0, 1));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn hooks_run_around_the_inserts() {
+        let path = csv_file(
+            "hooks",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-2.00,97.00,Cafe,Debit",
+            ],
+        );
+        let dir = path.parent().unwrap();
+        let before = dir.join("before.sql");
+        let after = dir.join("after.sql");
+        std::fs::write(&before, "SET session_replication_role = replica;").unwrap();
+        std::fs::write(&after, "SET session_replication_role = DEFAULT;").unwrap();
+        let args = [
+            "csv-importer",
+            "--before-sql",
+            before.to_str().unwrap(),
+            "--after-sql",
+            after.to_str().unwrap(),
+            "--hook-scope",
+            "run",
+        ];
+        let importer = memory_importer(Config {
+            database: config::DatabaseConfig::from(config::app().get_matches_from(args)),
+            source: Some(Source::File(path.clone())),
+            ..Config::default()
+        });
+
+        block_on(importer.import()).unwrap();
+
+        let hooks = match importer.store() {
+            TransactionStore::Memory(memory) => memory.hooks(),
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        assert_eq!(
+            hooks,
+            [
+                ("SET session_replication_role = replica;".to_string(), 0),
+                ("SET session_replication_role = DEFAULT;".to_string(), 2),
+            ]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn files_after_the_max_runtime_are_not_started() {
+        let dir = temp_dir("max-runtime");
+        write_csv(
+            &dir,
+            "1.csv",
+            &["BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit"],
+        );
+        write_csv(
+            &dir,
+            "2.csv",
+            &["BOFA,2,01/02/2021,-1.00,98.00,Grocer,Debit"],
+        );
+        let config = Config {
+            database: config::DatabaseConfig::default()
+                .with_max_runtime(std::time::Duration::from_millis(50)),
+            ..Config::default()
+        };
+        // the first file takes longer than the whole budget
+        struct Slow;
+        impl ImportObserver for Slow {
+            fn on_event(&self, event: &ImportEvent) {
+                if let ImportEvent::ChunkCommitted { .. } = event {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+            }
+        }
+        let importer = memory_importer(config).with_observer(Box::new(Slow));
+
+        let report = block_on(importer.import_directory(&dir)).unwrap();
+        assert!(report.timed_out);
+        assert_eq!(report.inserted, 1);
+        assert_eq!(keys(&stored(&importer, "")), vec!["BOFA/1"]);
+    }
+
+    #[test]
+    fn dates_of_a_file_are_in_the_configured_timezone() {
+        let path = csv_file("timezone", &["BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit"]);
+        let parsed = read_file(&path, &csv_config(&["--timezone=-05:00"])).unwrap();
+        assert_eq!(
+            parsed.records[0].date.to_rfc3339(),
+            "2021-01-31T00:00:00-05:00"
+        );
+        let parsed = read_file(&path, &csv_config(&[])).unwrap();
+        assert_eq!(
+            parsed.records[0].date.to_rfc3339(),
+            "2021-01-31T00:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn columns_no_field_reads_are_reported() {
+        let sources = csv::StringRecord::from(vec!["ACCOUNT", "ID", "Value", "Memo", "", "Sign"]);
+        let headers = csv::StringRecord::from(vec!["ACCOUNT", "ID", "Amount", "Memo", "", "Sign"]);
+        assert_eq!(unmapped_columns(&sources, &headers, Some(5)), vec!["Memo"]);
+        assert_eq!(
+            unmapped_columns(&sources, &headers, None),
+            vec!["Memo", "Sign"]
+        );
+
+        let path = temp_dir("strict-columns").join("transactions.csv");
+        std::fs::write(
+            &path,
+            "ACCOUNT,ID,Date,Value,Balance,Vendor,Type,Memo\n\
+             BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit,weekly shop\n",
+        )
+        .unwrap();
+        let reported = &["--column-map", "Value=Amount", "--report-unmapped-columns"];
+        assert_eq!(
+            read_file(&path, &csv_config(reported))
+                .unwrap()
+                .records
+                .len(),
+            1
+        );
+
+        let strict = &["--column-map", "Value=Amount", "--strict-columns"];
+        match read_file(&path, &csv_config(strict)) {
+            Err(e) => assert!(e.to_string().contains("unmapped column(s) Memo")),
+            Ok(_) => panic!("a file with an unmapped column was read"),
+        }
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn a_swap_replaces_the_target_once_loaded() {
+        struct Committed(Arc<Mutex<Vec<String>>>);
+        impl ImportObserver for Committed {
+            fn on_event(&self, event: &ImportEvent) {
+                if let ImportEvent::ChunkCommitted { table_name, .. } = event {
+                    self.0.lock().unwrap().push(table_name.to_string());
+                }
+            }
+        }
+
+        let path = csv_file(
+            "load-then-swap",
+            &[
+                "BOFA,2,01/02/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,3,01/03/2021,-1.00,98.00,Grocer,Debit",
+            ],
+        );
+        let config = Config {
+            source: Some(Source::File(path)),
+            database: config::DatabaseConfig::from(config::app().get_matches_from(vec![
+                "csv-importer",
+                "--db_table",
+                "tx",
+                "--load-then-swap",
+            ])),
+            ..Config::default()
+        };
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let importer =
+            memory_importer(config).with_observer(Box::new(Committed(committed.clone())));
+        match importer.store() {
+            TransactionStore::Memory(memory) => {
+                memory.insert(
+                    "tx",
+                    &[&record("BOFA", 1, 1)],
+                    &crate::db::ConflictPolicy::Nothing,
+                );
+            }
+            TransactionStore::Postgres(_) => unreachable!(),
+        }
+
+        block_on(importer.create_staging()).unwrap();
+        let report = block_on(importer.import()).unwrap();
+        assert_eq!(report.inserted, 2);
+        // the rows were only ever loaded into the staging table, leaving the target as it was
+        assert_eq!(*committed.lock().unwrap(), vec!["tx_staging"]);
+        assert_eq!(keys(&stored(&importer, "tx")), vec!["BOFA/2", "BOFA/3"]);
+        let tables = match importer.store() {
+            TransactionStore::Memory(memory) => memory.tables(),
+            TransactionStore::Postgres(_) => unreachable!(),
+        };
+        assert!(tables.iter().all(|(name, _)| name != "tx_staging"));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn concurrent_loads_of_an_account_resume_one_after_another() {
+        let importer = memory_importer(Config {
+            load_options: LoadOptions::New,
+            ..Config::default()
+        });
+        let rows = || (1..=3).map(|id| record("BOFA", id, id as u32)).collect();
+
+        let (first, second) = block_on(async {
+            tokio::join!(
+                importer.import_records(Path::new("first"), rows()),
+                importer.import_records(Path::new("second"), rows())
+            )
+        });
+        let (first, second) = (first.unwrap(), second.unwrap());
+        // the second load resumes after the rows of the first, rather than inserting them again
+        assert_eq!(first.inserted + second.inserted, 3);
+        assert_eq!(first.conflicted + second.conflicted, 0);
+        assert_eq!(
+            keys(&stored(&importer, "")),
+            vec!["BOFA/1", "BOFA/2", "BOFA/3"]
+        );
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn an_interrupted_file_resumes_after_its_checkpoint() {
+        let path = csv_file(
+            "checkpoint",
+            &[
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,2,01/02/2021,-1.00,98.00,Grocer,Debit",
+                "BOFA,3,01/03/2021,-1.00,97.00,Grocer,Debit",
+            ],
+        );
+        let checkpoint = path.with_file_name("checkpoint.json");
+        let importer = memory_importer(Config {
+            checkpoint: Some(checkpoint.clone()),
+            ..Config::default()
+        });
+
+        // the first run committed the first two rows before it was interrupted
+        let writer = crate::checkpoint::CheckpointWriter::new(&checkpoint, "");
+        writer.on_event(&ImportEvent::FileStarted { source: &path });
+        match importer.store() {
+            TransactionStore::Memory(memory) => {
+                let committed = [record("BOFA", 1, 1), record("BOFA", 2, 2)];
+                let committed = committed.iter().collect::<Vec<_>>();
+                memory.insert("", &committed, &crate::db::ConflictPolicy::Nothing);
+                writer.on_event(&ImportEvent::ChunkCommitted {
+                    table_name: "",
+                    rows: 2,
+                    last: committed.last().copied(),
+                });
+            }
+            TransactionStore::Postgres(_) => unreachable!(),
+        }
+
+        let report = block_on(importer.import_file(&path)).unwrap();
+        assert_eq!(report.skipped_by_filter, 2);
+        assert_eq!(report.inserted, 1);
+        assert_eq!(report.conflicted, 0);
+        assert_eq!(
+            keys(&stored(&importer, "")),
+            vec!["BOFA/1", "BOFA/2", "BOFA/3"]
+        );
+        // the file completed, so the next run starts from scratch
+        assert!(!checkpoint.exists());
+    }
+
+    #[test]
+    fn the_header_row_is_found_after_a_preamble() {
+        let dir = temp_dir("header-detect");
+        let row = "BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit";
+        let preambles = [
+            "",
+            "Statement of account\n\n",
+            "Bank of Example\nAccount: BOFA\nPeriod: January 2021\n\n",
+        ];
+        let config = csv_config(&["--header-detect", "10"]);
+        for (i, preamble) in preambles.iter().enumerate() {
+            let path = dir.join(format!("{}.csv", i));
+            std::fs::write(&path, format!("{}{}\n{}\n", preamble, HEADER, row)).unwrap();
+            let parsed = read_file(&path, &config).unwrap();
+            assert_eq!(keys(&parsed.records), vec!["BOFA/1"]);
+        }
+
+        // a header row past the lines searched is not found
+        let path = dir.join("late.csv");
+        std::fs::write(
+            &path,
+            format!("{}{}\n{}\n", "preamble\n".repeat(10), HEADER, row),
+        )
+        .unwrap();
+        match read_file(&path, &config) {
+            Err(e) => assert!(e
+                .to_string()
+                .contains("no header row in its first 10 lines")),
+            Ok(_) => panic!("a header row past the lines searched was found"),
+        }
+    }
+
+    #[test]
+    fn flexible_rows_may_omit_trailing_fields() {
+        let path = temp_dir("flexible").join("transactions.csv");
+        std::fs::write(
+            &path,
+            format!(
+                "{},Notes\n{}\n{}\n{}\n",
+                HEADER,
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit,weekly shop",
+                "BOFA,2,01/02/2021,-1.00,98.00,Grocer,Debit",
+                // the balance is missing, so the fields after it are shifted
+                "BOFA,3,01/03/2021,-1.00,Grocer,Debit",
+            ),
+        )
+        .unwrap();
+
+        let parsed = read_file(&path, &csv_config(&["--flexible"])).unwrap();
+        assert_eq!(keys(&parsed.records), vec!["BOFA/1", "BOFA/2"]);
+        assert_eq!(parsed.records[0].notes.as_deref(), Some("weekly shop"));
+        assert_eq!(parsed.records[1].notes.as_deref().unwrap_or(""), "");
+        assert_eq!(parsed.rejected, 1);
+
+        let parsed = read_file(&path, &csv_config(&[])).unwrap();
+        assert_eq!(keys(&parsed.records), vec!["BOFA/1"]);
+        assert_eq!(parsed.rejected, 2);
+    }
+
+    #[test]
+    fn dates_of_each_account_are_in_its_timezone() {
+        let path = csv_file(
+            "account-timezone",
+            &[
+                "CHASE,1,01/31/2021,-1.00,99.00,Grocer,Debit",
+                "N26,1,01/31/2021,-1.00,99.00,Grocer,Debit",
+                "BOFA,1,01/31/2021,-1.00,99.00,Grocer,Debit",
+            ],
+        );
+        let config = csv_config(&[
+            "--account-timezone",
+            "CHASE=America/New_York",
+            "--account-timezone",
+            "N26=Europe/Berlin",
+            "--timezone",
+            "+01:30",
+        ]);
+
+        let parsed = read_file(&path, &config).unwrap();
+        let instants = parsed
+            .records
+            .iter()
+            .map(|r| r.date.with_timezone(&Utc).to_rfc3339())
+            .collect::<Vec<_>>();
+        // the same midnight is a different instant in each account's zone; others take --timezone
+        assert_eq!(
+            instants,
+            vec![
+                "2021-01-31T05:00:00+00:00",
+                "2021-01-30T23:00:00+00:00",
+                "2021-01-30T22:30:00+00:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn source_lines_are_those_of_the_file() {
+        let dir = temp_dir("source-position");
+        let path = dir.join("statement.csv");
+        std::fs::write(
+            &path,
+            format!(
+                "Statement of account\n\n{}\n{}\n{}\n{}\n",
+                HEADER,
+                "BOFA,1,01/01/2021,-1.00,99.00,Grocer,Debit",
+                // a quoted vendor spanning two lines
+                "BOFA,2,01/02/2021,-1.00,98.00,\"Corner\nGrocer\",Debit",
+                "BOFA,3,01/03/2021,-1.00,97.00,Grocer,Debit",
+            ),
+        )
+        .unwrap();
+
+        let config = csv_config(&["--store-source-position", "--header-detect", "5"]);
+        let parsed = read_file(&path, &config).unwrap();
+        let positions = parsed
+            .records
+            .iter()
+            .map(|r| (r.source_file.clone().unwrap(), r.source_line.unwrap()))
+            .collect::<Vec<_>>();
+        let file = path.to_string_lossy().to_string();
+        assert_eq!(
+            positions,
+            vec![(file.clone(), 4), (file.clone(), 5), (file, 7)]
+        );
+
+        let parsed = read_file(&path, &csv_config(&["--header-detect", "5"])).unwrap();
+        assert!(parsed.records.iter().all(|r| r.source_line.is_none()));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn amounts_of_debit_types_are_made_negative() {
+        let raw = [("Debit", "debit"), ("Credit", "credit")]
+            .iter()
+            .map(|(t, direction)| (t.to_string(), direction.to_string()))
+            .collect();
+        let importer = memory_importer(Config {
+            type_signs: Some(crate::normalize::TypeSigns::from_map(raw, false).unwrap()),
+            ..Config::default()
+        });
+        let path = csv_file(
+            "sign-by-type",
+            &[
+                "BOFA,1,01/01/2021,12.34,87.66,Grocer,Debit",
+                "BOFA,2,01/02/2021,100.00,187.66,Employer,Credit",
+                "BOFA,3,01/03/2021,5.00,182.66,Bank,Fee",
+            ],
+        );
+
+        block_on(importer.import_file(&path)).unwrap();
+        let amounts = stored(&importer, "")
+            .iter()
+            .map(|r| r.amount.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(amounts, vec!["-12.34", "100.00", "5.00"]);
+    }
+}
@@ -0,0 +1,29 @@
+pub mod checkpoint;
+pub mod config;
+pub mod currency;
+pub mod db;
+pub mod dialect;
+pub mod domain;
+pub mod events;
+pub mod exit;
+pub mod export;
+pub mod groups;
+pub mod importer;
+pub mod manifest;
+pub mod mask;
+#[cfg(feature = "memory")]
+pub mod memory;
+pub mod normalize;
+pub mod notify;
+pub mod partition;
+pub mod prompt;
+pub mod report;
+pub mod selftest;
+pub mod state;
+pub mod store;
+pub mod transform;
+
+pub use importer::Importer;
+pub use report::ImportReport;
+
+pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
@@ -1,154 +1,241 @@
-mod config;
-mod currency;
-mod db;
-mod domain;
-
-use std::path::Path;
-
-use csv::ReaderBuilder;
-use itertools::Itertools;
-use log::{error, info};
-
-use crate::domain::LoadOptions;
-use config::DatabaseConfig;
-use domain::CsvRecord;
-
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
-type PgPool = sqlx::pool::Pool<sqlx::postgres::Postgres>;
+use log::{info, warn};
+
+use csv_importer::checkpoint::CheckpointWriter;
+use csv_importer::config::{self, Backend, Config};
+use csv_importer::exit::ExitCode;
+use csv_importer::groups::GroupReportFormat;
+#[cfg(feature = "memory")]
+use csv_importer::memory::MemoryStore;
+use csv_importer::report::DuplicateKey;
+use csv_importer::store::TransactionStore;
+use csv_importer::{
+    db, importer, notify, prompt, report, selftest, ImportReport, Importer, Result,
+};
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
     env_logger::init();
 
+    let code = match try_main().await {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitCode::of_error(e.as_ref())
+        }
+    };
+    std::process::exit(code.code());
+}
+
+/// runs the configured mode, returning the exit code of its outcome; see `ExitCode`
+async fn try_main() -> Result<ExitCode> {
     let config = match config::parse_args() {
         Ok(c) => c,
         Err(e) => return Err(e),
     };
 
-    let pool = match db::connect(&config.database).await {
-        Ok(p) => p,
-        Err(e) => return Err(Box::new(e)),
-    };
+    if config.self_test {
+        let report = selftest::run();
+        print!("{}", report);
+        return if report.passed() {
+            Ok(ExitCode::Success)
+        } else {
+            eprintln!("{} self-test case(s) failed.", report.failed());
+            Ok(ExitCode::Verification)
+        };
+    }
 
-    if config.database.is_init() {
-        if let Err(e) = db::init(&config.database, &pool).await {
-            return Err(Box::new(e));
-        }
+    if let (Some(n), Some(source)) = (config.sample, &config.source) {
+        importer::sample(source, &config.csv, n)?;
+        return Ok(ExitCode::Success);
+    }
+
+    if let (Some(options), Some(source)) = (&config.export, &config.source) {
+        importer::export(source, &config.csv, options)?;
+        return Ok(ExitCode::Success);
+    }
+
+    if let (Some(repeat), Some(source)) = (config.parse_only, &config.source) {
+        print!("{}", importer::benchmark(source, &config.csv, repeat)?);
+        return Ok(ExitCode::Success);
+    }
+
+    if config.explain {
+        let with_percentage = config.csv.get_percent_column().is_some();
+        print!("{}", db::explain(&config.database, with_percentage)?);
+        return Ok(ExitCode::Success);
     }
 
-    let options = config.load_options;
+    if let Some((old, new)) = &config.replace_account {
+        let pool = db::connect(&config.database).await?;
+        let table_name = config.database.get_table_name();
+
+        let affected = db::count_account_rows(old, &table_name, &pool).await?;
+        let prompt = format!(
+            "Rename account {} to {} in {} row(s) of {}?",
+            old, new, affected, table_name
+        );
+        if !prompt::confirm(&prompt, config.assume_yes)? {
+            println!("Not confirmed; no rows were renamed. Use --yes to confirm without asking.");
+            return Ok(ExitCode::Success);
+        }
+
+        let replaced = db::replace_account(old, new, &table_name, &pool).await?;
+        println!(
+            "Replaced account {} with {} in {} row(s) of {}.",
+            old, new, replaced, table_name
+        );
+        return Ok(ExitCode::Success);
+    }
 
-    match config.source {
-        config::Source::File(f) => {
-            import_file(&f, options, &config.database, &pool).await?;
+    if config.check_duplicates {
+        let pool = db::connect(&config.database).await?;
+        let table_name = config.database.get_table_name();
+        let key_columns = config.database.get_key_columns();
+
+        let duplicates = db::select_duplicate_keys(key_columns, &table_name, &pool)
+            .await?
+            .into_iter()
+            .map(|(values, rows)| DuplicateKey { values, rows })
+            .collect::<Vec<_>>();
+        if let Some(path) = &config.duplicates_file {
+            report::write_duplicate_keys(path, key_columns, &duplicates)?;
+        }
+        if duplicates.is_empty() {
+            println!(
+                "No key ({}) of {} is duplicated.",
+                key_columns.join(", "),
+                table_name
+            );
+            return Ok(ExitCode::Success);
         }
-        config::Source::Directory(dir) => {
-            import_directory(&dir, options, &config.database, &pool).await?;
+        for duplicate in &duplicates {
+            println!("{}", duplicate);
         }
+        println!(
+            "{} key(s) ({}) of {} are shared by several rows.",
+            duplicates.len(),
+            key_columns.join(", "),
+            table_name
+        );
+        return Ok(ExitCode::Verification);
     }
 
-    Ok(())
-}
+    if config.preview_new {
+        let pool = db::connect(&config.database).await?;
+        let preview = Importer::new(config, pool).preview_new().await?;
+        print!("{}", preview);
+        return Ok(ExitCode::Success);
+    }
+
+    let webhook_url = config.webhook_url.clone();
+    let report_path = config.report_path.clone();
+    let outcome = run(config).await;
 
-async fn import_directory(
-    f: &Path,
-    options: LoadOptions,
-    db_config: &DatabaseConfig,
-    pool: &PgPool,
-) -> Result<()> {
-    let paths = std::fs::read_dir(f)?;
+    if let Some(url) = webhook_url {
+        notify::post_webhook(&url, &outcome).await;
+    }
 
-    for entry in paths.flatten() {
-        let path = entry.path();
-        let _ = import_file(&path, options, db_config, pool).await;
+    let report = outcome?;
+    if let Some(path) = report_path {
+        report.write_json(&path)?;
     }
+    print!("{}", report);
 
-    Ok(())
+    Ok(ExitCode::of_report(&report))
 }
 
-async fn import_file(
-    f: &Path,
-    options: LoadOptions,
-    db_config: &DatabaseConfig,
-    pool: &PgPool,
-) -> Result<()> {
-    return match read_file(f) {
-        Ok(records) => {
-            load_rows(&records, options, db_config, pool).await?;
-            Ok(())
+/// connects to the configured backend, and imports the configured source
+async fn run(config: Config) -> Result<ImportReport> {
+    let store = match config.backend {
+        Backend::Postgres => {
+            let pool = match db::connect(&config.database).await {
+                Ok(p) => p,
+                Err(e) => return Err(Box::new(e)),
+            };
+
+            if config.database.is_init() {
+                if let Err(e) = db::init(&config.database, &pool).await {
+                    return Err(Box::new(e));
+                }
+            }
+
+            TransactionStore::Postgres(pool)
         }
-        Err(e) => {
-            error!("Could not read csv file. Aborting");
-            Err(e)
+        #[cfg(feature = "memory")]
+        Backend::Memory => {
+            info!("Using the in-memory backend; nothing will be written to a database.");
+            TransactionStore::Memory(MemoryStore::default())
         }
     };
-}
-
-async fn load_rows(
-    rows: &[CsvRecord],
-    options: LoadOptions,
-    db_config: &DatabaseConfig,
-    pool: &PgPool,
-) -> Result<()> {
-    let table_name = db_config.get_table_name();
 
-    match options {
-        LoadOptions::All => db::import(rows, &table_name, pool).await?,
-        LoadOptions::New => load_new_rows(rows, &table_name, pool).await?,
+    if config.database.is_append_only() {
+        info!("Append-only mode enabled; existing rows will never be modified or deleted.");
     }
 
-    Ok(())
-}
-
-async fn load_new_rows(rows: &[CsvRecord], table_name: &str, pool: &PgPool) -> Result<()> {
-    // group by account
-    for (account, group) in &rows.iter().group_by(|r| r.account.clone()) {
-        let account_rows = group.collect::<Vec<_>>();
-        if let Ok(max) = db::select_max_tx_for_account(&account, table_name, pool).await {
-            let to_import = account_rows
-                .iter()
-                .filter(|r| r.id > max as u64)
-                .cloned()
-                .collect::<Vec<_>>();
-
-            if !to_import.is_empty() {
-                info!(
-                    "Resuming import for account {} after tx {}. Attempting to import {} new rows.",
-                    account,
-                    max,
-                    to_import.len()
+    let mirror = match &config.mirror_url {
+        Some(url) => match connect_mirror(url, &config).await {
+            Ok(mirror) => Some(mirror),
+            Err(e) if config.mirror_required => return Err(e),
+            Err(e) => {
+                warn!(
+                    "The mirror is unavailable; rows will not be mirrored: {}",
+                    e
                 );
-                let _ = db::import_refs(&to_import, table_name, pool).await?;
+                None
             }
+        },
+        None => None,
+    };
+
+    let checkpoint = config
+        .checkpoint
+        .as_ref()
+        .map(|path| CheckpointWriter::new(path, &config.database.get_table_name()));
+    let mut importer = Importer::with_store(config, store);
+    if let Some(checkpoint) = checkpoint {
+        importer = importer.with_observer(Box::new(checkpoint));
+    }
+    if let Some(mirror) = mirror {
+        importer = importer.with_mirror(mirror);
+    }
+    importer.create_staging().await?;
+    importer.load_schema().await?;
+    importer.load_last_run().await?;
+    let report = importer.import().await?;
+
+    match importer.config().group_report {
+        Some(GroupReportFormat::Table) => print!("{}", importer.group_report()),
+        Some(GroupReportFormat::Json) => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&importer.group_report())?
+            )
         }
+        None => (),
     }
 
-    Ok(())
-}
+    if let Some(path) = &importer.config().dedup_report {
+        report::write_dedup_report(path, &importer.dropped_duplicates())?;
+    }
 
-fn read_file(f: &Path) -> Result<Vec<CsvRecord>> {
-    let abs_path = f.canonicalize()?;
-    info!("Reading csv records from file {:?}", abs_path);
+    #[cfg(feature = "memory")]
+    if let TransactionStore::Memory(memory) = importer.store() {
+        for (table_name, rows) in memory.tables() {
+            println!("Stored {} row(s) in {}:", rows.len(), table_name);
+            rows.iter().for_each(|row| println!("{}", row));
+        }
+    }
 
-    let mut records = Vec::new();
-    let mut reader = ReaderBuilder::new().trim(csv::Trim::Headers).from_path(f)?;
+    Ok(report)
+}
 
-    let mut bad_rows = 0;
+/// connects to the mirror, initializing it along with the database
+async fn connect_mirror(url: &str, config: &Config) -> Result<TransactionStore> {
+    let pool = db::connect_url(url).await?;
 
-    for result in reader.deserialize::<CsvRecord>() {
-        match result {
-            Ok(record) => records.push(record),
-            Err(e) => {
-                error!("Skipping row Could not read row: {}", e);
-                bad_rows += 1;
-            }
-        }
+    if config.database.is_init() {
+        db::init(&config.database, &pool).await?;
     }
 
-    info!(
-        "Read {} records from file. {} rows ignored because they could not be loaded.",
-        records.len(),
-        bad_rows
-    );
-    Ok(records)
+    Ok(TransactionStore::Postgres(pool))
 }
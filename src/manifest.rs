@@ -0,0 +1,59 @@
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+/// A file listed by a manifest, with the settings overriding those of the command line and the
+/// dialect map for that file alone
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestEntry {
+    path: PathBuf,
+    delimiter: Option<char>,
+    /// a chrono format pattern, such as `%d.%m.%Y`
+    date_format: Option<String>,
+    account: Option<String>,
+}
+
+impl ManifestEntry {
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_delimiter(&self) -> Option<char> {
+        self.delimiter
+    }
+
+    pub fn get_date_format(&self) -> Option<&str> {
+        self.date_format.as_deref()
+    }
+
+    pub fn get_account(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+}
+
+/// loads the entries of a manifest, one JSON object per line, in order. Blank lines and lines
+/// starting with `#` are ignored, and relative paths are relative to the manifest's directory.
+pub fn load(path: &Path) -> Result<Vec<ManifestEntry>, String> {
+    let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut entries = Vec::new();
+    for (index, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut entry: ManifestEntry =
+            serde_json::from_str(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        if entry.delimiter.map_or(false, |c| !c.is_ascii()) {
+            return Err(format!("line {}: the delimiter is not ascii", index + 1));
+        }
+        entry.path = base.join(&entry.path);
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::hash::Hasher;
+use std::str::FromStr;
+
+use siphasher::sip128::{Hasher128, SipHasher13};
+
+use crate::domain::CsvRecord;
+
+/// A field that may be masked on import
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaskedField {
+    Account,
+    Digits,
+    Vendor,
+}
+
+impl FromStr for MaskedField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "account" => Ok(MaskedField::Account),
+            "digits" => Ok(MaskedField::Digits),
+            "vendor" => Ok(MaskedField::Vendor),
+            other => Err(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for MaskedField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskedField::Account => write!(f, "account"),
+            MaskedField::Digits => write!(f, "digits"),
+            MaskedField::Vendor => write!(f, "vendor"),
+        }
+    }
+}
+
+/// Replaces sensitive fields with a keyed hash. The same value and key always produce
+/// the same mask, so masked values can still be grouped and joined, but they cannot be
+/// reversed without the key.
+pub struct Masker {
+    keys: (u64, u64),
+    fields: Vec<MaskedField>,
+}
+
+impl Masker {
+    pub fn new(key: &str, fields: Vec<MaskedField>) -> Self {
+        let mut hasher = SipHasher13::new_with_keys(0, 0);
+        hasher.write(key.as_bytes());
+        let hash = hasher.finish128();
+
+        Self {
+            keys: (hash.h1, hash.h2),
+            fields,
+        }
+    }
+
+    /// the keyed hash of the value, as hex
+    pub fn mask(&self, value: &str) -> String {
+        let mut hasher = SipHasher13::new_with_keys(self.keys.0, self.keys.1);
+        hasher.write(value.as_bytes());
+        let hash = hasher.finish128();
+
+        format!("{:016x}{:016x}", hash.h1, hash.h2)
+    }
+
+    /// masks the configured fields of the record in place
+    pub fn apply(&self, record: &mut CsvRecord) {
+        for field in &self.fields {
+            match field {
+                MaskedField::Account => record.account = self.mask(&record.account),
+                MaskedField::Vendor => record.vendor = self.mask(&record.vendor),
+                MaskedField::Digits => {
+                    record.digits = record.digits.as_deref().map(|d| self.mask(d));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    fn record() -> CsvRecord {
+        let date = FixedOffset::east(0).ymd(2021, 1, 31).and_hms(0, 0, 0);
+        CsvRecord::builder("BOFA", 1, date)
+            .vendor("Grocer")
+            .digits("1234")
+            .build()
+    }
+
+    #[test]
+    fn masks_are_deterministic_per_key() {
+        let masker = Masker::new("secret", vec![MaskedField::Account, MaskedField::Digits]);
+        assert_eq!(masker.mask("BOFA"), masker.mask("BOFA"));
+        assert_eq!(
+            masker.mask("BOFA"),
+            Masker::new("secret", Vec::new()).mask("BOFA")
+        );
+        assert_ne!(
+            masker.mask("BOFA"),
+            Masker::new("other", Vec::new()).mask("BOFA")
+        );
+        assert_ne!(masker.mask("BOFA"), masker.mask("CHASE"));
+        assert_eq!(masker.mask("BOFA").len(), 32);
+    }
+
+    #[test]
+    fn only_the_configured_fields_are_masked() {
+        let masker = Masker::new("secret", vec![MaskedField::Account, MaskedField::Digits]);
+        let mut masked = record();
+        masker.apply(&mut masked);
+
+        assert_eq!(masked.account, masker.mask("BOFA"));
+        assert_eq!(masked.digits, Some(masker.mask("1234")));
+        assert_eq!(masked.vendor, "Grocer");
+    }
+}
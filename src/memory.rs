@@ -0,0 +1,366 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::db::{ConflictPolicy, InsertOutcome, StoredFields};
+use crate::domain::{CsvRecord, PrimaryDate};
+use crate::report::ImportReport;
+
+/// The rows of a table, keyed like the table by (account, tx_id)
+type Table = BTreeMap<(String, u64), CsvRecord>;
+
+/// Stores transactions in memory, by table name. Nothing outlives the process; this backend
+/// exists for demos, and to exercise imports without a database.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    tables: Mutex<BTreeMap<String, Table>>,
+    /// the ids of the (category, subcategory) pairs, by categories table name
+    categories: Mutex<BTreeMap<String, BTreeMap<(String, String), i32>>>,
+    /// when rows were last inserted or updated, by table name, like the latest `imported_at`
+    last_import: Mutex<BTreeMap<String, DateTime<Utc>>>,
+    /// the statements of the hooks run, with the number of rows stored when each ran
+    hooks: Mutex<Vec<(String, usize)>>,
+}
+
+impl MemoryStore {
+    /// inserts the records, handling existing rows according to the conflict policy. Comparison
+    /// columns are not supported; with `ConflictPolicy::Update`, existing rows are always replaced.
+    pub fn insert(
+        &self,
+        table_name: &str,
+        records: &[&CsvRecord],
+        conflict: &ConflictPolicy,
+    ) -> ImportReport {
+        let mut tables = self.tables.lock().unwrap();
+        let rows = tables.entry(table_name.to_string()).or_default();
+        let mut report = ImportReport::default();
+
+        for row in records {
+            let key = (row.account.clone(), row.id);
+            let outcome = match (rows.contains_key(&key), conflict) {
+                (false, _) => InsertOutcome::Inserted,
+                (true, ConflictPolicy::Nothing) => InsertOutcome::Conflicted,
+                (true, ConflictPolicy::Update { .. }) => InsertOutcome::Updated,
+            };
+
+            if outcome != InsertOutcome::Conflicted {
+                let mut row = (*row).clone();
+                if let (
+                    Some(existing),
+                    ConflictPolicy::Update {
+                        merge_notes: true, ..
+                    },
+                ) = (rows.get(&key), conflict)
+                {
+                    row.notes = merged_notes(existing.notes.as_deref(), row.notes.as_deref());
+                }
+                rows.insert(key, row);
+            }
+            report.record_insert(&row.account, outcome);
+        }
+
+        if report.inserted + report.updated > 0 {
+            self.last_import
+                .lock()
+                .unwrap()
+                .insert(table_name.to_string(), Utc::now());
+        }
+        report
+    }
+
+    /// when rows were last inserted or updated in the table
+    pub fn last_import(&self, table_name: &str) -> Option<DateTime<Utc>> {
+        self.last_import.lock().unwrap().get(table_name).copied()
+    }
+
+    /// the ids of the (category, subcategory) pairs, numbering new pairs from 1 like a serial
+    /// column
+    pub fn upsert_categories(
+        &self,
+        categories_table: &str,
+        pairs: &[(String, String)],
+    ) -> Vec<(String, String, i32)> {
+        let mut categories = self.categories.lock().unwrap();
+        let ids = categories.entry(categories_table.to_string()).or_default();
+
+        pairs
+            .iter()
+            .map(|pair| {
+                let next = ids.len() as i32 + 1;
+                let id = *ids.entry(pair.clone()).or_insert(next);
+                (pair.0.clone(), pair.1.clone(), id)
+            })
+            .collect()
+    }
+
+    /// records the statements of a hook, which can't be run without a database
+    pub fn execute_hook(&self, sql: &str) {
+        let stored = self
+            .tables
+            .lock()
+            .unwrap()
+            .values()
+            .map(|rows| rows.len())
+            .sum();
+        self.hooks.lock().unwrap().push((sql.to_string(), stored));
+    }
+
+    /// the statements of the hooks run, in order, with the number of rows stored when each ran
+    pub fn hooks(&self) -> Vec<(String, usize)> {
+        self.hooks.lock().unwrap().clone()
+    }
+
+    /// (re)creates the staging table, empty
+    pub fn create_staging(&self, staging: &str) {
+        let mut tables = self.tables.lock().unwrap();
+        tables.insert(staging.to_string(), Table::new());
+    }
+
+    /// replaces the target table with the staging table
+    pub fn swap_tables(&self, target: &str, staging: &str) {
+        let mut tables = self.tables.lock().unwrap();
+        let rows = tables.remove(staging).unwrap_or_default();
+        tables.insert(target.to_string(), rows);
+    }
+
+    pub fn drop_table(&self, table_name: &str) {
+        self.tables.lock().unwrap().remove(table_name);
+    }
+
+    /// renames the account of every row of the table, like `db::replace_account`, returning the
+    /// number of rows renamed; None, renaming no rows, if the new account has a row of the same id
+    pub fn replace_account(&self, table_name: &str, old: &str, new: &str) -> Option<u64> {
+        let mut tables = self.tables.lock().unwrap();
+        let rows = match tables.get_mut(table_name) {
+            Some(rows) => rows,
+            None => return Some(0),
+        };
+
+        let ids = rows
+            .keys()
+            .filter(|(account, _)| account == old)
+            .map(|(_, id)| *id)
+            .collect::<Vec<_>>();
+        if old != new
+            && ids
+                .iter()
+                .any(|id| rows.contains_key(&(new.to_string(), *id)))
+        {
+            return None;
+        }
+
+        for id in &ids {
+            let mut row = rows.remove(&(old.to_string(), *id)).unwrap();
+            row.account = new.to_string();
+            rows.insert((new.to_string(), *id), row);
+        }
+        Some(ids.len() as u64)
+    }
+
+    /// the max id of the account's stored rows, ordered like the bigint column the ids are
+    /// stored in
+    pub fn max_tx_for_account(&self, table_name: &str, account: &str) -> Option<i64> {
+        self.tables
+            .lock()
+            .unwrap()
+            .get(table_name)?
+            .keys()
+            .filter(|(a, _)| a == account)
+            .map(|(_, id)| *id as i64)
+            .max()
+    }
+
+    /// the latest primary date of the account's stored rows
+    pub fn max_date_for_account(
+        &self,
+        table_name: &str,
+        account: &str,
+        primary: PrimaryDate,
+    ) -> Option<NaiveDate> {
+        self.tables
+            .lock()
+            .unwrap()
+            .get(table_name)?
+            .values()
+            .filter(|r| r.account == account)
+            .map(|r| primary.of(r).naive_utc().date())
+            .max()
+    }
+
+    /// the given transactions of the account that are stored
+    pub fn existing_tx_ids(&self, table_name: &str, account: &str, ids: &[i64]) -> Vec<i64> {
+        let tables = self.tables.lock().unwrap();
+        let rows = match tables.get(table_name) {
+            Some(rows) => rows,
+            None => return Vec::new(),
+        };
+
+        ids.iter()
+            .filter(|id| rows.contains_key(&(account.to_string(), **id as u64)))
+            .copied()
+            .collect()
+    }
+
+    /// the given (account, id) keys that are stored
+    pub fn existing_keys(&self, table_name: &str, keys: &[(String, i64)]) -> Vec<(String, i64)> {
+        let tables = self.tables.lock().unwrap();
+        let rows = match tables.get(table_name) {
+            Some(rows) => rows,
+            None => return Vec::new(),
+        };
+
+        keys.iter()
+            .filter(|(account, id)| rows.contains_key(&(account.clone(), *id as u64)))
+            .cloned()
+            .collect()
+    }
+
+    /// the amount, vendor, and type of the stored rows of the given (account, id) keys
+    pub fn stored_fields(&self, table_name: &str, keys: &[(String, i64)]) -> Vec<StoredFields> {
+        let tables = self.tables.lock().unwrap();
+        let rows = match tables.get(table_name) {
+            Some(rows) => rows,
+            None => return Vec::new(),
+        };
+
+        keys.iter()
+            .filter_map(|(account, id)| rows.get(&(account.clone(), *id as u64)))
+            .map(|r| {
+                (
+                    r.account.clone(),
+                    r.id as i64,
+                    r.amount.to_minor_units(),
+                    r.vendor.clone(),
+                    r.transaction_type.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// the number of stored rows, and the sum of their amounts in hundredths, of the given
+    /// transactions of the account
+    pub fn totals(&self, table_name: &str, account: &str, ids: &[i64]) -> (i64, i64) {
+        self.tables
+            .lock()
+            .unwrap()
+            .get(table_name)
+            .into_iter()
+            .flat_map(|rows| rows.values())
+            .filter(|r| r.account == account && ids.contains(&(r.id as i64)))
+            .fold((0, 0), |(count, sum), r| {
+                (count + 1, sum + r.amount.to_minor_units())
+            })
+    }
+
+    /// the stored rows of each table, ordered by table name, then account and id
+    pub fn tables(&self) -> Vec<(String, Vec<CsvRecord>)> {
+        self.tables
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(table_name, rows)| (table_name.clone(), rows.values().cloned().collect()))
+            .collect()
+    }
+}
+
+/// appends the incoming notes to the existing notes, unless they are empty or already included
+fn merged_notes(existing: Option<&str>, incoming: Option<&str>) -> Option<String> {
+    match (
+        existing.filter(|e| !e.is_empty()),
+        incoming.filter(|i| !i.is_empty()),
+    ) {
+        (None, incoming) => incoming.map(|i| i.to_string()),
+        (Some(existing), Some(incoming)) if !existing.contains(incoming) => {
+            Some(format!("{}; {}", existing, incoming))
+        }
+        (Some(existing), _) => Some(existing.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    fn record(notes: &str) -> CsvRecord {
+        let date = FixedOffset::east(0).ymd(2021, 1, 31).and_hms(0, 0, 0);
+        CsvRecord::builder("BOFA", 1, date).notes(notes).build()
+    }
+
+    #[test]
+    fn notes_are_appended_unless_included() {
+        assert_eq!(
+            merged_notes(Some("paid"), Some("refunded")).as_deref(),
+            Some("paid; refunded")
+        );
+        assert_eq!(
+            merged_notes(Some("paid; refunded"), Some("refunded")).as_deref(),
+            Some("paid; refunded")
+        );
+        assert_eq!(merged_notes(None, Some("paid")).as_deref(), Some("paid"));
+        assert_eq!(
+            merged_notes(Some("paid"), Some("")).as_deref(),
+            Some("paid")
+        );
+        assert_eq!(merged_notes(Some(""), None), None);
+    }
+
+    #[test]
+    fn upserts_merge_notes() {
+        let store = MemoryStore::default();
+        let conflict = ConflictPolicy::Update {
+            key: vec!["account".to_string(), "tx_id".to_string()],
+            compare: None,
+            merge_notes: true,
+        };
+
+        store.insert("transactions", &[&record("paid")], &conflict);
+        let report = store.insert("transactions", &[&record("refunded")], &conflict);
+        assert_eq!(report.updated, 1);
+        let rows = &store.tables()[0].1;
+        assert_eq!(rows[0].notes.as_deref(), Some("paid; refunded"));
+    }
+
+    #[test]
+    fn accounts_are_merged_unless_ids_collide() {
+        let store = MemoryStore::default();
+        let date = FixedOffset::east(0).ymd(2021, 1, 31).and_hms(0, 0, 0);
+        let rows = [
+            CsvRecord::builder("BofA", 1, date).build(),
+            CsvRecord::builder("BofA", 2, date).build(),
+            CsvRecord::builder("BOFA", 3, date).build(),
+        ];
+        store.insert(
+            "transactions",
+            &rows.iter().collect::<Vec<_>>(),
+            &ConflictPolicy::Nothing,
+        );
+
+        assert_eq!(
+            store.replace_account("transactions", "BofA", "BOFA"),
+            Some(2)
+        );
+        let accounts = store.tables()[0]
+            .1
+            .iter()
+            .map(|r| (r.account.clone(), r.id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            accounts,
+            vec![
+                ("BOFA".to_string(), 1),
+                ("BOFA".to_string(), 2),
+                ("BOFA".to_string(), 3)
+            ]
+        );
+
+        store.insert(
+            "transactions",
+            &[&CsvRecord::builder("BofA", 3, date).build()],
+            &ConflictPolicy::Nothing,
+        );
+        assert_eq!(store.replace_account("transactions", "BofA", "BOFA"), None);
+    }
+}
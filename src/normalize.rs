@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+use log::warn;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::currency::Currency;
+
+/// The casing applied to vendor names, so that "amazon", "AMAZON", and "Amazon" are grouped together
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VendorCase {
+    Upper,
+    Lower,
+    Title,
+    None,
+}
+
+impl Default for VendorCase {
+    fn default() -> Self {
+        VendorCase::None
+    }
+}
+
+impl VendorCase {
+    pub fn apply(&self, vendor: &str) -> String {
+        match self {
+            VendorCase::Upper => vendor.to_uppercase(),
+            VendorCase::Lower => vendor.to_lowercase(),
+            VendorCase::Title => title_case(vendor),
+            VendorCase::None => vendor.to_string(),
+        }
+    }
+}
+
+/// The Unicode normalization form applied to text values, so that composed and decomposed
+/// accents (and, with NFKC, full-width characters) compare equal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnicodeForm {
+    Nfc,
+    Nfkc,
+}
+
+/// Invisible characters that split otherwise equal text: zero-width space, non-joiner, and
+/// joiner, the word joiner, and the byte order mark
+const ZERO_WIDTH: [char; 5] = ['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+impl UnicodeForm {
+    /// strips zero-width characters, and normalizes the rest of the text to the form
+    pub fn apply(&self, text: &str) -> String {
+        let visible = text.chars().filter(|c| !ZERO_WIDTH.contains(c));
+        match self {
+            UnicodeForm::Nfc => visible.nfc().collect(),
+            UnicodeForm::Nfkc => visible.nfkc().collect(),
+        }
+    }
+}
+
+/// Canonical transaction types, by the raw types banks spell differently, e.g. "POS",
+/// "Point of Sale", and "PURCHASE"; raw types are matched ignoring case and surrounding whitespace
+#[derive(Debug, Clone, Default)]
+pub struct TypeMap {
+    types: HashMap<String, String>,
+    /// whether a type without a mapping is warned about; it is kept as is either way
+    warn_unmapped: bool,
+}
+
+impl TypeMap {
+    /// loads the map from a JSON file holding an object of raw types to canonical types
+    pub fn load(path: &Path, warn_unmapped: bool) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        let raw: HashMap<String, String> =
+            serde_json::from_reader(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+
+        let types = raw
+            .into_iter()
+            .map(|(from, to)| (from.trim().to_lowercase(), to))
+            .collect();
+        Ok(Self {
+            types,
+            warn_unmapped,
+        })
+    }
+
+    /// the canonical type of the raw type, or the raw type itself when it is not mapped
+    pub fn apply(&self, transaction_type: &str) -> String {
+        match self.types.get(&transaction_type.trim().to_lowercase()) {
+            Some(canonical) => canonical.clone(),
+            None => {
+                if self.warn_unmapped {
+                    warn!("Transaction type {:?} is not mapped", transaction_type);
+                }
+                transaction_type.to_string()
+            }
+        }
+    }
+}
+
+/// The direction of transaction types, for banks that state every amount as positive and only
+/// tell debits from credits by the type; types are matched like the keys of a `TypeMap`
+#[derive(Debug, Clone, Default)]
+pub struct TypeSigns {
+    /// whether amounts of the type are negative, i.e. debits
+    negative: HashMap<String, bool>,
+    /// whether amounts that are already negative are kept as stated, rather than signed by type
+    keep_signed: bool,
+}
+
+impl TypeSigns {
+    /// loads the signs from a JSON file holding an object of types to `"debit"` or `"credit"`
+    pub fn load(path: &Path, keep_signed: bool) -> Result<Self, String> {
+        let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+        let raw: HashMap<String, String> =
+            serde_json::from_reader(io::BufReader::new(file)).map_err(|e| e.to_string())?;
+        Self::from_map(raw, keep_signed)
+    }
+
+    /// the signs of a map of types to `"debit"` or `"credit"`
+    pub fn from_map(raw: HashMap<String, String>, keep_signed: bool) -> Result<Self, String> {
+        let mut negative = HashMap::new();
+        for (transaction_type, direction) in raw {
+            let is_debit = match direction.trim().to_lowercase().as_str() {
+                "debit" => true,
+                "credit" => false,
+                _ => {
+                    return Err(format!(
+                        "{:?} is neither debit nor credit, for type {:?}",
+                        direction, transaction_type
+                    ))
+                }
+            };
+            negative.insert(transaction_type.trim().to_lowercase(), is_debit);
+        }
+        Ok(Self {
+            negative,
+            keep_signed,
+        })
+    }
+
+    /// the amount signed by the direction of its type; amounts of types without a direction,
+    /// and with `keep_signed` negative amounts, are returned as is
+    pub fn apply(&self, amount: &Currency, transaction_type: &str) -> Currency {
+        match self.negative.get(&transaction_type.trim().to_lowercase()) {
+            _ if self.keep_signed && amount.to_minor_units() < 0 => amount.clone(),
+            Some(negative) => amount.with_sign(*negative),
+            None => amount.clone(),
+        }
+    }
+}
+
+/// upper-cases the first letter of each word, and lower-cases the rest. Words are separated by
+/// whitespace and punctuation such as `-`, `/`, `.`, and `&`, but not apostrophes; so
+/// "TRADER JOE'S" becomes "Trader Joe's" and "coca-cola" becomes "Coca-Cola".
+fn title_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut start_of_word = true;
+
+    for c in s.chars() {
+        if c.is_alphanumeric() {
+            if start_of_word {
+                result.extend(c.to_uppercase());
+            } else {
+                result.extend(c.to_lowercase());
+            }
+            start_of_word = false;
+        } else {
+            result.push(c);
+            start_of_word = c != '\'' && c != '\u{2019}';
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vendor_case_modes() {
+        let vendor = "TRADER JOE'S coca-cola";
+        assert_eq!(VendorCase::Upper.apply(vendor), "TRADER JOE'S COCA-COLA");
+        assert_eq!(VendorCase::Lower.apply(vendor), "trader joe's coca-cola");
+        assert_eq!(VendorCase::Title.apply(vendor), "Trader Joe's Coca-Cola");
+        assert_eq!(VendorCase::None.apply(vendor), vendor);
+        assert_eq!(VendorCase::Title.apply("AT&T/MOBILE"), "At&T/Mobile");
+    }
+
+    #[test]
+    fn raw_types_map_to_one_canonical_type() {
+        let path =
+            std::env::temp_dir().join(format!("csv-importer-types-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"{"POS": "Purchase", "Point of Sale": "Purchase", "PURCHASE": "Purchase"}"#,
+        )
+        .unwrap();
+        let types = TypeMap::load(&path, true).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for raw in &["POS", "pos", " Point of Sale ", "point of sale", "PURCHASE"] {
+            assert_eq!(types.apply(raw), "Purchase", "{:?}", raw);
+        }
+        // unmapped types pass through
+        assert_eq!(types.apply("Transfer"), "Transfer");
+    }
+
+    #[test]
+    fn amounts_are_signed_by_type() {
+        let raw = [
+            ("Purchase", "debit"),
+            ("ATM", " Debit "),
+            ("Deposit", "credit"),
+        ]
+        .iter()
+        .map(|(t, direction)| (t.to_string(), direction.to_string()))
+        .collect::<HashMap<_, _>>();
+        let signs = TypeSigns::from_map(raw.clone(), false).unwrap();
+        let signed = |signs: &TypeSigns, amount: &str, transaction_type: &str| {
+            signs
+                .apply(&amount.parse().unwrap(), transaction_type)
+                .to_string()
+        };
+
+        assert_eq!(signed(&signs, "12.34", "Purchase"), "-12.34");
+        assert_eq!(signed(&signs, "20.00", " atm"), "-20.00");
+        assert_eq!(signed(&signs, "-100.00", "Deposit"), "100.00");
+        assert_eq!(signed(&signs, "5.00", "Transfer"), "5.00");
+        assert_eq!(signed(&signs, "-5.00", "Transfer"), "-5.00");
+
+        // amounts already negative are kept, while the others are still signed
+        let keeping = TypeSigns::from_map(raw, true).unwrap();
+        assert_eq!(signed(&keeping, "-12.34", "Deposit"), "-12.34");
+        assert_eq!(signed(&keeping, "12.34", "Purchase"), "-12.34");
+
+        let invalid = std::iter::once(("Fee".to_string(), "outgoing".to_string())).collect();
+        assert!(TypeSigns::from_map(invalid, false).is_err());
+    }
+}
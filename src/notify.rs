@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::report::ImportReport;
+
+/// How long to wait for the webhook before giving up
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The JSON body posted to the webhook
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    success: bool,
+    report: Option<&'a ImportReport>,
+    error: Option<String>,
+}
+
+impl<'a> WebhookPayload<'a> {
+    fn of(outcome: &'a crate::Result<ImportReport>) -> Self {
+        match outcome {
+            Ok(report) => WebhookPayload {
+                success: true,
+                report: Some(report),
+                error: None,
+            },
+            Err(e) => WebhookPayload {
+                success: false,
+                report: None,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+/// posts the outcome of an import to the webhook. Failing to notify never fails the import;
+/// errors (including timeouts) are only logged.
+pub async fn post_webhook(url: &str, outcome: &crate::Result<ImportReport>) {
+    let payload = WebhookPayload::of(outcome);
+
+    let client = match reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            warn!("Could not create the webhook client: {}", e);
+            return;
+        }
+    };
+
+    match client.post(url).json(&payload).send().await {
+        Ok(response) if response.status().is_success() => {
+            info!("Posted the import summary to the webhook.")
+        }
+        Ok(response) => warn!("The webhook responded with {}", response.status()),
+        Err(e) => warn!("Could not post the import summary to the webhook: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payload_carries_the_report_or_the_error() {
+        let imported = Ok(ImportReport {
+            inserted: 2,
+            ..ImportReport::default()
+        });
+        let json = serde_json::to_value(WebhookPayload::of(&imported)).unwrap();
+        assert_eq!(json["success"], true);
+        assert_eq!(json["report"]["inserted"], 2);
+        assert!(json["error"].is_null());
+
+        let failed: crate::Result<ImportReport> = Err("connection refused".into());
+        let json = serde_json::to_value(WebhookPayload::of(&failed)).unwrap();
+        assert_eq!(json["success"], false);
+        assert!(json["report"].is_null());
+        assert_eq!(json["error"], "connection refused");
+    }
+}
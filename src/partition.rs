@@ -0,0 +1,91 @@
+use chrono::{DateTime, Datelike, TimeZone};
+
+/// A table name with date macros, such as `transactions_{year}` or `tx_{yyyymm}`, resolved
+/// either once per run or for the date of each row
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    /// `{year}`, such as `2021`
+    Year,
+    /// `{month}`, such as `07`
+    Month,
+    /// `{yyyymm}`, such as `202107`
+    YearMonth,
+}
+
+impl TableTemplate {
+    /// parses the table name, failing on an unknown or unclosed macro
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = name;
+        while let Some(open) = rest.find(|c| c == '{' || c == '}') {
+            if rest[open..].starts_with('}') {
+                return Err(format!("unmatched }} in {}", name));
+            }
+            let close = match rest[open..].find('}') {
+                Some(close) => open + close,
+                None => return Err(format!("unclosed {{ in {}", name)),
+            };
+
+            if open > 0 {
+                parts.push(Part::Text(rest[..open].to_string()));
+            }
+            parts.push(match &rest[open + 1..close] {
+                "year" => Part::Year,
+                "month" => Part::Month,
+                "yyyymm" => Part::YearMonth,
+                other => return Err(format!("unknown macro {{{}}} in {}", other, name)),
+            });
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+
+        Ok(TableTemplate { parts })
+    }
+
+    /// whether the name has any macros, and so may name a different table per date
+    pub fn has_macros(&self) -> bool {
+        self.parts.iter().any(|p| !matches!(p, Part::Text(_)))
+    }
+
+    /// the name of the table for the date
+    pub fn resolve<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> String {
+        self.parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.clone(),
+                Part::Year => format!("{:04}", date.year()),
+                Part::Month => format!("{:02}", date.month()),
+                Part::YearMonth => format!("{:04}{:02}", date.year(), date.month()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::FixedOffset;
+
+    #[test]
+    fn rows_of_two_months_resolve_to_two_tables() {
+        let template = TableTemplate::parse("tx_{yyyymm}").unwrap();
+        let date =
+            |month: u32, day: u32| FixedOffset::east(0).ymd(2021, month, day).and_hms(0, 0, 0);
+
+        assert_eq!(template.resolve(&date(1, 31)), "tx_202101");
+        assert_eq!(template.resolve(&date(2, 1)), "tx_202102");
+        assert_eq!(template.resolve(&date(2, 28)), "tx_202102");
+        assert!(template.has_macros());
+        assert!(TableTemplate::parse("tx_{week}").is_err());
+        assert!(TableTemplate::parse("tx_{yyyymm").is_err());
+    }
+}
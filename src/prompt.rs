@@ -0,0 +1,82 @@
+use std::io::{self, BufRead, Write};
+
+/// asks for confirmation before a destructive operation. The operation is confirmed without
+/// asking if `assume_yes`; otherwise, it is declined without asking unless stdin is a terminal,
+/// so that unattended runs never block, nor proceed by accident.
+pub fn confirm(prompt: &str, assume_yes: bool) -> io::Result<bool> {
+    ask(
+        prompt,
+        assume_yes,
+        atty::is(atty::Stream::Stdin),
+        io::stdin().lock(),
+        io::stdout(),
+    )
+}
+
+/// asks for confirmation on the output, reading the answer from the input
+fn ask<R: BufRead, W: Write>(
+    prompt: &str,
+    assume_yes: bool,
+    interactive: bool,
+    mut input: R,
+    mut output: W,
+) -> io::Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+    if !interactive {
+        return Ok(false);
+    }
+
+    write!(output, "{} [y/N] ", prompt)?;
+    output.flush()?;
+
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_interactive_runs_are_declined_without_asking() {
+        let mut output = Vec::new();
+        assert!(!ask(
+            "Truncate transactions?",
+            false,
+            false,
+            &b"y\n"[..],
+            &mut output
+        )
+        .unwrap());
+        assert!(output.is_empty());
+
+        assert!(ask("Truncate transactions?", true, false, &b""[..], &mut output).unwrap());
+    }
+
+    #[test]
+    fn interactive_runs_take_the_answer() {
+        let mut output = Vec::new();
+        assert!(ask(
+            "Truncate transactions?",
+            false,
+            true,
+            &b"yes\n"[..],
+            &mut output
+        )
+        .unwrap());
+        assert_eq!(output, b"Truncate transactions? [y/N] ");
+
+        assert!(!ask(
+            "Truncate transactions?",
+            false,
+            true,
+            &b"\n"[..],
+            Vec::new()
+        )
+        .unwrap());
+    }
+}
@@ -0,0 +1,513 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::db::InsertOutcome;
+use crate::domain::CsvRecord;
+
+/// The outcome of an import; the single source of truth for what an import did
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportReport {
+    /// the number of csv files (or archive entries) read
+    pub files: usize,
+    /// rows successfully parsed into records
+    pub rows_read: usize,
+    /// rows that could not be parsed
+    pub rows_rejected: usize,
+    /// rows with an amount or balance that had more fractional digits than are kept
+    pub rows_precision_lost: usize,
+    /// rows with a blank or unparseable amount, imported as zero
+    pub rows_amount_zeroed: usize,
+    /// rows inserted into the table
+    pub inserted: usize,
+    /// existing rows updated in the table
+    pub updated: usize,
+    /// rows that already existed in the table
+    pub conflicted: usize,
+    /// rows that could not be inserted
+    pub failed: usize,
+    /// rows read, but not imported because of a filter (i.e. when resuming)
+    pub skipped_by_filter: usize,
+    /// rows read, but not imported because the same row was already read from another file
+    pub duplicates: usize,
+    /// accounts whose stored totals did not match the rows read, when verifying totals
+    pub totals_mismatched: usize,
+    /// rows that could not be inserted into the mirror, if any
+    #[serde(default)]
+    pub mirror_failed: usize,
+    /// rows whose stated balance differed from the balance recomputed from the anchor row
+    #[serde(default)]
+    pub rows_balance_diverged: usize,
+    /// whether the import stopped early because `--max-runtime` was exceeded
+    #[serde(default)]
+    pub timed_out: bool,
+    #[serde(
+        serialize_with = "serialize_seconds",
+        deserialize_with = "deserialize_seconds"
+    )]
+    pub duration: Duration,
+    pub accounts: BTreeMap<String, AccountReport>,
+    /// the outcome of loading the cleaned rows into the output table, if any
+    pub output: Option<Box<ImportReport>>,
+}
+
+/// The counts of an import for a single account
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountReport {
+    pub rows_read: usize,
+    pub inserted: usize,
+    pub updated: usize,
+    pub conflicted: usize,
+    pub failed: usize,
+    pub skipped_by_filter: usize,
+    pub duplicates: usize,
+}
+
+impl ImportReport {
+    pub fn record_read(&mut self, account: &str) {
+        self.rows_read += 1;
+        self.account(account).rows_read += 1;
+    }
+
+    pub fn record_skipped(&mut self, account: &str) {
+        self.skipped_by_filter += 1;
+        self.account(account).skipped_by_filter += 1;
+    }
+
+    pub fn record_duplicate(&mut self, account: &str) {
+        self.duplicates += 1;
+        self.account(account).duplicates += 1;
+    }
+
+    pub fn record_insert(&mut self, account: &str, outcome: InsertOutcome) {
+        match outcome {
+            InsertOutcome::Inserted => {
+                self.inserted += 1;
+                self.account(account).inserted += 1;
+            }
+            InsertOutcome::Updated => {
+                self.updated += 1;
+                self.account(account).updated += 1;
+            }
+            InsertOutcome::Conflicted => {
+                self.conflicted += 1;
+                self.account(account).conflicted += 1;
+            }
+            InsertOutcome::Failed => {
+                self.failed += 1;
+                self.account(account).failed += 1;
+            }
+        }
+    }
+
+    /// adds the counts of another report to this one; durations are summed
+    pub fn merge(&mut self, other: ImportReport) {
+        self.files += other.files;
+        self.rows_read += other.rows_read;
+        self.rows_rejected += other.rows_rejected;
+        self.rows_precision_lost += other.rows_precision_lost;
+        self.rows_amount_zeroed += other.rows_amount_zeroed;
+        self.inserted += other.inserted;
+        self.updated += other.updated;
+        self.conflicted += other.conflicted;
+        self.failed += other.failed;
+        self.skipped_by_filter += other.skipped_by_filter;
+        self.duplicates += other.duplicates;
+        self.totals_mismatched += other.totals_mismatched;
+        self.mirror_failed += other.mirror_failed;
+        self.rows_balance_diverged += other.rows_balance_diverged;
+        self.timed_out |= other.timed_out;
+        self.duration += other.duration;
+
+        if let Some(output) = other.output {
+            self.output
+                .get_or_insert_with(Default::default)
+                .merge(*output);
+        }
+
+        for (account, counts) in other.accounts {
+            let entry = self.account(&account);
+            entry.rows_read += counts.rows_read;
+            entry.inserted += counts.inserted;
+            entry.updated += counts.updated;
+            entry.conflicted += counts.conflicted;
+            entry.failed += counts.failed;
+            entry.skipped_by_filter += counts.skipped_by_filter;
+            entry.duplicates += counts.duplicates;
+        }
+    }
+
+    /// writes the report to the file as JSON
+    pub fn write_json(&self, path: &Path) -> crate::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    fn account(&mut self, account: &str) -> &mut AccountReport {
+        self.accounts.entry(account.to_string()).or_default()
+    }
+}
+
+/// A row dropped as a duplicate, as listed by `--dedup-report`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DroppedDuplicate {
+    /// the file (or archive entry) the row was read from
+    pub source: String,
+    /// the key that collided: `account/id`, or `account/amount/vendor` for near-duplicates
+    pub key: String,
+    /// the file the kept row was read from, or the `account/id` of the near-duplicate kept
+    pub kept: String,
+    pub account: String,
+    pub id: u64,
+    pub date: String,
+    pub amount: String,
+    pub vendor: String,
+}
+
+impl DroppedDuplicate {
+    pub fn new(source: &Path, key: String, kept: String, row: &CsvRecord) -> Self {
+        DroppedDuplicate {
+            source: source.display().to_string(),
+            key,
+            kept,
+            account: row.account.clone(),
+            id: row.id,
+            date: row.date.to_rfc3339(),
+            amount: row.amount.to_string(),
+            vendor: row.vendor.clone(),
+        }
+    }
+}
+
+/// writes the dropped duplicates to the file as csv, one row each, with a header row
+pub fn write_dedup_report(path: &Path, dropped: &[DroppedDuplicate]) -> crate::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    for row in dropped {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A key shared by several rows of the table, found by `--check-duplicates`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateKey {
+    /// the values of the key columns, as text
+    pub values: Vec<String>,
+    pub rows: i64,
+}
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({} rows)", self.values.join("/"), self.rows)
+    }
+}
+
+/// writes the duplicate keys to the file as csv, one row each, with a header row of the key
+/// columns and `rows`
+pub fn write_duplicate_keys(
+    path: &Path,
+    key_columns: &[String],
+    duplicates: &[DuplicateKey],
+) -> crate::Result<()> {
+    let mut writer = csv::Writer::from_path(path)?;
+    writer.write_record(key_columns.iter().map(|c| c.as_str()).chain(Some("rows")))?;
+    for duplicate in duplicates {
+        let rows = duplicate.rows.to_string();
+        writer.write_record(
+            duplicate
+                .values
+                .iter()
+                .map(|v| v.as_str())
+                .chain(Some(rows.as_str())),
+        )?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The rows of a source that are new to the table, and those already stored, by (account, id)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NewRowsPreview {
+    pub new: usize,
+    pub existing: usize,
+    /// the (new, existing) counts of each account
+    pub accounts: BTreeMap<String, (usize, usize)>,
+}
+
+impl NewRowsPreview {
+    pub fn record(&mut self, account: &str, exists: bool) {
+        let counts = self.accounts.entry(account.to_string()).or_default();
+        if exists {
+            self.existing += 1;
+            counts.1 += 1;
+        } else {
+            self.new += 1;
+            counts.0 += 1;
+        }
+    }
+}
+
+impl fmt::Display for NewRowsPreview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "New rows: {}, existing: {}", self.new, self.existing)?;
+        for (account, (new, existing)) in &self.accounts {
+            writeln!(f, "  {}: new: {}, existing: {}", account, new, existing)?;
+        }
+        Ok(())
+    }
+}
+
+/// The throughput of parsing a source repeatedly, without a database
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseBenchmark {
+    /// the number of times the source was parsed
+    pub repeat: usize,
+    /// the rows parsed, over every repetition
+    pub rows: usize,
+    /// the bytes read, over every repetition
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+impl ParseBenchmark {
+    pub fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.duration.as_secs_f64()
+    }
+
+    pub fn megabytes_per_second(&self) -> f64 {
+        self.bytes as f64 / 1_000_000.0 / self.duration.as_secs_f64()
+    }
+}
+
+impl fmt::Display for ParseBenchmark {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Parsed {} row(s) ({} bytes) {} time(s) in {:.2}s.",
+            self.rows / self.repeat.max(1),
+            self.bytes / self.repeat.max(1) as u64,
+            self.repeat,
+            self.duration.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Throughput: {:.0} rows/sec, {:.2} MB/sec",
+            self.rows_per_second(),
+            self.megabytes_per_second()
+        )
+    }
+}
+
+/// durations are serialized as fractional seconds
+fn serialize_seconds<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(d.as_secs_f64())
+}
+
+fn deserialize_seconds<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+    let seconds = f64::deserialize(d)?;
+    if seconds.is_finite() && seconds >= 0.0 {
+        Ok(Duration::from_secs_f64(seconds))
+    } else {
+        Err(serde::de::Error::custom(
+            "duration must be a non-negative number of seconds",
+        ))
+    }
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Imported {} file(s) in {:.2}s.",
+            self.files,
+            self.duration.as_secs_f64()
+        )?;
+        writeln!(
+            f,
+            "Rows read: {}, rejected: {}, inserted: {}, updated: {}, conflicted: {}, failed: {}, skipped: {}, duplicates: {}",
+            self.rows_read,
+            self.rows_rejected,
+            self.inserted,
+            self.updated,
+            self.conflicted,
+            self.failed,
+            self.skipped_by_filter,
+            self.duplicates
+        )?;
+        if self.totals_mismatched > 0 {
+            writeln!(
+                f,
+                "Accounts with mismatched totals: {}",
+                self.totals_mismatched
+            )?;
+        }
+        if self.mirror_failed > 0 {
+            writeln!(f, "Rows not mirrored: {}", self.mirror_failed)?;
+        }
+        if self.timed_out {
+            writeln!(
+                f,
+                "Stopped early: the maximum runtime was exceeded; the counts are partial."
+            )?;
+        }
+        if self.rows_balance_diverged > 0 {
+            writeln!(
+                f,
+                "Rows with balances diverging from the anchor: {}",
+                self.rows_balance_diverged
+            )?;
+        }
+        if self.rows_precision_lost > 0 {
+            writeln!(
+                f,
+                "Rows with amounts that lost precision: {}",
+                self.rows_precision_lost
+            )?;
+        }
+        if self.rows_amount_zeroed > 0 {
+            writeln!(
+                f,
+                "Rows with amounts imported as zero: {}",
+                self.rows_amount_zeroed
+            )?;
+        }
+
+        for (account, counts) in &self.accounts {
+            writeln!(
+                f,
+                "  {}: read: {}, inserted: {}, updated: {}, conflicted: {}, failed: {}, skipped: {}, duplicates: {}",
+                account,
+                counts.rows_read,
+                counts.inserted,
+                counts.updated,
+                counts.conflicted,
+                counts.failed,
+                counts.skipped_by_filter,
+                counts.duplicates
+            )?;
+        }
+
+        if let Some(output) = &self.output {
+            writeln!(
+                f,
+                "Output table: inserted: {}, updated: {}, conflicted: {}, failed: {}, skipped: {}, duplicates: {}",
+                output.inserted,
+                output.updated,
+                output.conflicted,
+                output.failed,
+                output.skipped_by_filter,
+                output.duplicates
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_kept_per_account_and_merged() {
+        let mut report = ImportReport::default();
+        report.record_read("A");
+        report.record_read("A");
+        report.record_read("B");
+        report.record_insert("A", InsertOutcome::Inserted);
+        report.record_insert("A", InsertOutcome::Conflicted);
+        report.record_insert("B", InsertOutcome::Failed);
+
+        let mut other = ImportReport {
+            files: 1,
+            ..ImportReport::default()
+        };
+        other.record_read("B");
+        other.record_skipped("B");
+        report.merge(other);
+
+        assert_eq!(report.files, 1);
+        assert_eq!(report.rows_read, 4);
+        assert_eq!(
+            (report.inserted, report.conflicted, report.failed),
+            (1, 1, 1)
+        );
+        assert_eq!(report.skipped_by_filter, 1);
+        assert_eq!(
+            report.accounts["A"],
+            AccountReport {
+                rows_read: 2,
+                inserted: 1,
+                conflicted: 1,
+                ..AccountReport::default()
+            }
+        );
+        assert_eq!(
+            report.accounts["B"],
+            AccountReport {
+                rows_read: 2,
+                failed: 1,
+                skipped_by_filter: 1,
+                ..AccountReport::default()
+            }
+        );
+    }
+
+    #[test]
+    fn the_written_json_reads_back_into_the_report() {
+        let mut report = ImportReport {
+            files: 2,
+            rows_rejected: 1,
+            duration: Duration::from_millis(1500),
+            ..ImportReport::default()
+        };
+        report.record_read("A");
+        report.record_read("B");
+        report.record_insert("A", InsertOutcome::Inserted);
+        report.record_insert("B", InsertOutcome::Updated);
+        report.output = Some(Box::new(report.clone()));
+
+        let path =
+            std::env::temp_dir().join(format!("csv-importer-report-{}.json", std::process::id()));
+        report.write_json(&path).unwrap();
+        let read: ImportReport = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(read, report);
+        assert_eq!(read.accounts["B"].updated, 1);
+        assert_eq!(read.duration.as_millis(), 1500);
+    }
+
+    #[test]
+    fn duplicate_keys_are_listed_and_written() {
+        let duplicates = vec![
+            DuplicateKey {
+                values: vec!["BOFA".to_string(), "1".to_string()],
+                rows: 2,
+            },
+            DuplicateKey {
+                values: vec!["CHASE".to_string(), "7".to_string()],
+                rows: 3,
+            },
+        ];
+        assert_eq!(duplicates[0].to_string(), "BOFA/1 (2 rows)");
+
+        let dir =
+            std::env::temp_dir().join(format!("csv-importer-duplicates-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("duplicates.csv");
+        let key_columns = vec!["account".to_string(), "tx_id".to_string()];
+        write_duplicate_keys(&path, &key_columns, &duplicates).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "account,tx_id,rows\nBOFA,1,2\nCHASE,7,3\n"
+        );
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::FixedOffset;
+use chrono_tz::Tz;
+
+use crate::currency::{Currency, CurrencyFormat};
+use crate::domain::{self, Percentage, DEFAULT_DATE_FORMAT};
+use crate::normalize::TypeSigns;
+
+/// Currency inputs, and their parsed values; None if the input must be rejected
+const CURRENCY_CASES: [(&str, Option<&str>); 24] = [
+    ("12.34", Some("12.34")),
+    ("$12.34", Some("12.34")),
+    ("(12.34)", Some("-12.34")),
+    ("(0.50)", Some("-0.50")),
+    ("-12.34", Some("-12.34")),
+    ("12.34-", Some("-12.34")),
+    ("\u{2212}12.34", Some("-12.34")),
+    ("$-12.34", Some("-12.34")),
+    ("-0.50", Some("-0.50")),
+    ("1,234.56", Some("1234.56")),
+    ("12,345,678.90", Some("12345678.90")),
+    ("1.5", Some("1.50")),
+    ("1.05", Some("1.05")),
+    ("1.50", Some("1.50")),
+    ("1.5000", Some("1.50")),
+    ("12.005", Some("12.00")),
+    ("12.015", Some("12.02")),
+    ("$ -", Some("0.00")),
+    ("$-", Some("0.00")),
+    ("-", Some("0.00")),
+    ("0", Some("0.00")),
+    ("0.00", Some("0.00")),
+    ("12", None),
+    ("abc", None),
+];
+
+/// Currency inputs in the European format, with `--currency-format european`, and their parsed
+/// values
+const EUROPEAN_CURRENCY_CASES: [(&str, Option<&str>); 4] = [
+    ("1.234,56", Some("1234.56")),
+    ("12.345.678,90", Some("12345678.90")),
+    ("(1.234,56)", Some("-1234.56")),
+    ("0,5", Some("0.50")),
+];
+
+/// Percentage inputs, and their parsed ratios; None if the input must be rejected
+const PERCENTAGE_CASES: [(&str, Option<&str>); 5] = [
+    ("1.5%", Some("0.015")),
+    ("1,5%", Some("0.015")),
+    ("0.015", Some("0.015")),
+    ("-2%", Some("-0.02")),
+    ("abc", None),
+];
+
+/// Date inputs in the default format, and their parsed dates; None if the input must be rejected
+const DATE_CASES: [(&str, Option<&str>); 4] = [
+    ("01/31/2021", Some("2021-01-31")),
+    ("2/3/2021", Some("2021-02-03")),
+    ("31/01/2021", None),
+    ("2021-01-31", None),
+];
+
+/// Date inputs and formats, parsed with a `+02:00` offset for dates without one, and their
+/// parsed instants; a date alone is midnight in that offset, not in UTC
+const ZONED_DATE_CASES: [(&str, &str, Option<&str>); 4] = [
+    ("01/31/2021", "%m/%d/%Y", Some("2021-01-31T00:00:00+02:00")),
+    (
+        "01/31/2021 23:30",
+        "%m/%d/%Y %H:%M",
+        Some("2021-01-31T23:30:00+02:00"),
+    ),
+    (
+        "01/31/2021 23:30 -0500",
+        "%m/%d/%Y %H:%M %z",
+        Some("2021-01-31T23:30:00-05:00"),
+    ),
+    ("01/31/2021 25:00", "%m/%d/%Y %H:%M", None),
+];
+
+/// Date inputs in the default format, parsed in a time zone like the dates of an account with
+/// `--account-timezone`, and their parsed instants; the offset follows daylight saving time
+const ACCOUNT_ZONE_CASES: [(&str, Tz, Option<&str>); 3] = [
+    (
+        "01/31/2021",
+        Tz::America__New_York,
+        Some("2021-01-31T00:00:00-05:00"),
+    ),
+    (
+        "07/31/2021",
+        Tz::America__New_York,
+        Some("2021-07-31T00:00:00-04:00"),
+    ),
+    (
+        "01/31/2021",
+        Tz::Europe__Berlin,
+        Some("2021-01-31T00:00:00+01:00"),
+    ),
+];
+
+/// Amounts signed by their transaction type, as with `--sign-by-type` mapping `Debit` to debits
+/// and `Credit` to credits: the type, the amount as read, whether negative amounts are kept as
+/// stated, and the signed amount
+const TYPE_SIGN_CASES: [(&str, &str, bool, Option<&str>); 6] = [
+    ("Debit", "12.34", false, Some("-12.34")),
+    ("Debit", "-12.34", false, Some("-12.34")),
+    ("Credit", "12.34", false, Some("12.34")),
+    ("Credit", "-12.34", false, Some("12.34")),
+    ("Credit", "-12.34", true, Some("-12.34")),
+    ("Transfer", "12.34", false, Some("12.34")),
+];
+
+/// The outcome of a single case
+struct CaseOutcome {
+    parser: &'static str,
+    input: &'static str,
+    expected: Option<&'static str>,
+    actual: Option<String>,
+}
+
+impl CaseOutcome {
+    fn passed(&self) -> bool {
+        self.expected == self.actual.as_deref()
+    }
+}
+
+/// The outcomes of the built-in parser cases
+pub struct SelfTestReport {
+    outcomes: Vec<CaseOutcome>,
+}
+
+impl SelfTestReport {
+    pub fn failed(&self) -> usize {
+        self.outcomes.iter().filter(|o| !o.passed()).count()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for outcome in &self.outcomes {
+            writeln!(
+                f,
+                "{} {} {:?}: expected {}, got {}",
+                if outcome.passed() { "PASS" } else { "FAIL" },
+                outcome.parser,
+                outcome.input,
+                outcome.expected.unwrap_or("rejection"),
+                outcome.actual.as_deref().unwrap_or("rejection")
+            )?;
+        }
+        writeln!(
+            f,
+            "{} of {} cases passed.",
+            self.outcomes.len() - self.failed(),
+            self.outcomes.len()
+        )
+    }
+}
+
+/// runs the currency, percentage, and date parsers, and the signing of amounts by type, against
+/// the built-in cases, which double as a reference of the accepted formats
+pub fn run() -> SelfTestReport {
+    let currency = CURRENCY_CASES.iter().map(|(input, expected)| CaseOutcome {
+        parser: "currency",
+        input,
+        expected: *expected,
+        actual: Currency::from_str(input).ok().map(|c| c.to_string()),
+    });
+    let european_currency = EUROPEAN_CURRENCY_CASES
+        .iter()
+        .map(|(input, expected)| CaseOutcome {
+            parser: "european currency",
+            input,
+            expected: *expected,
+            actual: Currency::parse_with(input, CurrencyFormat::European)
+                .ok()
+                .map(|c| c.to_string()),
+        });
+    let percentage = PERCENTAGE_CASES
+        .iter()
+        .map(|(input, expected)| CaseOutcome {
+            parser: "percentage",
+            input,
+            expected: *expected,
+            actual: Percentage::from_str(input).ok().map(|p| p.to_string()),
+        });
+    let date = DATE_CASES.iter().map(|(input, expected)| CaseOutcome {
+        parser: "date",
+        input,
+        expected: *expected,
+        actual: domain::parse_date(input, DEFAULT_DATE_FORMAT, &FixedOffset::east(0))
+            .ok()
+            .map(|d| d.format("%Y-%m-%d").to_string()),
+    });
+    let zoned_date = ZONED_DATE_CASES
+        .iter()
+        .map(|(input, format, expected)| CaseOutcome {
+            parser: "zoned date",
+            input,
+            expected: *expected,
+            actual: domain::parse_date(input, format, &FixedOffset::east(2 * 3600))
+                .ok()
+                .map(|d| d.to_rfc3339()),
+        });
+
+    let account_zone = ACCOUNT_ZONE_CASES
+        .iter()
+        .map(|(input, zone, expected)| CaseOutcome {
+            parser: "account zone date",
+            input,
+            expected: *expected,
+            actual: domain::parse_date(input, DEFAULT_DATE_FORMAT, zone)
+                .ok()
+                .map(|d| d.to_rfc3339()),
+        });
+
+    let directions = [("Debit", "debit"), ("Credit", "credit")]
+        .iter()
+        .map(|(t, d)| (t.to_string(), d.to_string()))
+        .collect::<HashMap<_, _>>();
+    let type_sign =
+        TYPE_SIGN_CASES
+            .iter()
+            .map(|(transaction_type, input, keep_signed, expected)| {
+                let signs = TypeSigns::from_map(directions.clone(), *keep_signed);
+                CaseOutcome {
+                    parser: transaction_type,
+                    input,
+                    expected: *expected,
+                    actual: signs.ok().and_then(|signs| {
+                        Currency::from_str(input)
+                            .ok()
+                            .map(|amount| signs.apply(&amount, transaction_type).to_string())
+                    }),
+                }
+            });
+
+    SelfTestReport {
+        outcomes: currency
+            .chain(european_currency)
+            .chain(percentage)
+            .chain(date)
+            .chain(zoned_date)
+            .chain(account_zone)
+            .chain(type_sign)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_reference_battery_passes() {
+        let report = run();
+        assert!(report.passed(), "{}", report);
+        assert_eq!(
+            report.outcomes.len(),
+            CURRENCY_CASES.len()
+                + EUROPEAN_CURRENCY_CASES.len()
+                + PERCENTAGE_CASES.len()
+                + DATE_CASES.len()
+                + ZONED_DATE_CASES.len()
+                + ACCOUNT_ZONE_CASES.len()
+                + TYPE_SIGN_CASES.len()
+        );
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Records the files of a directory import that completed successfully, one file name per line,
+/// so that an interrupted import can be rerun without reprocessing them
+pub struct StateFile {
+    path: PathBuf,
+    completed: HashSet<String>,
+}
+
+impl StateFile {
+    /// reads the completed files from the state file; a missing state file has none
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let completed = match std::fs::read_to_string(path) {
+            Ok(contents) => contents
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashSet::new(),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            completed,
+        })
+    }
+
+    pub fn is_completed(&self, file: &Path) -> bool {
+        self.completed.contains(&Self::key(file))
+    }
+
+    /// records the file as completed, appending it to the state file immediately
+    pub fn record_completed(&mut self, file: &Path) -> io::Result<()> {
+        let key = Self::key(file);
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(f, "{}", key)?;
+        f.sync_all()?;
+
+        self.completed.insert(key);
+        Ok(())
+    }
+
+    /// files are identified by their file name, so that reruns may name the directory differently
+    fn key(file: &Path) -> String {
+        file.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completed_files_survive_a_rerun() {
+        let dir = std::env::temp_dir().join(format!("csv-importer-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("import.state");
+
+        let mut state = StateFile::load(&path).unwrap();
+        assert!(!state.is_completed(Path::new("in/january.csv")));
+        state.record_completed(Path::new("in/january.csv")).unwrap();
+
+        // files are known by name, wherever the directory is
+        let state = StateFile::load(&path).unwrap();
+        assert!(state.is_completed(Path::new("renamed/january.csv")));
+        assert!(!state.is_completed(Path::new("in/february.csv")));
+    }
+}
@@ -0,0 +1,227 @@
+use std::time::Instant;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::config::DatabaseConfig;
+use crate::db::{self, PgPool, StoredFields, TableSchema};
+use crate::domain::{CsvRecord, PrimaryDate};
+use crate::events::{self, ImportEvent, ImportObserver};
+#[cfg(feature = "memory")]
+use crate::memory::MemoryStore;
+use crate::report::ImportReport;
+
+/// Where imported transactions are stored
+pub enum TransactionStore {
+    Postgres(PgPool),
+    /// an in-memory store, for trying the importer without a database
+    #[cfg(feature = "memory")]
+    Memory(MemoryStore),
+}
+
+impl TransactionStore {
+    /// whether the table exists; tables of the in-memory store are created on first insert
+    pub async fn table_exists(&self, table_name: &str) -> Result<bool, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::table_exists(table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(_) => Ok(true),
+        }
+    }
+
+    pub async fn select_table_schema(&self, table_name: &str) -> Result<TableSchema, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_table_schema(table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(_) => Ok(TableSchema::known()),
+        }
+    }
+
+    pub async fn select_max_tx_for_account(
+        &self,
+        account: &str,
+        table_name: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_max_tx_for_account(account, table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.max_tx_for_account(table_name, account)),
+        }
+    }
+
+    /// the latest primary date of the account's stored rows, if any
+    pub async fn select_max_date_for_account(
+        &self,
+        account: &str,
+        primary: PrimaryDate,
+        table_name: &str,
+    ) -> Result<Option<NaiveDate>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                db::select_max_date_for_account(account, primary, table_name, pool).await
+            }
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.max_date_for_account(table_name, account, primary)),
+        }
+    }
+
+    /// when rows were last imported into the table, if ever
+    pub async fn select_last_import(
+        &self,
+        table_name: &str,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_last_import(table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.last_import(table_name)),
+        }
+    }
+
+    /// the given transactions of the account that are already stored
+    pub async fn select_existing_tx_ids(
+        &self,
+        account: &str,
+        ids: &[i64],
+        table_name: &str,
+    ) -> Result<Vec<i64>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                db::select_existing_tx_ids(account, ids, table_name, pool).await
+            }
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.existing_tx_ids(table_name, account, ids)),
+        }
+    }
+
+    /// the amount, vendor, and type of the stored rows of the given (account, tx_id) keys
+    pub async fn select_stored_fields(
+        &self,
+        keys: &[(String, i64)],
+        table_name: &str,
+    ) -> Result<Vec<StoredFields>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_stored_fields(keys, table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.stored_fields(table_name, keys)),
+        }
+    }
+
+    /// the given (account, tx_id) keys that are already stored
+    pub async fn select_existing_keys(
+        &self,
+        keys: &[(String, i64)],
+        table_name: &str,
+    ) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_existing_keys(keys, table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.existing_keys(table_name, keys)),
+        }
+    }
+
+    /// the ids of the (category, subcategory) pairs in the categories table, inserting the
+    /// pairs that are missing
+    pub async fn upsert_categories(
+        &self,
+        pairs: &[(String, String)],
+        categories_table: &str,
+    ) -> Result<Vec<(String, String, i32)>, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::upsert_categories(pairs, categories_table, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.upsert_categories(categories_table, pairs)),
+        }
+    }
+
+    /// runs the statements of a `--before-sql` or `--after-sql` hook; the in-memory store has no
+    /// sql, and ignores hooks
+    pub async fn execute_hook(&self, sql: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::execute_hook(sql, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => {
+                memory.execute_hook(sql);
+                Ok(())
+            }
+        }
+    }
+
+    /// (re)creates the empty staging table of a `--load-then-swap` load, like the target table
+    pub async fn create_staging(&self, target: &str, staging: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::create_staging(target, staging, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => {
+                memory.create_staging(staging);
+                Ok(())
+            }
+        }
+    }
+
+    /// atomically replaces the target table with the loaded staging table
+    pub async fn swap_tables(&self, target: &str, staging: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::swap_tables(target, staging, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => {
+                memory.swap_tables(target, staging);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn drop_table(&self, table_name: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::drop_table(table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => {
+                memory.drop_table(table_name);
+                Ok(())
+            }
+        }
+    }
+
+    /// the number of stored rows, and the sum of their amounts in hundredths, of the given
+    /// transactions of the account
+    pub async fn select_totals(
+        &self,
+        account: &str,
+        ids: &[i64],
+        table_name: &str,
+    ) -> Result<(i64, i64), sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => db::select_totals(account, ids, table_name, pool).await,
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => Ok(memory.totals(table_name, account, ids)),
+        }
+    }
+
+    pub async fn import(
+        &self,
+        records: &[&CsvRecord],
+        table_name: &str,
+        c: &DatabaseConfig,
+        schema: Option<&TableSchema>,
+        observers: &[Box<dyn ImportObserver>],
+        deadline: Option<Instant>,
+    ) -> Result<ImportReport, sqlx::Error> {
+        match self {
+            Self::Postgres(pool) => {
+                db::import_refs(records, table_name, c, schema, observers, deadline, pool).await
+            }
+            #[cfg(feature = "memory")]
+            Self::Memory(memory) => {
+                // the rows are inserted at once, so the deadline only applies between files
+                let report = memory.insert(table_name, records, c.get_conflict_policy());
+                events::emit(
+                    observers,
+                    &ImportEvent::ChunkCommitted {
+                        table_name,
+                        rows: records.len(),
+                        last: records.last().copied(),
+                    },
+                );
+                Ok(report)
+            }
+        }
+    }
+}
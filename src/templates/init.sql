@@ -1,6 +1,6 @@
 CREATE TABLE IF NOT EXISTS {table_name} (
   account TEXT NOT NULL,
-  tx_id INTEGER NOT NULL,
+  tx_id BIGINT NOT NULL,
   tx_date DATE NOT NULL,
   amount NUMERIC(13,4) NOT NULL,
   balance NUMERIC(13,4) NOT NULL,
@@ -10,16 +10,22 @@ CREATE TABLE IF NOT EXISTS {table_name} (
   category TEXT NULL,
   subcategory TEXT NULL,
   notes TEXT NULL,
-  PRIMARY KEY (account, tx_id)
+  PRIMARY KEY ({key_columns})
 );;;
 
-CREATE INDEX IF NOT EXISTS idx_import_tx_date ON {table_name} (tx_date);;;
-CREATE INDEX IF NOT EXISTS idx_import_tx_vendor ON {table_name} (vendor);;;
-CREATE INDEX IF NOT EXISTS idx_import_tx_category ON {table_name} (category);;;
-CREATE INDEX IF NOT EXISTS idx_import_tx_type ON {table_name} (transaction_type);;;
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_date ON {table_name} (tx_date);;;
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_vendor ON {table_name} (vendor);;;
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_category ON {table_name} (category);;;
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_type ON {table_name} (transaction_type);;;
 
 ALTER TABLE IF EXISTS {table_name}
     ADD COLUMN IF NOT EXISTS is_income BOOL NOT NULL DEFAULT false,
     ADD COLUMN IF NOT EXISTS is_fixed BOOL NOT NULL DEFAULT false,
-    ADD COLUMN IF NOT EXISTS is_spend BOOL NOT NULL DEFAULT false
+    ADD COLUMN IF NOT EXISTS is_spend BOOL NOT NULL DEFAULT false,
+    ADD COLUMN IF NOT EXISTS percentage NUMERIC(9,6) NULL,
+    ADD COLUMN IF NOT EXISTS posted_date DATE NULL,
+    ADD COLUMN IF NOT EXISTS currency_code TEXT NULL,
+    ADD COLUMN IF NOT EXISTS source_file TEXT NULL,
+    ADD COLUMN IF NOT EXISTS source_line BIGINT NULL,
+    ADD COLUMN IF NOT EXISTS imported_at TIMESTAMPTZ NOT NULL DEFAULT now()
 ;
\ No newline at end of file
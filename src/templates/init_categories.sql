@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS {categories_table} (
+  category_id SERIAL PRIMARY KEY,
+  category TEXT NOT NULL,
+  subcategory TEXT NOT NULL DEFAULT '',
+  UNIQUE (category, subcategory)
+);;;
+
+ALTER TABLE IF EXISTS {table_name}
+    ADD COLUMN IF NOT EXISTS category_id INTEGER NULL REFERENCES {categories_table} (category_id)
+;
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS {table_name} (
+  account TEXT NOT NULL,
+  tx_id BIGINT NOT NULL,
+  tx_date DATE NOT NULL,
+  record JSONB NOT NULL,
+  imported_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  PRIMARY KEY ({key_columns})
+);;;
+
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_date ON {table_name} (tx_date);;;
+CREATE INDEX IF NOT EXISTS idx_{index_prefix}_tx_record ON {table_name} USING GIN (record);;;
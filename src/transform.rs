@@ -0,0 +1,154 @@
+use std::str::FromStr;
+
+use evalexpr::{build_operator_tree, ContextWithMutableVariables, HashMapContext, Node, Value};
+use log::warn;
+
+use crate::domain::CsvRecord;
+
+/// The fields a transform may set; these are also the text variables of an expression
+const FIELDS: [&str; 7] = [
+    "account",
+    "vendor",
+    "digits",
+    "transaction_type",
+    "category",
+    "subcategory",
+    "notes",
+];
+
+/// Sets a field of every row to the value of an expression over the fields of the row, such as
+/// `vendor=str::to_uppercase(vendor)`. Expressions are evaluated with
+/// [evalexpr](https://docs.rs/evalexpr), and are parsed along with the configuration.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    field: &'static str,
+    expression: String,
+    node: Node,
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    /// parses `FIELD=EXPRESSION`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (field, expression) = s
+            .split_once('=')
+            .ok_or_else(|| format!("{} is not FIELD=EXPRESSION", s))?;
+        let field = FIELDS
+            .iter()
+            .find(|f| **f == field.trim())
+            .ok_or_else(|| format!("{} is not a text field", field.trim()))?;
+        let node = build_operator_tree(expression.trim()).map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            field,
+            expression: expression.trim().to_string(),
+            node,
+        })
+    }
+}
+
+impl Transform {
+    /// sets the field of the record to the value of the expression. An expression that fails
+    /// to evaluate, or doesn't evaluate to text, leaves the field unchanged, with a warning.
+    pub fn apply(&self, record: &mut CsvRecord) {
+        let context = match context(record) {
+            Ok(context) => context,
+            Err(e) => {
+                warn!("Could not transform {}: {}", self.field, e);
+                return;
+            }
+        };
+
+        let value = match self.node.eval_with_context(&context) {
+            Ok(Value::String(value)) => Some(value),
+            Ok(Value::Empty) => None,
+            Ok(other) => {
+                warn!(
+                    "{} of row {}/{} is not text: {}",
+                    self.expression, record.account, record.id, other
+                );
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "Could not evaluate {} for row {}/{}: {}",
+                    self.expression, record.account, record.id, e
+                );
+                return;
+            }
+        };
+
+        // required fields become blank rather than absent
+        let value = value.filter(|v| !v.is_empty());
+        match self.field {
+            "account" => record.account = value.unwrap_or_default(),
+            "vendor" => record.vendor = value.unwrap_or_default(),
+            "transaction_type" => record.transaction_type = value.unwrap_or_default(),
+            "digits" => record.digits = value,
+            "category" => record.category = value,
+            "subcategory" => record.subcategory = value,
+            "notes" => record.notes = value,
+            _ => {}
+        }
+    }
+}
+
+/// the variables of an expression: the text fields (blank if absent), the amount as a number,
+/// and the flags
+fn context(record: &CsvRecord) -> evalexpr::EvalexprResult<HashMapContext> {
+    let text = [
+        Some(record.account.as_str()),
+        Some(record.vendor.as_str()),
+        record.digits.as_deref(),
+        Some(record.transaction_type.as_str()),
+        record.category.as_deref(),
+        record.subcategory.as_deref(),
+        record.notes.as_deref(),
+    ];
+
+    let mut context = HashMapContext::new();
+    for (field, value) in FIELDS.iter().zip(text.iter()) {
+        context.set_value(
+            field.to_string(),
+            Value::String(value.unwrap_or_default().to_string()),
+        )?;
+    }
+    context.set_value(
+        "amount".to_string(),
+        Value::Float(record.amount.to_minor_units() as f64 / 100.0),
+    )?;
+    context.set_value("income".to_string(), Value::Boolean(record.income))?;
+    context.set_value("fixed".to_string(), Value::Boolean(record.fixed))?;
+    context.set_value("spend".to_string(), Value::Boolean(record.spend))?;
+
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{FixedOffset, TimeZone};
+
+    fn record(vendor: &str) -> CsvRecord {
+        let date = FixedOffset::east(0).ymd(2021, 1, 31).and_hms(0, 0, 0);
+        CsvRecord::builder("BOFA", 1, date).vendor(vendor).build()
+    }
+
+    #[test]
+    fn expressions_uppercase_the_vendor() {
+        let transform = Transform::from_str("vendor = str::to_uppercase(vendor)").unwrap();
+        let mut row = record("Corner Grocer");
+        transform.apply(&mut row);
+        assert_eq!(row.vendor, "CORNER GROCER");
+        assert_eq!(row.account, "BOFA");
+    }
+
+    #[test]
+    fn invalid_transforms_fail_to_parse() {
+        assert!(Transform::from_str("vendor").is_err());
+        assert!(Transform::from_str("amount=1").is_err());
+        assert!(Transform::from_str("vendor=str::to_uppercase(").is_err());
+    }
+}